@@ -0,0 +1,303 @@
+//! Tune the evaluation parameters with Texel's tuning method.
+//!
+//! Usage: `tune <positions> [<output>] [<parameters>]`
+//!
+//! The positions file contains one position per line, a FEN followed by the result of the game,
+//! either as `1-0`, `0-1` and `1/2-1/2` or as the score of White, e.g. `0.5`.
+//! The result can be enclosed in brackets or quotes.
+//! The positions should be quiet, because they are evaluated without a search.
+//!
+//! Starting from the given parameters (or the built-in ones),
+//! every weight is changed in small steps as long as the evaluation predicts the results better.
+//! The tuned parameters are written to the output file after every pass over the weights,
+//! they can then be loaded with the `EvalParameters` option of the engine.
+//!
+//! See <https://www.chessprogramming.org/Texel%27s_Tuning_Method>.
+
+use std::{env, fs, process, thread};
+
+use pleco::{Board, Player};
+use stonefish_engine::stonefish::{evaluate, EvalParameters};
+
+/// The file the tuned parameters are written to, if no other file is given.
+const DEFAULT_OUTPUT: &str = "eval_parameters.txt";
+/// The scaling constants that are tried to map the evaluation to the expected result.
+const SCALING_RANGE: (f64, f64) = (0.0, 5.0);
+
+/// A position with the result of its game.
+struct TuningPosition {
+    /// The position to evaluate.
+    board: Board,
+    /// The result of the game from White's view, 1 for a win and 0.5 for a draw.
+    result: f64,
+}
+
+/// Parse the result of a game from White's view.
+fn parse_result(token: &str) -> Option<f64> {
+    match token.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';')) {
+        "1-0" | "1.0" => Some(1.0),
+        "1/2-1/2" | "0.5" => Some(0.5),
+        "0-1" | "0.0" => Some(0.0),
+        _ => None,
+    }
+}
+
+/// Parse a line with a FEN and the result of the game.
+///
+/// The move counters of the FEN are optional.
+fn parse_position(line: &str) -> Option<TuningPosition> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    let result = parse_result(tokens.last()?)?;
+
+    let mut fen_fields = tokens.get(..4)?.to_vec();
+    let counters = tokens.get(4..6).unwrap_or_default();
+
+    if counters.len() == 2 && counters.iter().all(|field| field.parse::<u32>().is_ok()) {
+        fen_fields.extend(counters);
+    } else {
+        fen_fields.extend(["0", "1"]);
+    }
+
+    let board = Board::from_fen(&fen_fields.join(" ")).ok()?;
+    Some(TuningPosition { board, result })
+}
+
+/// Load the positions of the file, skipping invalid lines.
+fn load_positions(path: &str) -> Result<Vec<TuningPosition>, String> {
+    let text =
+        fs::read_to_string(path).map_err(|error| format!("could not read '{path}': {error}"))?;
+    let lines = text.lines().filter(|line| !line.trim().is_empty());
+
+    let mut positions = Vec::new();
+    let mut invalid_lines = 0;
+
+    for line in lines {
+        match parse_position(line) {
+            Some(position) => positions.push(position),
+            None => invalid_lines += 1,
+        }
+    }
+
+    if invalid_lines > 0 {
+        eprintln!("Skipped {invalid_lines} invalid lines.");
+    }
+
+    if positions.is_empty() {
+        return Err(format!("'{path}' doesn't contain any positions"));
+    }
+
+    Ok(positions)
+}
+
+/// The evaluation of the position from White's view, in centipawns.
+fn white_evaluation(parameters: &EvalParameters, board: &Board) -> f64 {
+    let evaluation = evaluate(parameters, board) as f64;
+
+    match board.turn() {
+        Player::White => evaluation,
+        Player::Black => -evaluation,
+    }
+}
+
+/// The expected result for White, given the evaluation in centipawns.
+fn expected_result(scaling: f64, evaluation: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-scaling * evaluation / 400.0))
+}
+
+/// The evaluations of all positions, computed on all available threads.
+fn evaluations(parameters: &EvalParameters, positions: &[TuningPosition]) -> Vec<f64> {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = positions.len().div_ceil(threads);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|position| white_evaluation(parameters, &position.board))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// The mean squared error between the results and the expected results of the evaluations.
+fn mean_squared_error(positions: &[TuningPosition], evaluations: &[f64], scaling: f64) -> f64 {
+    let sum: f64 = positions
+        .iter()
+        .zip(evaluations)
+        .map(|(position, evaluation)| {
+            (position.result - expected_result(scaling, *evaluation)).powi(2)
+        })
+        .sum();
+
+    sum / positions.len() as f64
+}
+
+/// The scaling constant that maps the evaluations to the results with the smallest error.
+///
+/// The constant is fitted once, so that the tuning doesn't just scale all weights.
+fn optimal_scaling(positions: &[TuningPosition], evaluations: &[f64]) -> f64 {
+    let (mut low, mut high) = SCALING_RANGE;
+
+    // The error is convex in the scaling constant, so a ternary search finds its minimum
+    for _ in 0..100 {
+        let left = low + (high - low) / 3.0;
+        let right = high - (high - low) / 3.0;
+
+        if mean_squared_error(positions, evaluations, left)
+            < mean_squared_error(positions, evaluations, right)
+        {
+            high = right;
+        } else {
+            low = left;
+        }
+    }
+
+    (low + high) / 2.0
+}
+
+/// Change every weight by one step as long as the error decreases.
+///
+/// The parameters are written to the output file after every pass.
+fn tune(
+    parameters: &mut EvalParameters,
+    positions: &[TuningPosition],
+    scaling: f64,
+    output: &str,
+) -> Result<(), String> {
+    let error = |parameters: &EvalParameters| {
+        mean_squared_error(positions, &evaluations(parameters, positions), scaling)
+    };
+
+    let mut weights = parameters.weights();
+    let mut best_error = error(parameters);
+    println!("Initial error: {best_error:.8}");
+
+    for pass in 1.. {
+        let mut improved = false;
+
+        for index in 0..weights.len() {
+            for step in [1, -1] {
+                weights[index] += step;
+                parameters.set_weights(&weights);
+                let new_error = error(parameters);
+
+                if new_error < best_error {
+                    best_error = new_error;
+                    improved = true;
+                    break;
+                }
+
+                weights[index] -= step;
+            }
+        }
+
+        parameters.set_weights(&weights);
+        fs::write(output, parameters.to_string())
+            .map_err(|error| format!("could not write '{output}': {error}"))?;
+        println!("Pass {pass}: error {best_error:.8}, written to '{output}'");
+
+        if !improved {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Load the positions and parameters and tune them.
+fn run(args: &[String]) -> Result<(), String> {
+    let [positions_path, rest @ ..] = args else {
+        return Err("Usage: tune <positions> [<output>] [<parameters>]".to_string());
+    };
+    let output = rest.first().map_or(DEFAULT_OUTPUT, String::as_str);
+
+    let mut parameters = match rest.get(1) {
+        Some(path) => EvalParameters::from_file(path)
+            .map_err(|error| format!("invalid parameters '{path}': {error}"))?,
+        None => EvalParameters::default(),
+    };
+
+    let positions = load_positions(positions_path)?;
+    println!("Loaded {} positions.", positions.len());
+
+    let scaling = optimal_scaling(&positions, &evaluations(&parameters, &positions));
+    println!("Scaling constant: {scaling:.4}");
+
+    tune(&mut parameters, &positions, scaling, output)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if let Err(error) = run(&args) {
+        eprintln!("{error}");
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use stonefish_engine::stonefish::EvalParameters;
+
+    use super::{evaluations, optimal_scaling, parse_position, TuningPosition};
+
+    #[test]
+    fn should_parse_positions() {
+        let parameters = [
+            (
+                "result after the FEN",
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1 1/2-1/2",
+                Some(0.5),
+            ),
+            (
+                "FEN without move counters",
+                "4k3/8/8/8/8/8/4P3/4K3 w - - [1.0]",
+                Some(1.0),
+            ),
+            (
+                "quoted result with a comment",
+                "4k3/8/8/8/8/8/4p3/4K3 w - - c9 \"0-1\";",
+                Some(0.0),
+            ),
+            ("missing result", "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", None),
+            ("invalid FEN", "4k3/8/8 w - - 1-0", None),
+        ];
+
+        for (name, line, expected) in parameters {
+            assert_eq!(
+                parse_position(line).map(|position| position.result),
+                expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_fit_scaling_to_results() {
+        let positions: Vec<TuningPosition> = [
+            "4k3/8/8/8/8/8/3PPP2/4K3 w - - 0 1 1-0",
+            "4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1 1/2-1/2",
+            "4k3/3ppp2/8/8/8/8/8/4K3 w - - 0 1 0-1",
+        ]
+        .iter()
+        .map(|line| parse_position(line).unwrap())
+        .collect();
+
+        let evaluations = evaluations(&EvalParameters::default(), &positions);
+        let scaling = optimal_scaling(&positions, &evaluations);
+
+        // The results match the evaluations, so they are sharpened as much as possible
+        assert!(evaluations[0] > 0.0 && evaluations[2] < 0.0);
+        assert!(scaling > 1.0, "{scaling}");
+    }
+}
@@ -0,0 +1,7 @@
+//! Stonefish, an amateur chess engine.
+//!
+//! The engine is run by the main binary, the library also gives the `tune` binary
+//! access to the evaluation.
+
+pub mod stonefish;
+pub mod uci;
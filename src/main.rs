@@ -1,8 +1,4 @@
-use stonefish::Stonefish;
-use uci::UciRunner;
-
-mod stonefish;
-mod uci;
+use stonefish_engine::{stonefish::Stonefish, uci::UciRunner};
 
 fn main() {
     // Launch UCI protocol
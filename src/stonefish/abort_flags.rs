@@ -1,10 +1,13 @@
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
+    atomic::{AtomicBool, AtomicUsize, Ordering},
     Arc,
 };
 
 use crate::uci::AbortFlag;
 
+/// A counter for the nodes searched, shared between all search threads.
+pub type NodeCounter = Arc<AtomicUsize>;
+
 /// The search has been aborted.
 #[derive(Debug, Eq, PartialEq)]
 pub struct SearchAborted;
@@ -15,6 +18,12 @@ pub struct AbortFlags {
     stop_flag: AbortFlag,
     /// Flag to check if the search ran out of time.
     time_flag: AbortFlag,
+    /// The number of nodes that have been searched so far.
+    node_counter: NodeCounter,
+    /// The maximum number of nodes to search.
+    max_nodes: Option<usize>,
+    /// The number of positions that have been found in the endgame tablebases.
+    tb_hits: NodeCounter,
 }
 
 impl AbortFlags {
@@ -24,24 +33,81 @@ impl AbortFlags {
         Self {
             stop_flag: Arc::new(AtomicBool::new(false)),
             time_flag: Arc::new(AtomicBool::new(false)),
+            node_counter: Arc::new(AtomicUsize::new(0)),
+            max_nodes: None,
+            tb_hits: Arc::new(AtomicUsize::new(0)),
         }
     }
 
     /// Create new abort flags from existing flags.
-    pub fn from_flags(stop_flag: AbortFlag, time_flag: AbortFlag) -> Self {
+    pub fn from_flags(
+        stop_flag: AbortFlag,
+        time_flag: AbortFlag,
+        node_counter: NodeCounter,
+        max_nodes: Option<usize>,
+        tb_hits: NodeCounter,
+    ) -> Self {
         Self {
             stop_flag,
             time_flag,
+            node_counter,
+            max_nodes,
+            tb_hits,
         }
     }
 
+    /// Add the given number of nodes to the searched nodes.
+    pub fn add_nodes(&self, count: usize) {
+        self.node_counter.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Count a position that has been found in the endgame tablebases.
+    pub fn add_tb_hit(&self) {
+        self.tb_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
     /// Check if the search has been aborted.
     pub fn check(&self) -> Result<(), SearchAborted> {
         // Check if the search has been aborted
         if self.stop_flag.load(Ordering::SeqCst) || self.time_flag.load(Ordering::SeqCst) {
-            Err(SearchAborted)
-        } else {
-            Ok(())
+            return Err(SearchAborted);
+        }
+
+        // Check if the node budget has been used up
+        if self
+            .max_nodes
+            .is_some_and(|max_nodes| self.node_counter.load(Ordering::Relaxed) >= max_nodes)
+        {
+            return Err(SearchAborted);
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc,
+    };
+
+    use super::{AbortFlags, SearchAborted};
+
+    #[test]
+    fn should_abort_when_node_budget_is_used_up() {
+        let abort_flags = AbortFlags::from_flags(
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            Some(10),
+            Arc::new(AtomicUsize::new(0)),
+        );
+
+        abort_flags.add_nodes(9);
+        assert_eq!(abort_flags.check(), Ok(()));
+
+        abort_flags.add_nodes(1);
+        assert_eq!(abort_flags.check(), Err(SearchAborted));
     }
 }
@@ -0,0 +1,323 @@
+//! Opening books in the Polyglot format.
+//!
+//! A book is a list of entries sorted by the key of their position.
+//! Every entry has 16 bytes: the key, the move, the weight of the move and learning data,
+//! all stored big-endian. Moves with higher weights should be played more often.
+//!
+//! See <http://hgm.nubati.net/book_format.html>.
+
+mod key;
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt::Display,
+    fs,
+    hash::{BuildHasher, Hasher},
+    path::Path,
+};
+
+use pleco::{BitMove, Board, PieceType, SQ};
+
+use self::key::polyglot_key;
+
+/// The number of bytes of a book entry.
+const ENTRY_SIZE: usize = 16;
+
+/// An error while loading a book.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookError {
+    /// The file could not be read.
+    Io(String),
+    /// The number of bytes is not a multiple of the entry size.
+    InvalidSize(usize),
+}
+
+impl Display for BookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "could not read the file: {error}"),
+            Self::InvalidSize(size) => write!(f, "{size} bytes is not a valid book size"),
+        }
+    }
+}
+
+/// How the move is chosen if the book has multiple moves for a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BookSelection {
+    /// A random move, moves with higher weights are chosen more often.
+    #[default]
+    Weighted,
+    /// Always the move with the highest weight.
+    Best,
+}
+
+impl BookSelection {
+    /// The names of the selections, as shown in the GUI.
+    pub const NAMES: [&'static str; 2] = ["Weighted", "Best"];
+
+    /// The selection with the given name, ignoring the case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "weighted" => Some(Self::Weighted),
+            "best" => Some(Self::Best),
+            _ => None,
+        }
+    }
+}
+
+/// A move of the book for a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BookEntry {
+    /// The Polyglot key of the position.
+    key: u64,
+    /// The move, encoded as target square, source square and promotion piece.
+    mv: u16,
+    /// How often the move should be played, relative to the other moves of the position.
+    weight: u16,
+}
+
+impl BookEntry {
+    /// Parse an entry from its bytes, the learning data is ignored.
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            key: u64::from_be_bytes(bytes[0..8].try_into().unwrap()),
+            mv: u16::from_be_bytes([bytes[8], bytes[9]]),
+            weight: u16::from_be_bytes([bytes[10], bytes[11]]),
+        }
+    }
+
+    /// The legal move of the entry in the position, if there is one.
+    ///
+    /// Castling is encoded as the king moving to the rook.
+    fn legal_move(&self, board: &Board) -> Option<BitMove> {
+        let square_name = |square: u16| {
+            let file = (b'a' + (square % 8) as u8) as char;
+            let rank = (b'1' + (square / 8) as u8) as char;
+            format!("{file}{rank}")
+        };
+
+        let from = (self.mv >> 6) & 0x3f;
+        let mut to = self.mv & 0x3f;
+        let promotion = match (self.mv >> 12) & 0x7 {
+            1 => "n",
+            2 => "b",
+            3 => "r",
+            4 => "q",
+            _ => "",
+        };
+
+        let is_king = board.piece_at_sq(SQ(from as u8)).type_of() == PieceType::K;
+
+        if is_king && from % 8 == 4 && to / 8 == from / 8 {
+            // The king moves two squares towards the rook instead
+            match to % 8 {
+                0 => to = from - 2,
+                7 => to = from + 2,
+                _ => (),
+            }
+        }
+
+        let uci_move = format!("{}{}{promotion}", square_name(from), square_name(to));
+
+        board
+            .generate_moves()
+            .iter()
+            .find(|mv| mv.stringify() == uci_move)
+            .copied()
+    }
+}
+
+/// An opening book, loaded from a Polyglot file.
+#[derive(Debug)]
+pub struct Book {
+    /// The entries, sorted by key.
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    /// Load a book from a Polyglot file.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, BookError> {
+        let bytes = fs::read(path).map_err(|error| BookError::Io(error.to_string()))?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    /// Load a book from the bytes of a Polyglot file.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BookError> {
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(BookError::InvalidSize(bytes.len()));
+        }
+
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(BookEntry::from_bytes)
+            .collect();
+        // The entries should already be sorted, but we rely on it for the lookup
+        entries.sort_by_key(|entry| entry.key);
+
+        Ok(Self { entries })
+    }
+
+    /// The number of entries in the book.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determine if the book has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The legal moves of the book in the position, with their weights.
+    fn moves(&self, board: &Board) -> Vec<(BitMove, u16)> {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+
+        self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((entry.legal_move(board)?, entry.weight)))
+            .collect()
+    }
+
+    /// Choose a move of the book for the position, if the position is in the book.
+    ///
+    /// `random` is used to choose between the moves with the weighted selection.
+    pub fn choose_move(
+        &self,
+        board: &Board,
+        selection: BookSelection,
+        random: u64,
+    ) -> Option<BitMove> {
+        let moves = self.moves(board);
+        let total_weight: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+
+        if selection == BookSelection::Best || total_weight == 0 {
+            // Prefer the first move if the weights are equal
+            return moves
+                .iter()
+                .rev()
+                .max_by_key(|(_, weight)| *weight)
+                .map(|(mv, _)| *mv);
+        }
+
+        let mut target = random % total_weight;
+
+        for (mv, weight) in moves {
+            if target < weight as u64 {
+                return Some(mv);
+            }
+
+            target -= weight as u64;
+        }
+
+        None
+    }
+}
+
+/// A random number to choose between book moves, different for every call.
+pub fn random_seed() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use super::{key::polyglot_key, Book, BookError, BookSelection};
+
+    /// The bytes of a book entry for the position after the moves.
+    fn entry_bytes(moves: &[&str], mv: u16, weight: u16) -> Vec<u8> {
+        let mut board = Board::start_pos();
+
+        for uci_move in moves {
+            assert!(board.apply_uci_move(uci_move));
+        }
+
+        let mut bytes = polyglot_key(&board).to_be_bytes().to_vec();
+        bytes.extend(mv.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes
+    }
+
+    /// Encode a move in the Polyglot format from its squares, e.g. `(12, 28)` for e2e4.
+    fn encode_move(from: u16, to: u16) -> u16 {
+        from << 6 | to
+    }
+
+    #[test]
+    fn should_choose_book_moves() {
+        let bytes = [
+            // 1. e4 with weight 3 and 1. d4 with weight 1
+            entry_bytes(&[], encode_move(12, 28), 3),
+            entry_bytes(&[], encode_move(11, 27), 1),
+            // An illegal move is ignored
+            entry_bytes(&["e2e4"], encode_move(12, 28), 100),
+            entry_bytes(&["e2e4"], encode_move(52, 36), 1),
+        ]
+        .concat();
+        let book = Book::from_bytes(&bytes).unwrap();
+        let board = Board::start_pos();
+
+        let choose = |board: &Board, selection, random| {
+            book.choose_move(board, selection, random)
+                .map(|mv| mv.stringify())
+        };
+
+        assert_eq!(book.len(), 4);
+        assert_eq!(
+            choose(&board, BookSelection::Best, 0),
+            Some("e2e4".to_string())
+        );
+
+        let parameters = [(0, "e2e4"), (2, "e2e4"), (3, "d2d4"), (7, "d2d4")];
+
+        for (random, expected) in parameters {
+            assert_eq!(
+                choose(&board, BookSelection::Weighted, random),
+                Some(expected.to_string()),
+                "{random}"
+            );
+        }
+
+        let mut board = Board::start_pos();
+        board.apply_uci_move("e2e4");
+        assert_eq!(
+            choose(&board, BookSelection::Best, 0),
+            Some("e7e5".to_string())
+        );
+
+        // The position has left the book
+        board.apply_uci_move("e7e5");
+        assert_eq!(choose(&board, BookSelection::Weighted, 0), None);
+    }
+
+    #[test]
+    fn should_decode_castling() {
+        let moves = ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4", "g8f6"];
+        // The king moves to the rook
+        let bytes = entry_bytes(&moves, encode_move(4, 7), 1);
+        let book = Book::from_bytes(&bytes).unwrap();
+        let mut board = Board::start_pos();
+
+        for uci_move in moves {
+            board.apply_uci_move(uci_move);
+        }
+
+        assert_eq!(
+            book.choose_move(&board, BookSelection::Best, 0)
+                .map(|mv| mv.stringify()),
+            Some("e1g1".to_string())
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_books() {
+        assert_eq!(
+            Book::from_bytes(&[0; 17]).unwrap_err(),
+            BookError::InvalidSize(17)
+        );
+        assert!(Book::from_file("does/not/exist.bin").is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use pleco::{Board, PieceType, Player};
+
+/// The number of plies without capture or pawn move after which the game is drawn.
+const FIFTY_MOVE_PLIES: i16 = 100;
+
+/// Determine if the game is drawn by the fifty-move rule.
+///
+/// A checkmate on the last move still counts.
+/// See <https://www.chessprogramming.org/Fifty-move_Rule>.
+pub fn is_fifty_move_draw(board: &Board) -> bool {
+    board.rule_50() >= FIFTY_MOVE_PLIES && !board.checkmate()
+}
+
+/// Determine if neither player has enough material left to checkmate.
+///
+/// This is the case with only the kings and at most one knight or bishop left.
+/// See <https://www.chessprogramming.org/Draw_Evaluation>.
+pub fn is_insufficient_material(board: &Board) -> bool {
+    if board.count_all_pieces() > 3 {
+        return false;
+    }
+
+    [Player::White, Player::Black].into_iter().all(|player| {
+        [PieceType::P, PieceType::R, PieceType::Q]
+            .into_iter()
+            .all(|piece| board.count_piece(player, piece) == 0)
+    })
+}
+
+/// Determine if the game is drawn by the fifty-move rule or insufficient material.
+///
+/// Repetitions are tracked separately, see [`super::types::RepetitionTable`].
+pub fn is_rule_draw(board: &Board) -> bool {
+    is_insufficient_material(board) || is_fifty_move_draw(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use super::{is_fifty_move_draw, is_insufficient_material, is_rule_draw};
+
+    #[test]
+    fn should_detect_insufficient_material() {
+        let parameters = [
+            ("king against king", "8/8/8/4k3/8/8/4K3/8 w - - 0 1", true),
+            ("bishop", "8/8/8/4k3/8/8/3BK3/8 w - - 0 1", true),
+            ("knight", "8/8/1n6/4k3/8/8/4K3/8 b - - 0 1", true),
+            ("two knights", "8/8/1n6/4k3/8/8/4K1N1/8 w - - 0 1", false),
+            ("pawn", "8/8/8/4k3/8/8/3PK3/8 w - - 0 1", false),
+            ("rook", "8/8/8/4k3/8/8/3RK3/8 w - - 0 1", false),
+            (
+                "start position",
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                false,
+            ),
+        ];
+
+        for (name, fen, expected) in parameters {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(is_insufficient_material(&board), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn should_detect_fifty_move_draws() {
+        let parameters = [
+            (
+                "clock below limit",
+                "8/8/8/4k3/8/8/3QK3/8 b - - 99 120",
+                false,
+            ),
+            ("clock at limit", "8/8/8/4k3/8/8/3QK3/8 b - - 100 120", true),
+            (
+                "clock above limit",
+                "8/8/8/4k3/8/8/3QK3/8 b - - 120 130",
+                true,
+            ),
+            (
+                "checkmate on the last move",
+                "3k4/3Q4/3K4/8/8/8/8/8 b - - 100 120",
+                false,
+            ),
+        ];
+
+        for (name, fen, expected) in parameters {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(is_fifty_move_draw(&board), expected, "{name}");
+            assert_eq!(is_rule_draw(&board), expected, "{name}");
+        }
+    }
+}
@@ -16,6 +16,13 @@ pub enum Evaluation {
 }
 use std::cmp::Ordering;
 
+/// The evaluation of a win found in the endgame tablebases, in centipawns.
+///
+/// It is above every heuristic evaluation, but below every mate.
+pub const TABLEBASE_WIN: i32 = 20_000;
+/// Evaluations above this value are tablebase wins that are reached after some plies.
+const MIN_TABLEBASE_WIN: i32 = TABLEBASE_WIN - 1_000;
+
 impl Evaluation {
     /// Determine if the evaluation marks the end of the game.
     pub fn is_game_over(&self) -> bool {
@@ -34,19 +41,45 @@ impl Evaluation {
 
     /// Convert the evaluation to the previous plie.
     ///
+    /// Like mates, tablebase wins are evaluated lower the more plies it takes to reach them.
+    ///
     /// # Examples
     ///
-    /// ```
+    /// ```ignore
     /// assert_eq!(Evaluation::PlayerCheckmate(3).previous_plie(), Evaluation::PlayerCheckmate(4));
     /// ```
     pub fn previous_plie(&self) -> Self {
         match self {
+            Evaluation::Centipawns(mat) if mat.abs() > MIN_TABLEBASE_WIN => {
+                Evaluation::Centipawns(mat - mat.signum())
+            }
             Evaluation::Centipawns(mat) => Evaluation::Centipawns(*mat),
             Evaluation::PlayerCheckmate(plies) => Evaluation::PlayerCheckmate(plies + 1),
             Evaluation::OpponentCheckmate(plies) => Evaluation::OpponentCheckmate(plies + 1),
             Evaluation::Draw => Evaluation::Draw,
         }
     }
+
+    /// The smallest evaluation that is better than this one.
+    ///
+    /// This is used to search with a null window.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// assert_eq!(Evaluation::Centipawns(20).next_better(), Evaluation::Centipawns(21));
+    /// assert_eq!(Evaluation::PlayerCheckmate(3).next_better(), Evaluation::PlayerCheckmate(2));
+    /// ```
+    pub fn next_better(&self) -> Self {
+        match self {
+            Evaluation::Centipawns(mat) => Evaluation::Centipawns(mat.saturating_add(1)),
+            Evaluation::PlayerCheckmate(plies) => {
+                Evaluation::PlayerCheckmate(plies.saturating_sub(1))
+            }
+            Evaluation::OpponentCheckmate(plies) => Evaluation::OpponentCheckmate(plies + 1),
+            Evaluation::Draw => Evaluation::Centipawns(1),
+        }
+    }
 }
 
 impl Ord for Evaluation {
@@ -104,7 +137,7 @@ impl PartialEq for Evaluation {
 mod tests {
     use std::cmp::Ordering;
 
-    use super::Evaluation;
+    use super::{Evaluation, TABLEBASE_WIN};
 
     #[test]
     fn should_recognize_game_over() {
@@ -154,6 +187,35 @@ mod tests {
             Evaluation::Centipawns(100).previous_plie(),
             Evaluation::Centipawns(100)
         );
+        assert_eq!(
+            Evaluation::Centipawns(TABLEBASE_WIN).previous_plie(),
+            Evaluation::Centipawns(TABLEBASE_WIN - 1)
+        );
+        assert_eq!(
+            Evaluation::Centipawns(-TABLEBASE_WIN).previous_plie(),
+            Evaluation::Centipawns(-TABLEBASE_WIN + 1)
+        );
+    }
+
+    #[test]
+    fn should_convert_to_next_better() {
+        let evaluations = [
+            Evaluation::Centipawns(-100),
+            Evaluation::Centipawns(100),
+            Evaluation::Draw,
+            Evaluation::PlayerCheckmate(3),
+            Evaluation::OpponentCheckmate(3),
+        ];
+
+        for evaluation in evaluations {
+            assert!(evaluation.next_better() > evaluation, "{evaluation:?}");
+        }
+
+        assert_eq!(Evaluation::Draw.next_better(), Evaluation::Centipawns(1));
+        assert_eq!(
+            Evaluation::OpponentCheckmate(3).next_better(),
+            Evaluation::OpponentCheckmate(4)
+        );
     }
 
     #[test]
@@ -0,0 +1,183 @@
+//! The handcrafted evaluation, see the [heuristic module](crate::stonefish::heuristic).
+
+use std::sync::Arc;
+
+use pleco::{BitMove, Board};
+
+use crate::stonefish::{
+    evaluation::Evaluation,
+    heuristic::{
+        final_heuristic, move_heuristic, move_piece_square_delta, piece_square_score,
+        static_heuristic, EvalParameters, PawnHashTable, TaperedScore,
+    },
+};
+
+use super::Evaluator;
+
+/// Evaluates positions with the handcrafted heuristics.
+///
+/// The material, positions and pawn structure are updated incrementally,
+/// the attacks of the pieces are only evaluated in quiet positions.
+#[derive(Debug)]
+pub struct HandcraftedEvaluator<'a> {
+    /// The weights of the evaluation terms.
+    parameters: Arc<EvalParameters>,
+    /// The cached pawn structures of the search thread.
+    pawn_table: &'a mut PawnHashTable,
+    /// The sums of the piece positions of the current line, the last one is the current position.
+    ///
+    /// They are only blended by the game phase when a move is evaluated.
+    piece_squares: Vec<TaperedScore>,
+}
+
+impl<'a> HandcraftedEvaluator<'a> {
+    /// Create an evaluator for a search from the given position.
+    pub fn new(
+        parameters: Arc<EvalParameters>,
+        pawn_table: &'a mut PawnHashTable,
+        board: &Board,
+    ) -> Self {
+        let piece_squares = vec![piece_square_score(&parameters, board)];
+
+        Self {
+            parameters,
+            pawn_table,
+            piece_squares,
+        }
+    }
+
+    /// The sum of the piece positions of the current position.
+    fn current_piece_squares(&self) -> TaperedScore {
+        *self.piece_squares.last().unwrap()
+    }
+}
+
+impl Evaluator for HandcraftedEvaluator<'_> {
+    fn move_evaluation(
+        &mut self,
+        evaluation: Evaluation,
+        board: &Board,
+        mv: BitMove,
+    ) -> Evaluation {
+        let piece_squares = self.current_piece_squares();
+        move_heuristic(
+            &self.parameters,
+            self.pawn_table,
+            piece_squares,
+            evaluation,
+            board,
+            mv,
+        )
+    }
+
+    fn static_evaluation(&self, evaluation: Evaluation, board: &Board) -> Evaluation {
+        static_heuristic(&self.parameters, evaluation, board)
+    }
+
+    fn final_evaluation(&self, evaluation: Evaluation, board: &Board) -> Evaluation {
+        final_heuristic(evaluation, board)
+    }
+
+    fn estimates_static_evaluation(&self) -> bool {
+        true
+    }
+
+    fn make_move(&mut self, board: &Board, mv: BitMove) {
+        let delta = move_piece_square_delta(&self.parameters, board, mv);
+        self.piece_squares
+            .push(self.current_piece_squares() + delta);
+    }
+
+    fn make_null_move(&mut self) {
+        // The pieces stay on their squares
+        self.piece_squares.push(self.current_piece_squares());
+    }
+
+    fn undo_move(&mut self) {
+        self.piece_squares.pop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use pleco::Board;
+
+    use crate::stonefish::{
+        evaluator::Evaluator,
+        heuristic::{initial_heuristic, piece_square_score, EvalParameters, PawnHashTable},
+    };
+
+    use super::HandcraftedEvaluator;
+
+    #[test]
+    fn should_evaluate_with_own_parameters() {
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let default_parameters = Arc::new(EvalParameters::default());
+        let mut rook_parameters = EvalParameters::default();
+        let mut weights = rook_parameters.weights();
+        // The value of the rook
+        weights[3] += 100;
+        rook_parameters.set_weights(&weights);
+        let rook_parameters = Arc::new(rook_parameters);
+
+        let mut pawn_table = PawnHashTable::new();
+        let mut evaluate = |parameters: &Arc<EvalParameters>| {
+            let evaluator = HandcraftedEvaluator::new(parameters.clone(), &mut pawn_table, &board);
+            evaluator.static_evaluation(initial_heuristic(parameters, &board), &board)
+        };
+
+        assert!(evaluate(&rook_parameters) > evaluate(&default_parameters));
+    }
+
+    #[test]
+    fn should_update_piece_squares_incrementally() {
+        let eval_parameters = Arc::new(EvalParameters::default());
+        let parameters = [
+            // Castling on both sides
+            (
+                "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1",
+                vec!["e1g1", "e8c8", "g1h1"],
+            ),
+            // En passant
+            ("4k3/1p6/8/2pP4/8/8/8/4K3 w - c6 0 2", vec!["d5c6", "b7c6"]),
+            // Promotion with capture, which changes the game phase
+            (
+                "r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1",
+                vec!["b7a8q", "e8d7", "a8a7"],
+            ),
+        ];
+
+        for (fen, moves) in parameters {
+            let mut board = Board::from_fen(fen).unwrap();
+            let mut pawn_table = PawnHashTable::new();
+            let mut evaluator =
+                HandcraftedEvaluator::new(eval_parameters.clone(), &mut pawn_table, &board);
+            let initial = evaluator.current_piece_squares();
+
+            for uci_move in &moves {
+                let mv = board
+                    .generate_moves()
+                    .iter()
+                    .copied()
+                    .find(|mv| mv.stringify() == *uci_move)
+                    .unwrap();
+                evaluator.make_move(&board, mv);
+                board.apply_move(mv);
+
+                assert_eq!(
+                    evaluator.current_piece_squares(),
+                    piece_square_score(&eval_parameters, &board),
+                    "{fen} after {uci_move}"
+                );
+            }
+
+            for _ in &moves {
+                evaluator.undo_move();
+            }
+
+            assert_eq!(evaluator.current_piece_squares(), initial, "{fen}");
+        }
+    }
+}
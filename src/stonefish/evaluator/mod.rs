@@ -0,0 +1,107 @@
+//! The evaluators that score the positions of a search.
+//!
+//! The handcrafted evaluation is always available,
+//! the neural network evaluation needs a [`Network`] loaded from the `EvalFile` option.
+
+use std::sync::Arc;
+
+use pleco::{BitMove, Board};
+
+use super::{
+    evaluation::Evaluation,
+    heuristic::{EvalParameters, PawnHashTable},
+};
+
+use self::{handcrafted::HandcraftedEvaluator, nnue::NnueEvaluator};
+
+mod handcrafted;
+mod nnue;
+
+pub use self::nnue::Network;
+
+/// The evaluation of the positions in a search.
+///
+/// Every search has its own evaluator, which follows the moves made on the board.
+/// The search passes a cheap incremental evaluation down the tree, which is used to order and prune moves.
+/// Quiet positions are then scored with the static evaluation.
+pub trait Evaluator {
+    /// The incremental evaluation after the move, used for move ordering.
+    ///
+    /// The board is the position before the move has been applied,
+    /// the returned evaluation is from the view of the opponent.
+    fn move_evaluation(&mut self, evaluation: Evaluation, board: &Board, mv: BitMove)
+        -> Evaluation;
+
+    /// The evaluation of the quiet position on the board, e.g. to stand pat in the quiescence search.
+    ///
+    /// The given evaluation is the incremental evaluation of the position.
+    fn static_evaluation(&self, evaluation: Evaluation, board: &Board) -> Evaluation;
+
+    /// The evaluation of a position without legal moves.
+    fn final_evaluation(&self, evaluation: Evaluation, board: &Board) -> Evaluation;
+
+    /// Whether the move evaluations are close to the static evaluation of the resulting position.
+    ///
+    /// Only then they can be compared with the search window, e.g. to skip hopeless captures.
+    fn estimates_static_evaluation(&self) -> bool;
+
+    /// Follow the move, before it is applied to the board.
+    fn make_move(&mut self, _board: &Board, _mv: BitMove) {}
+
+    /// Follow passing the turn.
+    fn make_null_move(&mut self) {}
+
+    /// Take back the last move or null move.
+    fn undo_move(&mut self) {}
+}
+
+/// The kind of evaluator used by the search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvaluatorType {
+    /// The handcrafted evaluation, see [`HandcraftedEvaluator`].
+    #[default]
+    Handcrafted,
+    /// The neural network evaluation, see [`NnueEvaluator`].
+    Nnue,
+}
+
+impl EvaluatorType {
+    /// The names of the evaluators, as shown in the GUI.
+    pub const NAMES: [&'static str; 2] = ["Handcrafted", "NNUE"];
+
+    /// The evaluator with the given name, ignoring the case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "handcrafted" => Some(Self::Handcrafted),
+            "nnue" => Some(Self::Nnue),
+            _ => None,
+        }
+    }
+
+    /// Create an evaluator for a search from the given position.
+    ///
+    /// The parameters weight the handcrafted evaluation, which also orders the moves of the network.
+    /// The pawn table of the search thread caches the pawn structures of the handcrafted evaluation.
+    /// Falls back to the handcrafted evaluation if no network has been loaded.
+    pub fn create<'a>(
+        &self,
+        board: &Board,
+        parameters: &Arc<EvalParameters>,
+        network: Option<&Arc<Network>>,
+        pawn_table: &'a mut PawnHashTable,
+    ) -> Box<dyn Evaluator + 'a> {
+        match (self, network) {
+            (Self::Nnue, Some(network)) => Box::new(NnueEvaluator::new(
+                network.clone(),
+                parameters.clone(),
+                pawn_table,
+                board,
+            )),
+            _ => Box::new(HandcraftedEvaluator::new(
+                parameters.clone(),
+                pawn_table,
+                board,
+            )),
+        }
+    }
+}
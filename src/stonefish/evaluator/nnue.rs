@@ -0,0 +1,484 @@
+//! An efficiently updatable neural network (NNUE) to evaluate positions.
+//!
+//! The network has a hidden layer for the view of each player,
+//! with one input for every piece on every square (768 → N)x2 → 1.
+//! The hidden layers are kept in accumulators that are updated for every move,
+//! only the output layer has to be computed to evaluate a position.
+//!
+//! See <https://www.chessprogramming.org/NNUE>.
+
+use std::{fmt::Display, fs, path::Path, sync::Arc};
+
+use pleco::{BitMove, Board, PieceType, Player, SQ};
+
+use crate::stonefish::{
+    evaluation::Evaluation,
+    heuristic::{EvalParameters, PawnHashTable},
+};
+
+use super::{handcrafted::HandcraftedEvaluator, Evaluator};
+
+/// The number of inputs, for every player, piece type and square.
+const FEATURES: usize = 2 * 6 * 64;
+/// The quantization of the hidden layer.
+const QA: i32 = 255;
+/// The quantization of the output layer.
+const QB: i32 = 64;
+/// Scales the output of the network to centipawns.
+const SCALE: i32 = 400;
+/// The network files are padded to a multiple of this number of bytes.
+const FILE_ALIGNMENT: usize = 64;
+
+/// An error while loading a network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    /// The file could not be read.
+    Io(String),
+    /// The number of bytes doesn't match any size of the hidden layer.
+    InvalidSize(usize),
+}
+
+impl Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "could not read the file: {error}"),
+            Self::InvalidSize(size) => write!(f, "{size} bytes is not a valid network size"),
+        }
+    }
+}
+
+/// The index of the piece type in the inputs.
+fn piece_index(piece: PieceType) -> usize {
+    match piece {
+        PieceType::P => 0,
+        PieceType::N => 1,
+        PieceType::B => 2,
+        PieceType::R => 3,
+        PieceType::Q => 4,
+        _ => 5,
+    }
+}
+
+/// The input of the piece on the square, from the view of the given player.
+///
+/// The own pieces come first, the board is mirrored for Black.
+fn feature(view: Player, player: Player, piece: PieceType, sq: SQ) -> usize {
+    let sq = match view {
+        Player::White => sq.0,
+        Player::Black => sq.0 ^ 56,
+    };
+
+    (view != player) as usize * 384 + piece_index(piece) * 64 + sq as usize
+}
+
+/// The weights of a network, quantized to integers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    /// The number of neurons in the hidden layer of each view.
+    hidden_size: usize,
+    /// The weights of the hidden layer, indexed by input and neuron.
+    feature_weights: Vec<i16>,
+    /// The biases of the hidden layer.
+    feature_biases: Vec<i16>,
+    /// The weights of the output layer, first for the player to move, then for the opponent.
+    output_weights: Vec<i16>,
+    /// The bias of the output layer.
+    output_bias: i16,
+}
+
+impl Network {
+    /// Read a network from its binary format.
+    ///
+    /// The file consists of little-endian 16-bit integers: the weights of the hidden layer
+    /// (768 x N, ordered by input), its N biases, the 2 x N weights of the output layer and its bias.
+    /// The size of the hidden layer is determined by the size of the file,
+    /// which can be padded to a multiple of 64 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NetworkError> {
+        let values: Vec<i16> = bytes
+            .chunks_exact(2)
+            .map(|value| i16::from_le_bytes([value[0], value[1]]))
+            .collect();
+
+        let hidden_size = values.len().saturating_sub(1) / (FEATURES + 3);
+        let network_bytes = 2 * ((FEATURES + 3) * hidden_size + 1);
+
+        if hidden_size == 0 || bytes.len() - network_bytes >= FILE_ALIGNMENT {
+            return Err(NetworkError::InvalidSize(bytes.len()));
+        }
+
+        let (feature_weights, values) = values.split_at(FEATURES * hidden_size);
+        let (feature_biases, values) = values.split_at(hidden_size);
+        let (output_weights, values) = values.split_at(2 * hidden_size);
+
+        Ok(Self {
+            hidden_size,
+            feature_weights: feature_weights.to_vec(),
+            feature_biases: feature_biases.to_vec(),
+            output_weights: output_weights.to_vec(),
+            output_bias: values[0],
+        })
+    }
+
+    /// Load a network from a file in the binary format.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, NetworkError> {
+        let bytes = fs::read(path).map_err(|error| NetworkError::Io(error.to_string()))?;
+
+        Self::from_bytes(&bytes)
+    }
+
+    /// The weights of the hidden layer for the input.
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.feature_weights[feature * self.hidden_size..(feature + 1) * self.hidden_size]
+    }
+
+    /// Compute the hidden layers of the position from scratch.
+    fn accumulator(&self, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator {
+            views: [self.feature_biases.clone(), self.feature_biases.clone()],
+        };
+
+        for player in [Player::White, Player::Black] {
+            for piece in [
+                PieceType::P,
+                PieceType::N,
+                PieceType::B,
+                PieceType::R,
+                PieceType::Q,
+                PieceType::K,
+            ] {
+                for sq in board.piece_bb(player, piece) {
+                    accumulator.add(self, player, piece, sq);
+                }
+            }
+        }
+
+        accumulator
+    }
+
+    /// The evaluation of the hidden layers, from the view of the player to move, in centipawns.
+    fn evaluate(&self, accumulator: &Accumulator, player: Player) -> i32 {
+        let views = [
+            &accumulator.views[player as usize],
+            &accumulator.views[player.other_player() as usize],
+        ];
+        let mut output: i64 = 0;
+
+        for (view, weights) in views
+            .into_iter()
+            .zip(self.output_weights.chunks_exact(self.hidden_size))
+        {
+            for (value, weight) in view.iter().zip(weights) {
+                // Squared clipped ReLU
+                let activation = (*value as i64).clamp(0, QA as i64);
+                output += activation * activation * *weight as i64;
+            }
+        }
+
+        let output = output / QA as i64 + self.output_bias as i64;
+        (output * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+}
+
+/// The hidden layers of the network for a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Accumulator {
+    /// The values of the hidden layer, indexed by the player of the view.
+    views: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    /// Add the piece of the player on the square.
+    fn add(&mut self, network: &Network, player: Player, piece: PieceType, sq: SQ) {
+        for view in [Player::White, Player::Black] {
+            let weights = network.weights(feature(view, player, piece, sq));
+
+            for (value, weight) in self.views[view as usize].iter_mut().zip(weights) {
+                *value = value.wrapping_add(*weight);
+            }
+        }
+    }
+
+    /// Remove the piece of the player from the square.
+    fn remove(&mut self, network: &Network, player: Player, piece: PieceType, sq: SQ) {
+        for view in [Player::White, Player::Black] {
+            let weights = network.weights(feature(view, player, piece, sq));
+
+            for (value, weight) in self.views[view as usize].iter_mut().zip(weights) {
+                *value = value.wrapping_sub(*weight);
+            }
+        }
+    }
+
+    /// Update the accumulator with the move.
+    ///
+    /// The board is the position before the move has been applied.
+    fn apply_move(&mut self, network: &Network, board: &Board, mv: BitMove) {
+        let player = board.turn();
+        let opponent = player.other_player();
+
+        if mv.is_castle() {
+            // Both the king and the rook change their square
+            let (king_squares, rook_squares) = match (player, mv.is_king_castle()) {
+                (Player::White, true) => ((SQ::E1, SQ::G1), (SQ::H1, SQ::F1)),
+                (Player::White, false) => ((SQ::E1, SQ::C1), (SQ::A1, SQ::D1)),
+                (Player::Black, true) => ((SQ::E8, SQ::G8), (SQ::H8, SQ::F8)),
+                (Player::Black, false) => ((SQ::E8, SQ::C8), (SQ::A8, SQ::D8)),
+            };
+
+            self.remove(network, player, PieceType::K, king_squares.0);
+            self.add(network, player, PieceType::K, king_squares.1);
+            self.remove(network, player, PieceType::R, rook_squares.0);
+            self.add(network, player, PieceType::R, rook_squares.1);
+            return;
+        }
+
+        let src = mv.get_src();
+        let dest = mv.get_dest();
+        let piece = board.piece_at_sq(src).type_of();
+
+        if mv.is_en_passant() {
+            // The captured pawn is not on the destination square
+            let captured_sq = SQ((src.rank_idx_of_sq() * 8) + dest.file_idx_of_sq());
+            self.remove(network, opponent, PieceType::P, captured_sq);
+        } else if mv.is_capture() {
+            let captured_piece = board.piece_at_sq(dest).type_of();
+            self.remove(network, opponent, captured_piece, dest);
+        }
+
+        let new_piece = if mv.is_promo() {
+            mv.promo_piece()
+        } else {
+            piece
+        };
+
+        self.remove(network, player, piece, src);
+        self.add(network, player, new_piece, dest);
+    }
+}
+
+/// Evaluates quiet positions with a neural network.
+///
+/// The accumulators of all positions on the way to the current position are kept on a stack,
+/// so that taking back a move doesn't need any computation.
+/// The moves are still ordered by the handcrafted evaluation, which is cheaper to update.
+#[derive(Debug)]
+pub struct NnueEvaluator<'a> {
+    /// The network to evaluate the positions with.
+    network: Arc<Network>,
+    /// The handcrafted evaluation to order the moves.
+    handcrafted: HandcraftedEvaluator<'a>,
+    /// The accumulators of the positions of the current line, the last one is the current position.
+    ///
+    /// Accumulators of undone moves are kept to reuse their memory.
+    accumulators: Vec<Accumulator>,
+    /// The index of the accumulator of the current position.
+    ply: usize,
+}
+
+impl<'a> NnueEvaluator<'a> {
+    /// Create a new evaluator for a search from the given position.
+    pub fn new(
+        network: Arc<Network>,
+        parameters: Arc<EvalParameters>,
+        pawn_table: &'a mut PawnHashTable,
+        board: &Board,
+    ) -> Self {
+        let accumulator = network.accumulator(board);
+
+        Self {
+            network,
+            handcrafted: HandcraftedEvaluator::new(parameters, pawn_table, board),
+            accumulators: vec![accumulator],
+            ply: 0,
+        }
+    }
+
+    /// Copy the current accumulator to the next ply.
+    fn push_accumulator(&mut self) {
+        if self.accumulators.len() <= self.ply + 1 {
+            self.accumulators.push(self.accumulators[self.ply].clone());
+        } else {
+            let (previous, next) = self.accumulators.split_at_mut(self.ply + 1);
+
+            for (view, previous_view) in next[0].views.iter_mut().zip(&previous[self.ply].views) {
+                view.copy_from_slice(previous_view);
+            }
+        }
+
+        self.ply += 1;
+    }
+}
+
+impl Evaluator for NnueEvaluator<'_> {
+    fn move_evaluation(
+        &mut self,
+        evaluation: Evaluation,
+        board: &Board,
+        mv: BitMove,
+    ) -> Evaluation {
+        self.handcrafted.move_evaluation(evaluation, board, mv)
+    }
+
+    fn static_evaluation(&self, evaluation: Evaluation, board: &Board) -> Evaluation {
+        match evaluation {
+            Evaluation::Centipawns(_) => Evaluation::Centipawns(
+                self.network
+                    .evaluate(&self.accumulators[self.ply], board.turn()),
+            ),
+            _ => evaluation,
+        }
+    }
+
+    fn final_evaluation(&self, evaluation: Evaluation, board: &Board) -> Evaluation {
+        // Only checkmate and stalemate are left, which don't need the network
+        self.handcrafted.final_evaluation(evaluation, board)
+    }
+
+    fn estimates_static_evaluation(&self) -> bool {
+        // The moves are ordered with the handcrafted heuristic, which can be far from the network
+        false
+    }
+
+    fn make_move(&mut self, board: &Board, mv: BitMove) {
+        self.handcrafted.make_move(board, mv);
+        self.push_accumulator();
+        self.accumulators[self.ply].apply_move(&self.network, board, mv);
+    }
+
+    fn make_null_move(&mut self) {
+        // The pieces stay on their squares, only the view of the output changes
+        self.handcrafted.make_null_move();
+        self.push_accumulator();
+    }
+
+    fn undo_move(&mut self) {
+        self.handcrafted.undo_move();
+        self.ply -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use pleco::Board;
+
+    use crate::stonefish::{
+        evaluation::Evaluation,
+        evaluator::Evaluator,
+        heuristic::{EvalParameters, PawnHashTable},
+    };
+
+    use super::{Network, NetworkError, NnueEvaluator, FEATURES};
+
+    /// A network with pseudo-random weights in the binary format.
+    fn random_network_bytes(hidden_size: usize) -> Vec<u8> {
+        let mut state: u32 = 0x1234_5678;
+        let value_count = (FEATURES + 3) * hidden_size + 1;
+
+        (0..value_count)
+            .flat_map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                // Small weights, so that the accumulators don't overflow
+                let value = (state >> 16) as i16 % 64;
+                value.to_le_bytes()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_load_networks_with_padding() {
+        let mut bytes = random_network_bytes(8);
+        assert_eq!(Network::from_bytes(&bytes).unwrap().hidden_size, 8);
+
+        bytes.resize(bytes.len().next_multiple_of(64), 0);
+        assert_eq!(Network::from_bytes(&bytes).unwrap().hidden_size, 8);
+
+        bytes.truncate(bytes.len() - 100);
+        assert_eq!(
+            Network::from_bytes(&bytes),
+            Err(NetworkError::InvalidSize(bytes.len()))
+        );
+        assert_eq!(Network::from_bytes(&[]), Err(NetworkError::InvalidSize(0)));
+    }
+
+    #[test]
+    fn should_update_accumulators_incrementally() {
+        let network = Arc::new(Network::from_bytes(&random_network_bytes(16)).unwrap());
+        let eval_parameters = Arc::new(EvalParameters::default());
+        let mut pawn_table = PawnHashTable::new();
+        let parameters = [
+            // Castling on both sides
+            (
+                "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w KQkq - 0 1",
+                vec!["e1g1", "e8c8", "g1h1"],
+            ),
+            // En passant
+            ("4k3/1p6/8/2pP4/8/8/8/4K3 w - c6 0 2", vec!["d5c6", "b7c6"]),
+            // Promotion with capture
+            (
+                "r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1",
+                vec!["b7a8q", "e8d7", "a8a7"],
+            ),
+        ];
+
+        for (fen, moves) in parameters {
+            let mut board = Board::from_fen(fen).unwrap();
+            let mut evaluator = NnueEvaluator::new(
+                network.clone(),
+                eval_parameters.clone(),
+                &mut pawn_table,
+                &board,
+            );
+            let initial = evaluator.accumulators[0].clone();
+
+            for uci_move in &moves {
+                let mv = board
+                    .generate_moves()
+                    .iter()
+                    .copied()
+                    .find(|mv| mv.stringify() == *uci_move)
+                    .unwrap();
+                evaluator.make_move(&board, mv);
+                board.apply_move(mv);
+
+                assert_eq!(
+                    evaluator.accumulators[evaluator.ply],
+                    network.accumulator(&board),
+                    "{fen} after {uci_move}"
+                );
+            }
+
+            for _ in &moves {
+                evaluator.undo_move();
+            }
+
+            assert_eq!(evaluator.accumulators[evaluator.ply], initial, "{fen}");
+        }
+    }
+
+    #[test]
+    fn should_evaluate_mirrored_positions_equally() {
+        let network = Arc::new(Network::from_bytes(&random_network_bytes(16)).unwrap());
+        let eval_parameters = Arc::new(EvalParameters::default());
+        let mut pawn_table = PawnHashTable::new();
+        let white =
+            Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let black =
+            Board::from_fen("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3")
+                .unwrap();
+
+        let white_eval = NnueEvaluator::new(
+            network.clone(),
+            eval_parameters.clone(),
+            &mut pawn_table,
+            &white,
+        )
+        .static_evaluation(Evaluation::Centipawns(0), &white);
+        let black_eval = NnueEvaluator::new(network, eval_parameters, &mut pawn_table, &black)
+            .static_evaluation(Evaluation::Centipawns(0), &black);
+
+        assert_eq!(white_eval, black_eval);
+    }
+}
@@ -0,0 +1,56 @@
+//! Bitboard helpers shared by the evaluation terms.
+
+use pleco::{
+    helper::prelude::{bishop_moves, knight_moves, queen_moves, rook_moves},
+    BitBoard, PieceType, Player, SQ,
+};
+
+/// The squares attacked by the piece, sliding pieces are blocked by the occupied squares.
+pub fn piece_attacks(piece: PieceType, sq: SQ, occupied: BitBoard) -> BitBoard {
+    match piece {
+        PieceType::N => knight_moves(sq),
+        PieceType::B => bishop_moves(occupied, sq),
+        PieceType::R => rook_moves(occupied, sq),
+        PieceType::Q => queen_moves(occupied, sq),
+        _ => BitBoard(0),
+    }
+}
+
+/// The bitboard of the file of the square.
+pub fn file_bb(sq: SQ) -> BitBoard {
+    BitBoard(BitBoard::FILE_A.0 << sq.file_idx_of_sq())
+}
+
+/// The bitboard of the files next to the file of the square.
+pub fn adjacent_files_bb(sq: SQ) -> BitBoard {
+    let file = file_bb(sq);
+    BitBoard((file.0 << 1) & !BitBoard::FILE_A.0) | BitBoard((file.0 >> 1) & !BitBoard::FILE_H.0)
+}
+
+/// The bitboard of all ranks in front of the square, from the player's view.
+pub fn forward_ranks_bb(sq: SQ, player: Player) -> BitBoard {
+    let rank = sq.rank_idx_of_sq() as u32;
+
+    match player {
+        Player::White => BitBoard(u64::MAX.checked_shl(8 * (rank + 1)).unwrap_or(0)),
+        Player::Black => BitBoard((1 << (8 * rank)) - 1),
+    }
+}
+
+/// The squares attacked by the pawns of the player.
+pub fn pawn_attacks_bb(pawns: BitBoard, player: Player) -> BitBoard {
+    let (left, right) = match player {
+        Player::White => (pawns.0 << 7, pawns.0 << 9),
+        Player::Black => (pawns.0 >> 9, pawns.0 >> 7),
+    };
+
+    BitBoard(left & !BitBoard::FILE_H.0) | BitBoard(right & !BitBoard::FILE_A.0)
+}
+
+/// The rank of the square from the player's view, starting at 0.
+pub fn relative_rank(sq: SQ, player: Player) -> usize {
+    match player {
+        Player::White => sq.rank_idx_of_sq() as usize,
+        Player::Black => 7 - sq.rank_idx_of_sq() as usize,
+    }
+}
@@ -0,0 +1,243 @@
+//! Evaluation of the safety of the kings.
+//!
+//! See <https://www.chessprogramming.org/King_Safety>.
+
+use pleco::{helper::prelude::king_moves, BitBoard, Board, PieceType, Player, SQ};
+
+use super::{
+    bitboards::piece_attacks,
+    parameters::EvalParameters,
+    tapered_score::{game_phase, TaperedScore},
+};
+
+/// The files of a king that hasn't castled yet, the pawn shield is only evaluated on the wings.
+const CENTER_FILES: [u8; 2] = [3, 4];
+/// The attack units of a piece for every attacked square of the king zone.
+fn attack_weight(parameters: &EvalParameters, piece: PieceType) -> i32 {
+    match piece {
+        PieceType::N => parameters.king_attack_weights[0],
+        PieceType::B => parameters.king_attack_weights[1],
+        PieceType::R => parameters.king_attack_weights[2],
+        PieceType::Q => parameters.king_attack_weights[3],
+        _ => 0,
+    }
+}
+
+/// The distance of the square in front of the king, from the player's view.
+///
+/// Returns `None` if the square is not in front of the king.
+fn distance_in_front(king_sq: SQ, sq: SQ, player: Player) -> Option<u8> {
+    let (king_rank, rank) = (king_sq.rank_idx_of_sq(), sq.rank_idx_of_sq());
+
+    match player {
+        Player::White => rank.checked_sub(king_rank),
+        Player::Black => king_rank.checked_sub(rank),
+    }
+    .filter(|distance| *distance > 0)
+}
+
+/// Evaluate the pawns on the file in front of the king.
+fn file_safety(
+    parameters: &EvalParameters,
+    board: &Board,
+    king_sq: SQ,
+    file: u8,
+    player: Player,
+) -> TaperedScore {
+    let file_bb = BitBoard(BitBoard::FILE_A.0 << file);
+    let pawns = board.piece_bb(player, PieceType::P) & file_bb;
+    let opponent_pawns = board.piece_bb(player.other_player(), PieceType::P) & file_bb;
+    let mut value = TaperedScore::default();
+
+    if pawns.is_empty() {
+        value += if opponent_pawns.is_empty() {
+            parameters.open_file
+        } else {
+            parameters.semi_open_file
+        };
+    }
+
+    // The pawns in front of a king in the center will still advance
+    if CENTER_FILES.contains(&king_sq.file_idx_of_sq()) {
+        return value;
+    }
+
+    // The closest pawns in front of the king shield it and block the opposing pawns
+    let shield_distance = pawns
+        .filter_map(|sq| distance_in_front(king_sq, sq, player))
+        .min();
+    value += match shield_distance {
+        Some(1) => parameters.shield_pawn_close,
+        Some(2) => parameters.shield_pawn_far,
+        _ => parameters.missing_shield_pawn,
+    };
+
+    let storm_distance = opponent_pawns
+        .filter_map(|sq| distance_in_front(king_sq, sq, player))
+        .min();
+    if let Some(storm_pawn) =
+        storm_distance.and_then(|distance| parameters.storm_pawn.get(distance as usize))
+    {
+        value += *storm_pawn;
+    }
+
+    value
+}
+
+/// The penalty for the opposing pieces attacking the squares around the king.
+///
+/// Every attacked square adds attack units depending on the attacking piece.
+/// The danger grows quadratically with the attack units,
+/// so that coordinated attacks are much more dangerous than single pieces.
+fn king_danger(
+    parameters: &EvalParameters,
+    board: &Board,
+    king_sq: SQ,
+    player: Player,
+) -> TaperedScore {
+    let opponent = player.other_player();
+    let king_zone = king_moves(king_sq) | king_sq.to_bb();
+    let occupied = board.occupied();
+
+    let mut attackers = 0;
+    let mut attack_units = 0;
+
+    for piece in [PieceType::N, PieceType::B, PieceType::R, PieceType::Q] {
+        for sq in board.piece_bb(opponent, piece) {
+            let attacked_squares = piece_attacks(piece, sq, occupied) & king_zone;
+
+            if attacked_squares.is_not_empty() {
+                attackers += 1;
+                attack_units +=
+                    attack_weight(parameters, piece) * attacked_squares.count_bits() as i32;
+            }
+        }
+    }
+
+    // A single piece can't mount a dangerous attack
+    if attackers < 2 {
+        return TaperedScore::default();
+    }
+
+    let danger = (attack_units * attack_units / 2).min(parameters.max_king_danger);
+    TaperedScore::new(-danger, -danger / 4)
+}
+
+/// Evaluate the safety of the player's king.
+fn player_king_safety(parameters: &EvalParameters, board: &Board, player: Player) -> TaperedScore {
+    let king_sq = board.king_sq(player);
+    let king_file = king_sq.file_idx_of_sq();
+
+    let file_value = (king_file.saturating_sub(1)..=(king_file + 1).min(7))
+        .map(|file| file_safety(parameters, board, king_sq, file, player))
+        .fold(TaperedScore::default(), |value, file_value| {
+            value + file_value
+        });
+
+    file_value + king_danger(parameters, board, king_sq, player)
+}
+
+/// The value of the king safety.
+///
+/// Returns a positive number if the king of the current player is safer.
+pub fn king_safety_value(parameters: &EvalParameters, board: &Board) -> i32 {
+    let player = board.turn();
+    let value = player_king_safety(parameters, board, player)
+        - player_king_safety(parameters, board, player.other_player());
+
+    value.taper(game_phase(board))
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::{Board, Player};
+
+    use crate::stonefish::heuristic::{parameters::EvalParameters, tapered_score::TaperedScore};
+
+    use super::{king_safety_value, player_king_safety};
+
+    #[test]
+    fn should_evaluate_pawn_shield() {
+        let eval_parameters = EvalParameters::default();
+        let EvalParameters {
+            shield_pawn_close,
+            shield_pawn_far,
+            missing_shield_pawn,
+            semi_open_file,
+            open_file,
+            ..
+        } = eval_parameters;
+        let parameters = [
+            (
+                "full shield",
+                "6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1",
+                shield_pawn_close * 3,
+            ),
+            (
+                "advanced shield pawn",
+                "6k1/5p1p/6p1/8/8/6P1/5P1P/6K1 w - - 0 1",
+                shield_pawn_close * 2 + shield_pawn_far,
+            ),
+            (
+                "missing shield pawn",
+                "6k1/5p1p/8/8/8/8/5P1P/6K1 w - - 0 1",
+                shield_pawn_close * 2 + missing_shield_pawn + open_file,
+            ),
+            (
+                "semi-open file",
+                "6k1/5ppp/8/8/8/8/5P1P/6K1 w - - 0 1",
+                shield_pawn_close * 2 + missing_shield_pawn + semi_open_file,
+            ),
+        ];
+
+        for (name, fen, expected) in parameters {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(
+                player_king_safety(&eval_parameters, &board, Player::White),
+                expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_only_evaluate_pawn_shield_on_the_wings() {
+        // The pawns in front of the king are missing, but the files aren't open
+        let board = Board::from_fen("4k3/ppp2ppp/3pp3/8/8/3PP3/PPP2PPP/4K3 w - - 0 1").unwrap();
+
+        assert_eq!(
+            player_king_safety(&EvalParameters::default(), &board, Player::White),
+            TaperedScore::default()
+        );
+    }
+
+    #[test]
+    fn should_penalize_pawn_storm() {
+        let board = Board::from_fen("6k1/5p1p/8/8/8/6p1/5P1P/6K1 w - - 0 1").unwrap();
+        let eval_parameters = EvalParameters::default();
+
+        assert_eq!(
+            player_king_safety(&eval_parameters, &board, Player::White),
+            eval_parameters.shield_pawn_close * 2
+                + eval_parameters.missing_shield_pawn
+                + eval_parameters.semi_open_file
+                + eval_parameters.storm_pawn[2]
+        );
+    }
+
+    #[test]
+    fn should_penalize_attacks_on_the_king() {
+        // The queen and the knight attack the castled king
+        let attacked =
+            Board::from_fen("r1b2rk1/ppp2ppp/8/8/6n1/7q/PPP2PPP/RNBQ1RK1 w - - 0 1").unwrap();
+        let defended =
+            Board::from_fen("r1b2rk1/ppp2ppp/8/8/8/8/PPP2PPP/RNBQ1RK1 w - - 0 1").unwrap();
+
+        let eval_parameters = EvalParameters::default();
+
+        assert!(
+            king_safety_value(&eval_parameters, &attacked)
+                < king_safety_value(&eval_parameters, &defended)
+        );
+    }
+}
@@ -1,6 +1,10 @@
 use pleco::{BitMove, Board, PieceType, Player};
 
-/// Get the value of the given piece.
+use super::parameters::EvalParameters;
+
+/// Get the value of the given piece to evaluate exchanges.
+///
+/// The material evaluation uses the piece values of the [`EvalParameters`] instead.
 pub fn get_piece_value(piece: PieceType) -> i32 {
     match piece {
         PieceType::P => 100,
@@ -12,7 +16,7 @@ pub fn get_piece_value(piece: PieceType) -> i32 {
 }
 
 /// The material value for the given player in centipawns.
-fn player_material_value(board: &Board, player: Player) -> i32 {
+fn player_material_value(parameters: &EvalParameters, board: &Board, player: Player) -> i32 {
     [
         PieceType::P,
         PieceType::N,
@@ -21,32 +25,35 @@ fn player_material_value(board: &Board, player: Player) -> i32 {
         PieceType::Q,
     ]
     .into_iter()
-    .map(|piece| board.count_piece(player, piece) as i32 * get_piece_value(piece))
+    .map(|piece| board.count_piece(player, piece) as i32 * parameters.piece_value(piece))
     .sum()
 }
 
 /// The material value from the view of the current player.
 ///
 /// Returns a positive value for a material advantage.
-pub fn material_value(board: &Board) -> i32 {
-    let player_mat = player_material_value(board, board.turn());
-    let opponent_mat = player_material_value(board, board.turn().other_player());
+pub fn material_value(parameters: &EvalParameters, board: &Board) -> i32 {
+    let player_mat = player_material_value(parameters, board, board.turn());
+    let opponent_mat = player_material_value(parameters, board, board.turn().other_player());
 
     player_mat - opponent_mat
 }
 
 /// The change in material value of the given move.
-pub fn material_move_delta(old_board: &Board, mv: BitMove) -> i32 {
+pub fn material_move_delta(parameters: &EvalParameters, old_board: &Board, mv: BitMove) -> i32 {
     let mut value = 0;
 
-    if mv.is_capture() {
+    if mv.is_en_passant() {
+        // The captured pawn is not on the destination square
+        value += parameters.piece_value(PieceType::P)
+    } else if mv.is_capture() {
         // We gain the captured piece
         let captured_piece = old_board.piece_at_sq(mv.get_dest()).type_of();
-        value += get_piece_value(captured_piece)
+        value += parameters.piece_value(captured_piece)
     }
     if mv.is_promo() {
         // We gain the promotion piece and lose a pawn
-        value += get_piece_value(mv.promo_piece()) - get_piece_value(PieceType::P)
+        value += parameters.piece_value(mv.promo_piece()) - parameters.piece_value(PieceType::P)
     }
 
     value
@@ -1,38 +1,79 @@
 use pleco::{BitMove, Board};
 
 use self::{
-    material_value::material_move_delta,
-    positional_value::{move_positional_value, threat_value},
+    king_safety::king_safety_value,
+    material_value::{material_move_delta, material_value},
+    pawn_structure::{move_pawn_structure_value, pawn_structure_value},
+    piece_activity::piece_activity_value,
+    positional_value::{initial_positional_value, move_positional_value, threat_value},
 };
 
 use super::evaluation::Evaluation;
 
+mod bitboards;
+mod king_safety;
 mod material_value;
+mod parameters;
+mod pawn_structure;
+mod piece_activity;
+mod piece_square_tables;
 mod positional_value;
+mod static_exchange;
+mod tapered_score;
+
+pub use self::{
+    parameters::{EvalParameters, EvalParametersError},
+    pawn_structure::PawnHashTable,
+    positional_value::{move_piece_square_delta, piece_square_score},
+    static_exchange::static_exchange_evaluation,
+    tapered_score::TaperedScore,
+};
 
 /// The initial heuristic value of a position.
-pub fn initial_heuristic(board: &Board) -> Evaluation {
+pub fn initial_heuristic(parameters: &EvalParameters, board: &Board) -> Evaluation {
     if board.checkmate() {
         // The player got checkmated, it's a win for the opponent
         Evaluation::OpponentCheckmate(0)
     } else {
-        let mat_value = material_value::material_value(board);
-        let pos_value = positional_value::initial_positional_value(board);
-        let value = mat_value + pos_value;
-
-        Evaluation::Centipawns(value)
+        Evaluation::Centipawns(incremental_value(parameters, board))
     }
 }
 
+/// The part of the evaluation that is updated incrementally for every move.
+fn incremental_value(parameters: &EvalParameters, board: &Board) -> i32 {
+    let mat_value = material_value(parameters, board);
+    let pos_value = initial_positional_value(parameters, board);
+    let pawn_value = pawn_structure_value(parameters, board);
+
+    mat_value + pos_value + pawn_value
+}
+
+/// The static evaluation of a quiet position with the given parameters, in centipawns.
+///
+/// This is the same evaluation as [`static_heuristic`], without the need for a search.
+/// Returns a positive number if the current player is better.
+pub fn evaluate(parameters: &EvalParameters, board: &Board) -> i32 {
+    incremental_value(parameters, board)
+        + king_safety_value(parameters, board)
+        + piece_activity_value(parameters, board)
+}
+
 /// The rough heuristic evaluation for a given move, used for move ordering.
+///
+/// The board is the position before the move has been applied,
+/// the piece squares are the sum of its piece positions, see [`piece_square_score`].
+/// The returned evaluation is from the view of the opponent.
 pub fn move_heuristic(
+    parameters: &EvalParameters,
+    pawn_table: &mut PawnHashTable,
+    piece_squares: TaperedScore,
     old_eval: Evaluation,
     old_board: &Board,
     mv: BitMove,
-    new_board: &Board,
 ) -> Evaluation {
-    let delta =
-        move_positional_value(old_board, mv, new_board) + material_move_delta(old_board, mv);
+    let delta = move_positional_value(parameters, old_board, piece_squares, mv)
+        + material_move_delta(parameters, old_board, mv)
+        + move_pawn_structure_value(parameters, pawn_table, old_board, mv);
 
     let new_eval = match old_eval {
         Evaluation::Centipawns(old_val) => Evaluation::Centipawns(old_val + delta),
@@ -42,6 +83,23 @@ pub fn move_heuristic(
     new_eval.for_opponent()
 }
 
+/// The static evaluation of a quiet position, e.g. to stand pat in the quiescence search.
+///
+/// Adds the terms that depend on the attacks of all pieces to the incremental evaluation,
+/// they are too expensive to update for every move.
+pub fn static_heuristic(
+    parameters: &EvalParameters,
+    evaluation: Evaluation,
+    board: &Board,
+) -> Evaluation {
+    match evaluation {
+        Evaluation::Centipawns(value) => Evaluation::Centipawns(
+            value + king_safety_value(parameters, board) + piece_activity_value(parameters, board),
+        ),
+        _ => evaluation,
+    }
+}
+
 /// Determine the heuristic value of the final position.
 pub fn final_heuristic(old_eval: Evaluation, board: &Board) -> Evaluation {
     // First check if the board is in a final state
@@ -65,31 +123,55 @@ mod tests {
 
     use crate::stonefish::{
         evaluation::Evaluation,
-        heuristic::{final_heuristic, initial_heuristic, move_heuristic},
+        heuristic::{
+            evaluate, final_heuristic, initial_heuristic, move_heuristic, piece_square_score,
+            static_heuristic, EvalParameters, PawnHashTable,
+        },
         node::Node,
-        types::HashTable,
+        transposition_table::TranspositionTable,
     };
 
     #[test]
     fn should_evaluate_start_position() {
+        let eval_parameters = EvalParameters::default();
         let board = Board::start_pos();
         let expected = Evaluation::Centipawns(0);
-        let actual = initial_heuristic(&board);
+        let actual = initial_heuristic(&eval_parameters, &board);
 
         assert_eq!(actual, expected);
     }
 
     #[test]
     fn should_evaluate_checkmate() {
+        let eval_parameters = EvalParameters::default();
         let board = Board::from_fen("k1R5/8/1K6/8/8/8/8/8 b - - 1 1").unwrap();
         let expected = Evaluation::OpponentCheckmate(0);
-        let actual = initial_heuristic(&board);
+        let actual = initial_heuristic(&eval_parameters, &board);
 
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn should_evaluate_like_static_heuristic() {
+        let eval_parameters = EvalParameters::default();
+        let board =
+            Board::from_fen("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R w KQ - 0 8")
+                .unwrap();
+
+        assert_eq!(
+            Evaluation::Centipawns(evaluate(&eval_parameters, &board)),
+            static_heuristic(
+                &eval_parameters,
+                initial_heuristic(&eval_parameters, &board),
+                &board
+            )
+        );
+    }
+
     #[test]
     fn should_properly_update_heuristic() {
+        let eval_parameters = EvalParameters::default();
+        let mut pawn_table = PawnHashTable::new();
         let fens = [
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
             // TODO: Fix bug with promotion capture
@@ -105,15 +187,24 @@ mod tests {
             "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R w - - 0 1",
             // Castling black
             "r3k2r/pppppppp/8/8/8/8/PPPPPPPP/R3K2R b - - 0 1",
+            // Pawn structure with en passant
+            "4k3/1p6/8/2pP4/8/8/PP3P2/4K3 w - c6 0 2",
+            // Passed pawns with promotions
+            "r3k2r/1P4p1/8/3p4/8/8/1p4P1/R3K2R w KQkq - 0 1",
         ];
 
         for fen in fens {
             let mut node = Node::new(Board::from_fen(fen).unwrap());
             let parent_heuristic = node.evaluation;
-            let children = node.expand(&HashTable::new());
+            let children = node.expand(
+                &eval_parameters,
+                &mut pawn_table,
+                &TranspositionTable::new(1),
+            );
 
             for child in children {
-                let initial_heuristic = initial_heuristic(&child.board).for_opponent();
+                let initial_heuristic =
+                    initial_heuristic(&eval_parameters, &child.board).for_opponent();
                 let incremental_heuristic = child.evaluation.for_opponent();
 
                 assert_eq!(
@@ -130,6 +221,8 @@ mod tests {
 
     #[test]
     fn should_properly_update_heuristic_for_move_sequences() {
+        let eval_parameters = EvalParameters::default();
+        let mut pawn_table = PawnHashTable::new();
         let parameters = [(
             "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
             ["e2e3", "d7d5", "d1h5", "g8h6", "h5g5", "g7g6", "g5e5"],
@@ -137,14 +230,22 @@ mod tests {
 
         for (fen, moves) in parameters {
             let mut cur_board = Board::from_fen(fen).unwrap();
-            let mut cur_eval = initial_heuristic(&cur_board);
+            let mut cur_eval = initial_heuristic(&eval_parameters, &cur_board);
 
             for uci_move in moves {
                 let mut new_board = cur_board.clone();
                 assert!(new_board.apply_uci_move(uci_move));
                 let mv = new_board.last_move().unwrap();
-                cur_eval = move_heuristic(cur_eval, &cur_board, mv, &new_board);
-                let fresh_eval = initial_heuristic(&new_board);
+                let piece_squares = piece_square_score(&eval_parameters, &cur_board);
+                cur_eval = move_heuristic(
+                    &eval_parameters,
+                    &mut pawn_table,
+                    piece_squares,
+                    cur_eval,
+                    &cur_board,
+                    mv,
+                );
+                let fresh_eval = initial_heuristic(&eval_parameters, &new_board);
 
                 assert_eq!(cur_eval, fresh_eval, "{fen} after {uci_move}");
                 cur_board = new_board;
@@ -154,6 +255,7 @@ mod tests {
 
     #[test]
     fn should_prefer_good_openings() {
+        let eval_parameters = EvalParameters::default();
         // The left side is the better opening, the right side the worse one
         let parameters = [
             (
@@ -192,8 +294,8 @@ mod tests {
             let board_better = Board::from_fen(fen_better).unwrap();
             let board_worse = Board::from_fen(fen_worse).unwrap();
 
-            let initial_eval_better = initial_heuristic(&board_better);
-            let initial_eval_worse = initial_heuristic(&board_worse);
+            let initial_eval_better = initial_heuristic(&eval_parameters, &board_better);
+            let initial_eval_worse = initial_heuristic(&eval_parameters, &board_worse);
 
             assert!(
                 initial_eval_better > initial_eval_worse,
@@ -0,0 +1,486 @@
+//! The weights of the evaluation.
+//!
+//! All weights are collected in [`EvalParameters`], so that they can be loaded from a file,
+//! e.g. after fitting them to a set of games with the `tune` binary.
+
+use std::{
+    fmt::Display,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+};
+
+use pleco::PieceType;
+
+use super::{piece_square_tables::PieceSquareTables, tapered_score::TaperedScore};
+
+/// The default parameters, compiled into the engine.
+const DEFAULT_PARAMETERS: &str = include_str!("eval_parameters.txt");
+
+/// The names of the middlegame and endgame piece-square tables, indexed like the tables.
+const TABLE_NAMES: [(&str, &str); 6] = [
+    ("pawn_mg", "pawn_eg"),
+    ("knight_mg", "knight_eg"),
+    ("bishop_mg", "bishop_eg"),
+    ("rook_mg", "rook_eg"),
+    ("queen_mg", "queen_eg"),
+    ("king_mg", "king_eg"),
+];
+
+/// The parsed default parameters, they only have to be parsed once.
+static DEFAULT: OnceLock<EvalParameters> = OnceLock::new();
+
+/// The ID of the next set of weights.
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// An error while parsing evaluation parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvalParametersError {
+    /// The file could not be read.
+    Io(String),
+    /// The name doesn't belong to any parameter.
+    UnknownParameter(String),
+    /// A value is not a number.
+    InvalidValue(String),
+    /// The parameter doesn't have the given number of values.
+    WrongValueCount(String, usize),
+    /// The parameter has not been defined.
+    MissingParameter(String),
+}
+
+impl Display for EvalParametersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "could not read the file: {error}"),
+            Self::UnknownParameter(name) => write!(f, "unknown parameter '{name}'"),
+            Self::InvalidValue(value) => write!(f, "'{value}' is not a valid value"),
+            Self::WrongValueCount(name, count) => {
+                write!(f, "the parameter '{name}' needs {count} values")
+            }
+            Self::MissingParameter(name) => write!(f, "the parameter '{name}' is missing"),
+        }
+    }
+}
+
+/// A new ID for a set of weights.
+fn next_id() -> u64 {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The middlegame and endgame values of the scores, in this order.
+fn score_weights<'a>(scores: impl IntoIterator<Item = &'a mut TaperedScore>) -> Vec<&'a mut i32> {
+    scores
+        .into_iter()
+        .flat_map(|TaperedScore { mg, eg }| [mg, eg])
+        .collect()
+}
+
+/// The weights of all terms of the evaluation.
+///
+/// The weights can only be changed as a whole, so that cached evaluations can be invalidated.
+#[derive(Debug, Clone)]
+pub struct EvalParameters {
+    /// Identifies the weights, it changes whenever the weights change.
+    id: u64,
+    /// The values of the pawn, knight, bishop, rook and queen.
+    pub(super) piece_values: [i32; 5],
+    /// The positional values of the pieces.
+    pub(super) piece_square_tables: PieceSquareTables,
+    /// Penalty for every pawn behind another pawn of the same player on the same file.
+    pub(super) doubled_pawn: TaperedScore,
+    /// Penalty for a pawn without pawns of the same player on the adjacent files.
+    pub(super) isolated_pawn: TaperedScore,
+    /// Penalty for a pawn that can't be supported by other pawns and can't advance safely.
+    pub(super) backward_pawn: TaperedScore,
+    /// Bonus for a pawn that is defended by a pawn or stands next to one, indexed by its relative rank.
+    pub(super) connected_pawn: [TaperedScore; 8],
+    /// Bonus for a pawn that can't be stopped by opposing pawns, indexed by its relative rank.
+    pub(super) passed_pawn: [TaperedScore; 8],
+    /// Additional bonus for a passed pawn with no pieces in front of it, indexed by its relative rank.
+    pub(super) free_passed_pawn: [TaperedScore; 8],
+    /// Bonus for a pawn of the shield directly in front of the king.
+    pub(super) shield_pawn_close: TaperedScore,
+    /// Bonus for a pawn of the shield two squares in front of the king.
+    pub(super) shield_pawn_far: TaperedScore,
+    /// Penalty for a file next to the king without a shield pawn.
+    pub(super) missing_shield_pawn: TaperedScore,
+    /// Penalty for an opposing pawn storming towards the king, indexed by its distance to the king.
+    pub(super) storm_pawn: [TaperedScore; 4],
+    /// Penalty for a file next to the king without pawns of the player.
+    pub(super) semi_open_file: TaperedScore,
+    /// Penalty for a file next to the king without any pawns.
+    pub(super) open_file: TaperedScore,
+    /// The attack units of a knight, bishop, rook and queen for every attacked square of the king zone.
+    pub(super) king_attack_weights: [i32; 4],
+    /// The maximum penalty for the attacks on the king zone, in centipawns.
+    pub(super) max_king_danger: i32,
+    /// The score for every square a knight, bishop, rook and queen can move to.
+    pub(super) mobility: [TaperedScore; 4],
+    /// Bonus for a knight on a square that can't be attacked by opposing pawns and is defended by a pawn.
+    pub(super) knight_outpost: TaperedScore,
+    /// Bonus for a bishop on a square that can't be attacked by opposing pawns and is defended by a pawn.
+    pub(super) bishop_outpost: TaperedScore,
+    /// Bonus for a rook on a file without pawns.
+    pub(super) rook_open_file: TaperedScore,
+    /// Bonus for a rook on a file without pawns of the player.
+    pub(super) rook_semi_open_file: TaperedScore,
+    /// Bonus for having both bishops.
+    pub(super) bishop_pair: TaperedScore,
+    /// Penalty for a bishop that is locked in by opposing pawns.
+    pub(super) trapped_bishop: TaperedScore,
+    /// Penalty for a rook that is locked in by the king.
+    pub(super) trapped_rook: TaperedScore,
+}
+
+impl EvalParameters {
+    /// Parameters where every weight is zero.
+    fn zero() -> Self {
+        let zero = TaperedScore::default();
+
+        Self {
+            id: next_id(),
+            piece_values: [0; 5],
+            piece_square_tables: PieceSquareTables::empty(),
+            doubled_pawn: zero,
+            isolated_pawn: zero,
+            backward_pawn: zero,
+            connected_pawn: [zero; 8],
+            passed_pawn: [zero; 8],
+            free_passed_pawn: [zero; 8],
+            shield_pawn_close: zero,
+            shield_pawn_far: zero,
+            missing_shield_pawn: zero,
+            storm_pawn: [zero; 4],
+            semi_open_file: zero,
+            open_file: zero,
+            king_attack_weights: [0; 4],
+            max_king_danger: 0,
+            mobility: [zero; 4],
+            knight_outpost: zero,
+            bishop_outpost: zero,
+            rook_open_file: zero,
+            rook_semi_open_file: zero,
+            bishop_pair: zero,
+            trapped_bishop: zero,
+            trapped_rook: zero,
+        }
+    }
+
+    /// Call the function with the name and the weights of every parameter, in the order of the text format.
+    fn visit_weights<'a>(&'a mut self, mut visit: impl FnMut(&'static str, Vec<&'a mut i32>)) {
+        let Self {
+            id: _,
+            piece_values,
+            piece_square_tables,
+            doubled_pawn,
+            isolated_pawn,
+            backward_pawn,
+            connected_pawn,
+            passed_pawn,
+            free_passed_pawn,
+            shield_pawn_close,
+            shield_pawn_far,
+            missing_shield_pawn,
+            storm_pawn,
+            semi_open_file,
+            open_file,
+            king_attack_weights,
+            max_king_danger,
+            mobility,
+            knight_outpost,
+            bishop_outpost,
+            rook_open_file,
+            rook_semi_open_file,
+            bishop_pair,
+            trapped_bishop,
+            trapped_rook,
+        } = self;
+
+        visit("piece_values", piece_values.iter_mut().collect());
+
+        for (table, (mg_name, eg_name)) in piece_square_tables.tables.iter_mut().zip(TABLE_NAMES) {
+            let mut mg_weights = Vec::with_capacity(64);
+            let mut eg_weights = Vec::with_capacity(64);
+
+            // The text starts at the 8th rank, the squares at the 1st rank
+            for score in table.chunks_mut(8).rev().flatten() {
+                mg_weights.push(&mut score.mg);
+                eg_weights.push(&mut score.eg);
+            }
+
+            visit(mg_name, mg_weights);
+            visit(eg_name, eg_weights);
+        }
+
+        visit("doubled_pawn", score_weights([doubled_pawn]));
+        visit("isolated_pawn", score_weights([isolated_pawn]));
+        visit("backward_pawn", score_weights([backward_pawn]));
+        visit("connected_pawn", score_weights(connected_pawn));
+        visit("passed_pawn", score_weights(passed_pawn));
+        visit("free_passed_pawn", score_weights(free_passed_pawn));
+
+        visit("shield_pawn_close", score_weights([shield_pawn_close]));
+        visit("shield_pawn_far", score_weights([shield_pawn_far]));
+        visit("missing_shield_pawn", score_weights([missing_shield_pawn]));
+        visit("storm_pawn", score_weights(storm_pawn));
+        visit("semi_open_file", score_weights([semi_open_file]));
+        visit("open_file", score_weights([open_file]));
+        visit(
+            "king_attack_weights",
+            king_attack_weights.iter_mut().collect(),
+        );
+        visit("max_king_danger", vec![max_king_danger]);
+
+        visit("mobility", score_weights(mobility));
+        visit("knight_outpost", score_weights([knight_outpost]));
+        visit("bishop_outpost", score_weights([bishop_outpost]));
+        visit("rook_open_file", score_weights([rook_open_file]));
+        visit("rook_semi_open_file", score_weights([rook_semi_open_file]));
+        visit("bishop_pair", score_weights([bishop_pair]));
+        visit("trapped_bishop", score_weights([trapped_bishop]));
+        visit("trapped_rook", score_weights([trapped_rook]));
+    }
+
+    /// Parse the parameters from their text format.
+    ///
+    /// Every parameter consists of its name followed by its values,
+    /// blended scores have a middlegame and an endgame value.
+    /// Later definitions replace earlier ones and lines starting with `#` are comments.
+    pub fn parse(text: &str) -> Result<Self, EvalParametersError> {
+        let mut definitions: Vec<(&str, Vec<i32>)> = Vec::new();
+
+        for token in text
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .flat_map(str::split_whitespace)
+        {
+            if !token.starts_with(|c: char| c == '-' || c.is_ascii_digit()) {
+                definitions.push((token, Vec::new()));
+                continue;
+            }
+
+            let value = token
+                .parse::<i32>()
+                .map_err(|_| EvalParametersError::InvalidValue(token.to_string()))?;
+
+            match definitions.last_mut() {
+                Some((_, values)) => values.push(value),
+                None => return Err(EvalParametersError::InvalidValue(token.to_string())),
+            }
+        }
+
+        let mut parameters = Self::zero();
+        let mut names = Vec::new();
+        parameters.visit_weights(|name, _| names.push(name));
+
+        if let Some((name, _)) = definitions.iter().find(|(name, _)| !names.contains(name)) {
+            return Err(EvalParametersError::UnknownParameter(name.to_string()));
+        }
+
+        let mut error = None;
+
+        parameters.visit_weights(|name, weights| {
+            let Some((_, values)) = definitions
+                .iter()
+                .rev()
+                .find(|(def_name, _)| *def_name == name)
+            else {
+                error.get_or_insert(EvalParametersError::MissingParameter(name.to_string()));
+                return;
+            };
+
+            if values.len() != weights.len() {
+                error.get_or_insert(EvalParametersError::WrongValueCount(
+                    name.to_string(),
+                    weights.len(),
+                ));
+                return;
+            }
+
+            for (weight, value) in weights.into_iter().zip(values) {
+                *weight = *value;
+            }
+        });
+
+        match error {
+            Some(error) => Err(error),
+            None => Ok(parameters),
+        }
+    }
+
+    /// Load the parameters from a file in the text format.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, EvalParametersError> {
+        let text =
+            fs::read_to_string(path).map_err(|error| EvalParametersError::Io(error.to_string()))?;
+
+        Self::parse(&text)
+    }
+
+    /// All weights in the order of the text format.
+    pub fn weights(&self) -> Vec<i32> {
+        let mut parameters = self.clone();
+        let mut weights = Vec::new();
+
+        parameters
+            .visit_weights(|_, group| weights.extend(group.into_iter().map(|weight| *weight)));
+        weights
+    }
+
+    /// Replace the weights, in the order of the text format.
+    ///
+    /// Missing weights keep their value.
+    pub fn set_weights(&mut self, weights: &[i32]) {
+        let mut values = weights.iter();
+
+        self.visit_weights(|_, group| {
+            for (weight, value) in group.into_iter().zip(values.by_ref()) {
+                *weight = *value;
+            }
+        });
+
+        self.id = next_id();
+    }
+
+    /// Identifies the weights, two parameters with the same ID have the same weights.
+    pub(super) fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The material value of the piece.
+    pub(super) fn piece_value(&self, piece: PieceType) -> i32 {
+        match piece {
+            PieceType::P => self.piece_values[0],
+            PieceType::N => self.piece_values[1],
+            PieceType::B => self.piece_values[2],
+            PieceType::R => self.piece_values[3],
+            PieceType::Q => self.piece_values[4],
+            _ => 0,
+        }
+    }
+}
+
+impl Default for EvalParameters {
+    fn default() -> Self {
+        DEFAULT
+            .get_or_init(|| {
+                Self::parse(DEFAULT_PARAMETERS)
+                    .expect("The default evaluation parameters must be valid")
+            })
+            .clone()
+    }
+}
+
+impl Display for EvalParameters {
+    /// Write the parameters in their text format.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parameters = self.clone();
+        let mut text = String::new();
+
+        parameters.visit_weights(|name, weights| {
+            let values: Vec<String> = weights.iter().map(|weight| weight.to_string()).collect();
+
+            if values.len() == 64 {
+                // Piece-square tables are written as a board
+                text.push_str(&format!("\n{name}\n"));
+
+                for rank in values.chunks(8) {
+                    text.push_str(&format!("{}\n", rank.join(" ")));
+                }
+
+                text.push('\n');
+            } else {
+                text.push_str(&format!("{name} {}\n", values.join(" ")));
+            }
+        });
+
+        write!(f, "{}", text.trim_start())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::{PieceType, Player, SQ};
+
+    use super::{EvalParameters, EvalParametersError, DEFAULT_PARAMETERS};
+
+    #[test]
+    fn should_write_and_parse_parameters() {
+        let parameters = EvalParameters::default();
+        let parsed = EvalParameters::parse(&parameters.to_string()).unwrap();
+
+        assert_eq!(parsed.weights(), parameters.weights());
+        assert_eq!(parsed.piece_value(PieceType::R), 500);
+        assert_eq!(parsed.piece_square_tables, parameters.piece_square_tables);
+    }
+
+    #[test]
+    fn should_parse_parameters_with_comments() {
+        // Later definitions replace earlier ones, only the pawn on a2 has a value
+        let text = format!(
+            "{DEFAULT_PARAMETERS}\n# Pawns\npawn_mg\n{}\n# 2nd rank\n42 {}",
+            "0 ".repeat(48),
+            "0 ".repeat(15)
+        );
+        let tables = EvalParameters::parse(&text).unwrap().piece_square_tables;
+
+        assert_eq!(tables.value(PieceType::P, Player::White, SQ::A2).mg, 42);
+        assert_eq!(tables.value(PieceType::P, Player::Black, SQ::A7).mg, 42);
+        assert_eq!(tables.value(PieceType::P, Player::White, SQ::E4).mg, 0);
+    }
+
+    #[test]
+    fn should_reject_invalid_parameters() {
+        let parameters = [
+            (
+                "unknown parameter",
+                format!("{DEFAULT_PARAMETERS}\nelephant_mg 0"),
+                EvalParametersError::UnknownParameter("elephant_mg".to_string()),
+            ),
+            (
+                "invalid value",
+                format!("{DEFAULT_PARAMETERS}\nbishop_pair 0 1x"),
+                EvalParametersError::InvalidValue("1x".to_string()),
+            ),
+            (
+                "value without parameter",
+                "42".to_string(),
+                EvalParametersError::InvalidValue("42".to_string()),
+            ),
+            (
+                "wrong value count",
+                format!("{DEFAULT_PARAMETERS}\nbishop_pair 0 1 2"),
+                EvalParametersError::WrongValueCount("bishop_pair".to_string(), 2),
+            ),
+            (
+                "missing parameter",
+                String::new(),
+                EvalParametersError::MissingParameter("piece_values".to_string()),
+            ),
+        ];
+
+        for (name, text, expected) in parameters {
+            assert_eq!(
+                EvalParameters::parse(&text).map(|parameters| parameters.weights()),
+                Err(expected),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_set_weights() {
+        let mut parameters = EvalParameters::default();
+        let id = parameters.id();
+        let mut weights = parameters.weights();
+        weights[0] = 90;
+
+        parameters.set_weights(&weights);
+
+        assert_eq!(parameters.piece_value(PieceType::P), 90);
+        assert_eq!(parameters.weights(), weights);
+        assert_ne!(parameters.id(), id);
+    }
+}
@@ -0,0 +1,427 @@
+//! Evaluation of the pawn structure.
+//!
+//! See <https://www.chessprogramming.org/Pawn_Structure>.
+
+use pleco::{BitBoard, BitMove, Board, PieceType, Player, SQ};
+
+use super::{
+    bitboards::{adjacent_files_bb, file_bb, forward_ranks_bb, pawn_attacks_bb, relative_rank},
+    parameters::EvalParameters,
+    tapered_score::{game_phase, game_phase_after_move, TaperedScore},
+};
+
+/// The number of entries in the pawn hash table of every thread.
+const PAWN_HASH_ENTRIES: usize = 1 << 14;
+
+/// Random numbers for the Zobrist key of every pawn, indexed by player and square.
+const PAWN_ZOBRIST: [[u64; 64]; 2] = pawn_zobrist();
+
+/// Generate the random numbers for the pawn keys, with a fixed seed.
+///
+/// See <https://prng.di.unimi.it/splitmix64.c>.
+const fn pawn_zobrist() -> [[u64; 64]; 2] {
+    let mut randoms = [[0; 64]; 2];
+    let mut state: u64 = 0x5354_4f4e_4546_4953;
+    let mut index = 0;
+
+    while index < 128 {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        randoms[index / 64][index % 64] = z ^ (z >> 31);
+        index += 1;
+    }
+
+    randoms
+}
+
+/// The squares in front of the pawn, up to its promotion square.
+fn front_span_bb(sq: SQ, player: Player) -> BitBoard {
+    file_bb(sq) & forward_ranks_bb(sq, player)
+}
+
+/// The cached evaluation of a pawn structure.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct PawnEntry {
+    /// The pawn key of the structure.
+    key: u64,
+    /// The value of the structure, from White's view.
+    score: TaperedScore,
+    /// The passed pawns, indexed by player.
+    passed: [BitBoard; 2],
+}
+
+/// The cached pawn evaluations of a search thread.
+///
+/// The pawns rarely change, so most positions of a search share a few pawn structures.
+#[derive(Debug, Clone)]
+pub struct PawnHashTable {
+    /// The ID of the parameters that the entries have been evaluated with.
+    parameters_id: u64,
+    /// The entries, indexed by the pawn key.
+    entries: Vec<PawnEntry>,
+}
+
+impl PawnHashTable {
+    /// Create a new, empty pawn hash table.
+    pub fn new() -> Self {
+        Self {
+            parameters_id: 0,
+            entries: vec![PawnEntry::default(); PAWN_HASH_ENTRIES],
+        }
+    }
+
+    /// The evaluation of the pawn structure, looked up in the table if possible.
+    fn entry(&mut self, parameters: &EvalParameters, position: &PawnPosition) -> PawnEntry {
+        let key = position.pawn_key();
+
+        // The entries are outdated when the weights change
+        if self.parameters_id != parameters.id() {
+            self.entries.fill(PawnEntry::default());
+            self.parameters_id = parameters.id();
+        }
+
+        let entry = &mut self.entries[key as usize % PAWN_HASH_ENTRIES];
+
+        if entry.key != key || key == 0 {
+            *entry = position.evaluate_pawns(parameters);
+        }
+
+        *entry
+    }
+}
+
+impl Default for PawnHashTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The pawns and the occupied squares of a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PawnPosition {
+    /// The pawns, indexed by player.
+    pawns: [BitBoard; 2],
+    /// The squares occupied by any piece.
+    occupied: BitBoard,
+}
+
+impl PawnPosition {
+    /// The pawns of the board.
+    fn from_board(board: &Board) -> Self {
+        Self {
+            pawns: [
+                board.piece_bb(Player::White, PieceType::P),
+                board.piece_bb(Player::Black, PieceType::P),
+            ],
+            occupied: board.occupied(),
+        }
+    }
+
+    /// The pawns after the move has been applied to the board.
+    fn after_move(board: &Board, mv: BitMove) -> Self {
+        let mut position = Self::from_board(board);
+        let player = board.turn();
+        let opponent = player.other_player();
+        let src = mv.get_src().to_bb();
+        let dest = mv.get_dest().to_bb();
+
+        if mv.is_castle() {
+            // Both the king and the rook change their square
+            let (king_squares, rook_squares) = match (player, mv.is_king_castle()) {
+                (Player::White, true) => ((SQ::E1, SQ::G1), (SQ::H1, SQ::F1)),
+                (Player::White, false) => ((SQ::E1, SQ::C1), (SQ::A1, SQ::D1)),
+                (Player::Black, true) => ((SQ::E8, SQ::G8), (SQ::H8, SQ::F8)),
+                (Player::Black, false) => ((SQ::E8, SQ::C8), (SQ::A8, SQ::D8)),
+            };
+            position.occupied ^= king_squares.0.to_bb() | rook_squares.0.to_bb();
+            position.occupied |= king_squares.1.to_bb() | rook_squares.1.to_bb();
+            return position;
+        }
+
+        if mv.is_en_passant() {
+            // The captured pawn is not on the destination square
+            let captured_sq =
+                SQ((mv.get_src().rank_idx_of_sq() * 8) + mv.get_dest().file_idx_of_sq());
+            position.pawns[opponent as usize] ^= captured_sq.to_bb();
+            position.occupied ^= captured_sq.to_bb();
+        } else if mv.is_capture() {
+            position.pawns[opponent as usize] &= !dest;
+        }
+
+        if board.piece_at_sq(mv.get_src()).type_of() == PieceType::P {
+            position.pawns[player as usize] ^= src;
+
+            if !mv.is_promo() {
+                position.pawns[player as usize] |= dest;
+            }
+        }
+
+        position.occupied ^= src;
+        position.occupied |= dest;
+        position
+    }
+
+    /// The Zobrist key of the pawns, independent of all other pieces.
+    fn pawn_key(&self) -> u64 {
+        [Player::White, Player::Black]
+            .into_iter()
+            .flat_map(|player| self.pawns[player as usize].map(move |sq| (player, sq)))
+            .fold(0, |key, (player, sq)| {
+                key ^ PAWN_ZOBRIST[player as usize][sq.0 as usize]
+            })
+    }
+
+    /// Evaluate the structure of the pawns, without considering the other pieces.
+    fn evaluate_pawns(&self, parameters: &EvalParameters) -> PawnEntry {
+        let mut entry = PawnEntry {
+            key: self.pawn_key(),
+            ..Default::default()
+        };
+
+        for player in [Player::White, Player::Black] {
+            let pawns = self.pawns[player as usize];
+            let opponent_pawns = self.pawns[player.other_player() as usize];
+            let opponent_attacks = pawn_attacks_bb(opponent_pawns, player.other_player());
+            let defended = pawn_attacks_bb(pawns, player);
+            let mut score = TaperedScore::default();
+
+            for sq in pawns {
+                let front_span = front_span_bb(sq, player);
+                let adjacent_files = adjacent_files_bb(sq);
+                let forward_ranks = forward_ranks_bb(sq, player);
+
+                if (front_span & pawns).is_not_empty() {
+                    score += parameters.doubled_pawn;
+                }
+
+                let is_connected = (defended & sq.to_bb()).is_not_empty()
+                    || (adjacent_files & pawns & BitBoard(0xFF << (8 * sq.rank_idx_of_sq())))
+                        .is_not_empty();
+
+                if is_connected {
+                    score += parameters.connected_pawn[relative_rank(sq, player)];
+                }
+
+                if (adjacent_files & pawns).is_empty() {
+                    score += parameters.isolated_pawn;
+                } else if (adjacent_files & pawns & !forward_ranks).is_empty() {
+                    // No pawn can support it, and it's attacked when moving forward
+                    let stop_sq = match player {
+                        Player::White => SQ(sq.0 + 8),
+                        Player::Black => SQ(sq.0 - 8),
+                    };
+
+                    if (opponent_attacks & stop_sq.to_bb()).is_not_empty() {
+                        score += parameters.backward_pawn;
+                    }
+                }
+
+                let stoppers = (file_bb(sq) | adjacent_files) & forward_ranks & opponent_pawns;
+
+                if stoppers.is_empty() {
+                    score += parameters.passed_pawn[relative_rank(sq, player)];
+                    entry.passed[player as usize] |= sq.to_bb();
+                }
+            }
+
+            match player {
+                Player::White => entry.score += score,
+                Player::Black => entry.score = entry.score - score,
+            }
+        }
+
+        entry
+    }
+
+    /// The value of the pawn structure with its cached entry, from White's view.
+    fn score(&self, parameters: &EvalParameters, entry: &PawnEntry) -> TaperedScore {
+        let mut score = entry.score;
+
+        // Free passed pawns depend on all pieces, so they can't be cached
+        for player in [Player::White, Player::Black] {
+            let mut free_score = TaperedScore::default();
+
+            for sq in entry.passed[player as usize] {
+                if (front_span_bb(sq, player) & self.occupied).is_empty() {
+                    free_score += parameters.free_passed_pawn[relative_rank(sq, player)];
+                }
+            }
+
+            match player {
+                Player::White => score += free_score,
+                Player::Black => score = score - free_score,
+            }
+        }
+
+        score
+    }
+
+    /// The value of the pawn structure from the view of the player, blended by the game phase.
+    fn value(
+        &self,
+        parameters: &EvalParameters,
+        entry: &PawnEntry,
+        player: Player,
+        phase: i32,
+    ) -> i32 {
+        let value = self.score(parameters, entry).taper(phase);
+
+        match player {
+            Player::White => value,
+            Player::Black => -value,
+        }
+    }
+}
+
+impl PawnEntry {
+    /// The squares in front of the passed pawns of both players.
+    fn passed_spans_bb(&self) -> BitBoard {
+        [Player::White, Player::Black]
+            .into_iter()
+            .flat_map(|player| self.passed[player as usize].map(move |sq| (player, sq)))
+            .fold(BitBoard(0), |spans, (player, sq)| {
+                spans | front_span_bb(sq, player)
+            })
+    }
+}
+
+/// The current value of the pawn structure.
+///
+/// Returns a positive number if the current player has the better pawn structure.
+pub fn pawn_structure_value(parameters: &EvalParameters, board: &Board) -> i32 {
+    let position = PawnPosition::from_board(board);
+    let entry = position.evaluate_pawns(parameters);
+
+    position.value(parameters, &entry, board.turn(), game_phase(board))
+}
+
+/// The change of the pawn structure value by the given move.
+///
+/// The board is the position before the move has been applied.
+/// The pawn structures are cached in the pawn hash table.
+pub fn move_pawn_structure_value(
+    parameters: &EvalParameters,
+    pawn_table: &mut PawnHashTable,
+    board: &Board,
+    mv: BitMove,
+) -> i32 {
+    let player = board.turn();
+    let phase = game_phase(board);
+    let new_phase = game_phase_after_move(board, mv);
+
+    let old_position = PawnPosition::from_board(board);
+    let new_position = PawnPosition::after_move(board, mv);
+    let old_entry = pawn_table.entry(parameters, &old_position);
+
+    if new_position.pawns == old_position.pawns {
+        let changed_squares = old_position.occupied ^ new_position.occupied;
+
+        // Most moves don't change the pawns, the paths of the passed pawns or the game phase
+        if new_phase == phase && (changed_squares & old_entry.passed_spans_bb()).is_empty() {
+            return 0;
+        }
+
+        return new_position.value(parameters, &old_entry, player, new_phase)
+            - old_position.value(parameters, &old_entry, player, phase);
+    }
+
+    let new_entry = pawn_table.entry(parameters, &new_position);
+
+    new_position.value(parameters, &new_entry, player, new_phase)
+        - old_position.value(parameters, &old_entry, player, phase)
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use super::{PawnHashTable, PawnPosition};
+    use crate::stonefish::heuristic::{parameters::EvalParameters, tapered_score::TaperedScore};
+
+    /// The pawn structure of the position, from White's view.
+    fn pawn_score(fen: &str) -> TaperedScore {
+        let eval_parameters = EvalParameters::default();
+        let position = PawnPosition::from_board(&Board::from_fen(fen).unwrap());
+        position.score(&eval_parameters, &position.evaluate_pawns(&eval_parameters))
+    }
+
+    #[test]
+    fn should_evaluate_pawn_structures() {
+        let EvalParameters {
+            doubled_pawn,
+            isolated_pawn,
+            backward_pawn,
+            connected_pawn,
+            passed_pawn,
+            free_passed_pawn,
+            ..
+        } = EvalParameters::default();
+        let parameters = [
+            (
+                "doubled and isolated pawns",
+                "4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1",
+                doubled_pawn + isolated_pawn * 2 + passed_pawn[1] + passed_pawn[2],
+            ),
+            (
+                "connected pawns against an isolated pawn",
+                "4k3/3p4/8/8/3PP3/8/8/4K3 w - - 0 1",
+                connected_pawn[3] * 2 - isolated_pawn,
+            ),
+            (
+                "backward pawn",
+                "4k3/8/8/8/3p4/1P6/2P5/4K3 w - - 0 1",
+                connected_pawn[2] + backward_pawn + passed_pawn[2] + free_passed_pawn[2]
+                    - isolated_pawn,
+            ),
+            (
+                "blocked passed pawn",
+                "8/8/4k3/4P3/8/8/8/4K3 w - - 0 1",
+                isolated_pawn + passed_pawn[4],
+            ),
+            (
+                "free passed pawn",
+                "8/8/2k5/4P3/8/8/8/4K3 w - - 0 1",
+                isolated_pawn + passed_pawn[4] + free_passed_pawn[4],
+            ),
+        ];
+
+        for (name, fen, expected) in parameters {
+            assert_eq!(pawn_score(fen), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn should_evaluate_mirrored_positions_inversely() {
+        let white = pawn_score("4k3/8/8/8/3p4/1P6/2P5/4K3 w - - 0 1");
+        let black = pawn_score("4k3/2p5/1p6/3P4/8/8/8/4K3 b - - 0 1");
+
+        assert_eq!(white, TaperedScore::default() - black);
+    }
+
+    #[test]
+    fn should_cache_pawn_evaluation() {
+        let board = Board::from_fen("4k3/pp3ppp/8/3p4/3P4/4P3/PP3PPP/4K3 w - - 0 1").unwrap();
+        let position = PawnPosition::from_board(&board);
+        let eval_parameters = EvalParameters::default();
+        let mut pawn_table = PawnHashTable::new();
+
+        let entry = pawn_table.entry(&eval_parameters, &position);
+        // The second lookup is a hit in the pawn hash table
+        assert_eq!(pawn_table.entry(&eval_parameters, &position), entry);
+        assert_eq!(entry, position.evaluate_pawns(&eval_parameters));
+
+        // The cached entry is outdated when the weights change
+        let mut changed_parameters = eval_parameters.clone();
+        let mut weights = changed_parameters.weights();
+        weights.iter_mut().for_each(|weight| *weight *= 2);
+        changed_parameters.set_weights(&weights);
+
+        assert_eq!(
+            pawn_table.entry(&changed_parameters, &position),
+            position.evaluate_pawns(&changed_parameters)
+        );
+        assert_ne!(pawn_table.entry(&changed_parameters, &position), entry);
+    }
+}
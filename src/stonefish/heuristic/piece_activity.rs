@@ -0,0 +1,228 @@
+//! Evaluation of the activity of the pieces.
+//!
+//! See <https://www.chessprogramming.org/Mobility>.
+
+use pleco::{Board, PieceType, Player, SQ};
+
+use super::{
+    bitboards::{
+        adjacent_files_bb, file_bb, forward_ranks_bb, pawn_attacks_bb, piece_attacks, relative_rank,
+    },
+    parameters::EvalParameters,
+    tapered_score::{game_phase, TaperedScore},
+};
+
+/// The maximum mobility of a rook that is considered trapped.
+const TRAPPED_ROOK_MOBILITY: i32 = 3;
+
+/// The squares of a trapped bishop and the pawn trapping it, from White's view.
+const TRAPPED_BISHOP_SQUARES: [(SQ, SQ); 2] = [(SQ::A7, SQ::B6), (SQ::H7, SQ::G6)];
+
+/// The mobility score of a piece that attacks the given number of squares.
+///
+/// The score is relative to the average mobility of the piece.
+fn mobility_score(parameters: &EvalParameters, piece: PieceType, mobility: i32) -> TaperedScore {
+    let (per_square, average) = match piece {
+        PieceType::N => (parameters.mobility[0], 4),
+        PieceType::B => (parameters.mobility[1], 6),
+        PieceType::R => (parameters.mobility[2], 7),
+        PieceType::Q => (parameters.mobility[3], 13),
+        _ => return TaperedScore::default(),
+    };
+
+    per_square * (mobility - average)
+}
+
+/// The square from the player's view, mirrored for Black.
+fn relative_sq(sq: SQ, player: Player) -> SQ {
+    match player {
+        Player::White => sq,
+        Player::Black => SQ(sq.0 ^ 56),
+    }
+}
+
+/// Determine if the rook is locked in by its own king on the first rank.
+fn is_trapped_rook(board: &Board, sq: SQ, player: Player, mobility: i32) -> bool {
+    let king_sq = board.king_sq(player);
+
+    if mobility > TRAPPED_ROOK_MOBILITY
+        || relative_rank(sq, player) != 0
+        || relative_rank(king_sq, player) != 0
+    {
+        return false;
+    }
+
+    let (king_file, rook_file) = (king_sq.file_idx_of_sq(), sq.file_idx_of_sq());
+
+    // The king has moved to the side of the rook without castling
+    ((5..=6).contains(&king_file) && rook_file > king_file)
+        || ((1..=2).contains(&king_file) && rook_file < king_file)
+}
+
+/// Evaluate the activity of the player's pieces.
+fn player_piece_activity(
+    parameters: &EvalParameters,
+    board: &Board,
+    player: Player,
+) -> TaperedScore {
+    let opponent = player.other_player();
+    let occupied = board.occupied();
+    let pawns = board.piece_bb(player, PieceType::P);
+    let opponent_pawns = board.piece_bb(opponent, PieceType::P);
+    let pawn_defended = pawn_attacks_bb(pawns, player);
+
+    // Squares that are occupied by own pieces or controlled by opposing pawns are not useful
+    let mobility_area =
+        !board.get_occupied_player(player) & !pawn_attacks_bb(opponent_pawns, opponent);
+
+    let mut value = TaperedScore::default();
+
+    for piece in [PieceType::N, PieceType::B, PieceType::R, PieceType::Q] {
+        for sq in board.piece_bb(player, piece) {
+            let mobility = (piece_attacks(piece, sq, occupied) & mobility_area).count_bits() as i32;
+            value += mobility_score(parameters, piece, mobility);
+
+            match piece {
+                PieceType::N | PieceType::B => {
+                    let is_outpost = (3..=5).contains(&relative_rank(sq, player))
+                        && (pawn_defended & sq.to_bb()).is_not_empty()
+                        && (adjacent_files_bb(sq) & forward_ranks_bb(sq, player) & opponent_pawns)
+                            .is_empty();
+
+                    if is_outpost {
+                        value += if piece == PieceType::N {
+                            parameters.knight_outpost
+                        } else {
+                            parameters.bishop_outpost
+                        };
+                    }
+                }
+                PieceType::R => {
+                    if (file_bb(sq) & pawns).is_empty() {
+                        value += if (file_bb(sq) & opponent_pawns).is_empty() {
+                            parameters.rook_open_file
+                        } else {
+                            parameters.rook_semi_open_file
+                        };
+                    }
+
+                    if is_trapped_rook(board, sq, player, mobility) {
+                        value += parameters.trapped_rook;
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    let bishops = board.piece_bb(player, PieceType::B);
+
+    if bishops.more_than_one() {
+        value += parameters.bishop_pair;
+    }
+
+    for (bishop_sq, pawn_sq) in TRAPPED_BISHOP_SQUARES {
+        let bishop_sq = relative_sq(bishop_sq, player);
+        let pawn_sq = relative_sq(pawn_sq, player);
+
+        if (bishops & bishop_sq.to_bb()).is_not_empty()
+            && (opponent_pawns & pawn_sq.to_bb()).is_not_empty()
+        {
+            value += parameters.trapped_bishop;
+        }
+    }
+
+    value
+}
+
+/// The value of the piece activity.
+///
+/// Returns a positive number if the pieces of the current player are more active.
+pub fn piece_activity_value(parameters: &EvalParameters, board: &Board) -> i32 {
+    let player = board.turn();
+    let value = player_piece_activity(parameters, board, player)
+        - player_piece_activity(parameters, board, player.other_player());
+
+    value.taper(game_phase(board))
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::{Board, PieceType, Player};
+
+    use crate::stonefish::heuristic::parameters::EvalParameters;
+
+    use super::{mobility_score, piece_activity_value, player_piece_activity};
+
+    #[test]
+    fn should_evaluate_piece_activity() {
+        let eval_parameters = EvalParameters::default();
+        let mobility = |piece, mobility| mobility_score(&eval_parameters, piece, mobility);
+        let parameters = [
+            (
+                "centralized knight",
+                "4k3/8/8/8/3N4/8/8/4K3 w - - 0 1",
+                mobility(PieceType::N, 8),
+            ),
+            (
+                "knight without squares controlled by pawns",
+                "4k3/8/2p1p3/8/3N4/8/8/4K3 w - - 0 1",
+                mobility(PieceType::N, 6),
+            ),
+            (
+                "knight outpost",
+                "4k3/8/8/3N4/2P5/8/8/4K3 w - - 0 1",
+                mobility(PieceType::N, 8) + eval_parameters.knight_outpost,
+            ),
+            (
+                "bishop outpost",
+                "4k3/8/8/3B4/2P5/8/8/4K3 w - - 0 1",
+                mobility(PieceType::B, 10) + eval_parameters.bishop_outpost,
+            ),
+            (
+                "rook on semi-open file",
+                "4k3/p7/8/8/8/8/1P6/R3K3 w - - 0 1",
+                mobility(PieceType::R, 9) + eval_parameters.rook_semi_open_file,
+            ),
+            (
+                "rook on open file",
+                "4k3/8/8/8/8/8/1P6/R3K3 w - - 0 1",
+                mobility(PieceType::R, 10) + eval_parameters.rook_open_file,
+            ),
+            (
+                "bishop pair",
+                "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1",
+                mobility(PieceType::B, 7) * 2 + eval_parameters.bishop_pair,
+            ),
+            (
+                "trapped bishop",
+                "4k3/B7/1pp5/8/8/8/8/4K3 w - - 0 1",
+                mobility(PieceType::B, 2) + eval_parameters.trapped_bishop,
+            ),
+            (
+                "trapped rook",
+                "4k3/8/8/8/8/8/5PPP/5K1R w - - 0 1",
+                mobility(PieceType::R, 1) + eval_parameters.trapped_rook,
+            ),
+        ];
+
+        for (name, fen, expected) in parameters {
+            let board = Board::from_fen(fen).unwrap();
+            assert_eq!(
+                player_piece_activity(&eval_parameters, &board, Player::White),
+                expected,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_evaluate_symmetrical_positions_equally() {
+        let board = Board::from_fen(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/2N2N2/PPPP1PPP/R1BQK2R w KQkq - 4 5",
+        )
+        .unwrap();
+
+        assert_eq!(piece_activity_value(&EvalParameters::default(), &board), 0);
+    }
+}
@@ -0,0 +1,85 @@
+//! Piece-square tables for the positional evaluation.
+//!
+//! See <https://www.chessprogramming.org/Piece-Square_Tables>.
+
+use pleco::{PieceType, Player, SQ};
+
+use super::tapered_score::TaperedScore;
+
+/// The pieces with a table, in the order of their index.
+const PIECES: [PieceType; 6] = [
+    PieceType::P,
+    PieceType::N,
+    PieceType::B,
+    PieceType::R,
+    PieceType::Q,
+    PieceType::K,
+];
+
+/// The index of the table of the piece.
+fn piece_index(piece: PieceType) -> Option<usize> {
+    PIECES.iter().position(|table_piece| *table_piece == piece)
+}
+
+/// The positional values of every piece on every square, for the middlegame and the endgame.
+///
+/// The tables are part of the [`EvalParameters`](super::EvalParameters).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceSquareTables {
+    /// The values indexed by piece and square, from White's view.
+    pub(super) tables: [[TaperedScore; 64]; 6],
+}
+
+impl PieceSquareTables {
+    /// Tables where every piece has no positional value.
+    pub(super) fn empty() -> Self {
+        Self {
+            tables: [[TaperedScore::default(); 64]; 6],
+        }
+    }
+
+    /// The positional value of the player's piece on the given square.
+    ///
+    /// The tables are mirrored for Black.
+    pub fn value(&self, piece: PieceType, player: Player, sq: SQ) -> TaperedScore {
+        let Some(index) = piece_index(piece) else {
+            return TaperedScore::default();
+        };
+
+        let sq = match player {
+            Player::White => sq.0,
+            Player::Black => sq.0 ^ 56,
+        };
+
+        self.tables[index][sq as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::{PieceType, Player, SQ};
+
+    use crate::stonefish::heuristic::{parameters::EvalParameters, tapered_score::TaperedScore};
+
+    #[test]
+    fn should_mirror_tables_for_black() {
+        let tables = EvalParameters::default().piece_square_tables;
+
+        assert_eq!(
+            tables.value(PieceType::K, Player::White, SQ::G1),
+            TaperedScore::new(30, -30)
+        );
+        assert_eq!(
+            tables.value(PieceType::K, Player::Black, SQ::G8),
+            TaperedScore::new(30, -30)
+        );
+        assert_eq!(
+            tables.value(PieceType::P, Player::White, SQ::E4),
+            tables.value(PieceType::P, Player::Black, SQ::E5)
+        );
+        assert_eq!(
+            tables.value(PieceType::N, Player::White, SQ::A1),
+            TaperedScore::new(-50, -50)
+        );
+    }
+}
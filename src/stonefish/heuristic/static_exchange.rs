@@ -0,0 +1,185 @@
+use pleco::{BitBoard, BitMove, Board, PieceType, Player, SQ};
+
+use super::material_value::get_piece_value;
+
+/// The value of the king in an exchange.
+///
+/// It's higher than all other pieces combined, so that the king never captures into an attack.
+const KING_EXCHANGE_VALUE: i32 = 10000;
+
+/// The maximum number of captures in an exchange on a single square.
+const MAX_EXCHANGE_LENGTH: usize = 32;
+
+/// The value of a piece in an exchange.
+fn exchange_value(piece: PieceType) -> i32 {
+    match piece {
+        PieceType::K => KING_EXCHANGE_VALUE,
+        _ => get_piece_value(piece),
+    }
+}
+
+/// Find the least valuable piece of the player among the attackers.
+fn least_valuable_attacker(
+    board: &Board,
+    attackers: BitBoard,
+    player: Player,
+) -> Option<(SQ, PieceType)> {
+    [
+        PieceType::P,
+        PieceType::N,
+        PieceType::B,
+        PieceType::R,
+        PieceType::Q,
+        PieceType::K,
+    ]
+    .into_iter()
+    .find_map(|piece| {
+        let piece_attackers = attackers & board.piece_bb(player, piece);
+
+        if piece_attackers.is_empty() {
+            None
+        } else {
+            Some((piece_attackers.bit_scan_forward(), piece))
+        }
+    })
+}
+
+/// The static exchange evaluation of a capture, in centipawns.
+///
+/// Both players take turns capturing on the destination square with their least valuable piece,
+/// until one of them is better off stopping the exchange.
+/// Pieces behind the capturing pieces join the exchange (x-rays),
+/// pins and checks are not considered.
+///
+/// Returns the material the current player wins with the move, negative values are losses.
+/// See <https://www.chessprogramming.org/Static_Exchange_Evaluation>.
+pub fn static_exchange_evaluation(board: &Board, mv: BitMove) -> i32 {
+    let src = mv.get_src();
+    let dest = mv.get_dest();
+    let mut occupied = board.occupied() ^ src.to_bb();
+
+    // The material each player has won after every capture of the exchange
+    let mut gains = [0; MAX_EXCHANGE_LENGTH];
+
+    if mv.is_en_passant() {
+        // The captured pawn is not on the destination square
+        let captured_sq = SQ((src.rank_idx_of_sq() * 8) + dest.file_idx_of_sq());
+        occupied ^= captured_sq.to_bb();
+        gains[0] = get_piece_value(PieceType::P);
+    } else if mv.is_capture() {
+        gains[0] = get_piece_value(board.piece_at_sq(dest).type_of());
+    }
+
+    // The piece that is currently on the destination square and can be captured next
+    let mut target_value = if mv.is_promo() {
+        gains[0] += get_piece_value(mv.promo_piece()) - get_piece_value(PieceType::P);
+        get_piece_value(mv.promo_piece())
+    } else {
+        exchange_value(board.piece_at_sq(src).type_of())
+    };
+
+    let mut player = board.turn().other_player();
+    let mut length = 1;
+
+    while length < MAX_EXCHANGE_LENGTH {
+        // Removed pieces uncover the sliders behind them
+        let attackers = board.attackers_to(dest, occupied) & occupied;
+
+        let Some((attacker_sq, attacker)) = least_valuable_attacker(board, attackers, player)
+        else {
+            break;
+        };
+
+        // Capture the piece on the square, the opponent can recapture
+        gains[length] = target_value - gains[length - 1];
+        length += 1;
+
+        occupied ^= attacker_sq.to_bb();
+        target_value = exchange_value(attacker);
+        player = player.other_player();
+    }
+
+    // Every player can stop the exchange if continuing would lose material
+    for index in (1..length).rev() {
+        gains[index - 1] = -gains[index].max(-gains[index - 1]);
+    }
+
+    gains[0]
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use super::static_exchange_evaluation;
+
+    /// The static exchange evaluation of the given move in the position.
+    fn evaluate_exchange(fen: &str, uci_move: &str) -> i32 {
+        let board = Board::from_fen(fen).unwrap();
+        let mv = *board
+            .generate_moves()
+            .iter()
+            .find(|mv| mv.stringify() == uci_move)
+            .unwrap();
+
+        static_exchange_evaluation(&board, mv)
+    }
+
+    #[test]
+    fn should_evaluate_exchanges() {
+        let parameters = [
+            (
+                "undefended pawn",
+                "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+                "e1e5",
+                100,
+            ),
+            (
+                "defended pawn with x-ray",
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5",
+                -200,
+            ),
+            (
+                "defended rook",
+                "4k3/8/2r5/8/8/2R5/1B6/4K3 b - - 0 1",
+                "c6c3",
+                0,
+            ),
+            (
+                "knight takes defended pawn",
+                "4k3/8/2p5/3p4/8/4N3/8/4K3 w - - 0 1",
+                "e3d5",
+                -200,
+            ),
+            (
+                "pawn takes defended knight",
+                "4k3/8/2p5/3n4/4P3/8/8/4K3 w - - 0 1",
+                "e4d5",
+                200,
+            ),
+            (
+                "king recaptures",
+                "3qk3/8/8/8/8/8/8/3RK3 b - - 0 1",
+                "d8d1",
+                -300,
+            ),
+            (
+                "king can't recapture defended piece",
+                "3rk3/3q4/8/8/8/8/8/3RK3 b - - 0 1",
+                "d7d1",
+                500,
+            ),
+            (
+                "en passant",
+                "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+                "e5d6",
+                100,
+            ),
+        ];
+
+        for (name, fen, uci_move, expected) in parameters {
+            assert_eq!(evaluate_exchange(fen, uci_move), expected, "{name}");
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! Scores that are blended between the middlegame and the endgame.
+//!
+//! See <https://www.chessprogramming.org/Tapered_Eval>.
+
+use std::ops::{Add, AddAssign, Mul, Sub};
+
+use pleco::{BitMove, Board, PieceType, Player};
+
+/// The game phase with all pieces on the board.
+pub const MAX_PHASE: i32 = 24;
+
+/// The contribution of a piece to the game phase.
+fn piece_phase(piece: PieceType) -> i32 {
+    match piece {
+        PieceType::N | PieceType::B => 1,
+        PieceType::R => 2,
+        PieceType::Q => 4,
+        _ => 0,
+    }
+}
+
+/// The game phase of the remaining material, before it is limited to `MAX_PHASE`.
+///
+/// The phase can exceed the maximum after promotions.
+fn material_phase(board: &Board) -> i32 {
+    [PieceType::N, PieceType::B, PieceType::R, PieceType::Q]
+        .into_iter()
+        .map(|piece| {
+            let count =
+                board.count_piece(Player::White, piece) + board.count_piece(Player::Black, piece);
+            count as i32 * piece_phase(piece)
+        })
+        .sum()
+}
+
+/// The game phase, from 0 with only kings and pawns left to `MAX_PHASE` in the opening.
+pub fn game_phase(board: &Board) -> i32 {
+    material_phase(board).min(MAX_PHASE)
+}
+
+/// The game phase after the given move has been applied to the board.
+pub fn game_phase_after_move(board: &Board, mv: BitMove) -> i32 {
+    let mut phase = material_phase(board);
+
+    if mv.is_capture() {
+        phase -= piece_phase(board.piece_at_sq(mv.get_dest()).type_of());
+    }
+    if mv.is_promo() {
+        phase += piece_phase(mv.promo_piece());
+    }
+
+    phase.min(MAX_PHASE)
+}
+
+/// A score with separate values for the middlegame and the endgame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaperedScore {
+    /// The score while most pieces are still on the board.
+    pub mg: i32,
+    /// The score when only few pieces are left.
+    pub eg: i32,
+}
+
+impl TaperedScore {
+    /// Create a new score from the middlegame and endgame value.
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Self { mg, eg }
+    }
+
+    /// Blend the middlegame and endgame value, depending on the game phase.
+    pub fn taper(&self, phase: i32) -> i32 {
+        (self.mg * phase + self.eg * (MAX_PHASE - phase)) / MAX_PHASE
+    }
+}
+
+impl Add for TaperedScore {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.mg + rhs.mg, self.eg + rhs.eg)
+    }
+}
+
+impl AddAssign for TaperedScore {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for TaperedScore {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.mg - rhs.mg, self.eg - rhs.eg)
+    }
+}
+
+impl Mul<i32> for TaperedScore {
+    type Output = Self;
+
+    fn mul(self, rhs: i32) -> Self::Output {
+        Self::new(self.mg * rhs, self.eg * rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use super::{game_phase, game_phase_after_move, TaperedScore, MAX_PHASE};
+
+    #[test]
+    fn should_calculate_game_phase() {
+        assert_eq!(game_phase(&Board::start_pos()), MAX_PHASE);
+        // Only kings and pawns
+        let board = Board::from_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(game_phase(&board), 0);
+        // Queen and rook against two knights
+        let board = Board::from_fen("1n2k1n1/8/8/8/8/8/8/3QK2R w - - 0 1").unwrap();
+        assert_eq!(game_phase(&board), 8);
+    }
+
+    #[test]
+    fn should_calculate_game_phase_after_move() {
+        // The pawn can promote with or without capturing the knight
+        let board = Board::from_fen("1n2k3/P7/8/8/8/8/8/3QK2R w - - 0 1").unwrap();
+
+        for (uci_move, expected) in [("d1d5", 7), ("a7a8q", 11), ("a7b8q", 10), ("a7b8n", 7)] {
+            let mut after_board = board.clone();
+            assert!(after_board.apply_uci_move(uci_move));
+            let mv = after_board.last_move().unwrap();
+
+            assert_eq!(game_phase_after_move(&board, mv), expected, "{uci_move}");
+            assert_eq!(game_phase(&after_board), expected, "{uci_move}");
+        }
+    }
+
+    #[test]
+    fn should_taper_score() {
+        let score = TaperedScore::new(40, -20);
+
+        assert_eq!(score.taper(MAX_PHASE), 40);
+        assert_eq!(score.taper(0), -20);
+        assert_eq!(score.taper(MAX_PHASE / 2), 10);
+    }
+}
@@ -1,22 +1,51 @@
 mod abort_flags;
+mod book;
+mod draw_rules;
 mod evaluation;
+mod evaluator;
 mod heuristic;
 mod node;
+mod search_limits;
+mod search_options;
+mod tablebase;
 mod time_management;
+mod transposition_table;
 mod types;
 
-use pleco::Board;
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread,
+    time::Duration,
+};
+
+use pleco::{BitMove, Board};
 
 use crate::{
     stonefish::node::Node,
     uci::{
         uci_command::{UciGoConfig, UciPosition},
         uci_option::{UciOption, UciOptionType},
-        AbortFlag, UciEngine,
+        AbortFlag, PonderFlag, UciEngine,
     },
 };
 
-use self::{time_management::get_max_time, types::RepetitionTable};
+pub use self::heuristic::{evaluate, EvalParameters, EvalParametersError};
+
+use self::{
+    book::{random_seed, Book, BookSelection},
+    evaluator::{EvaluatorType, Network},
+    search_limits::SearchLimits,
+    search_options::{
+        SearchOptions, DEFAULT_LATE_MOVE_REDUCTIONS, DEFAULT_MULTI_PV, DEFAULT_NULL_MOVE_PRUNING,
+        DEFAULT_THREADS, MAX_MULTI_PV, MAX_THREADS, MIN_MULTI_PV, MIN_THREADS,
+    },
+    tablebase::Tablebases,
+    time_management::{DEFAULT_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS, MIN_MOVE_OVERHEAD_MS},
+    transposition_table::{
+        TranspositionTable, DEFAULT_HASH_SIZE_MB, MAX_HASH_SIZE_MB, MIN_HASH_SIZE_MB,
+    },
+    types::RepetitionTable,
+};
 
 #[derive(Debug, Clone)]
 pub struct Stonefish {
@@ -24,6 +53,18 @@ pub struct Stonefish {
     board: Board,
     /// Table to track threefold repetion.
     repetition_table: RepetitionTable,
+    /// Table to cache search results, shared between all search threads.
+    hash_table: Arc<TranspositionTable>,
+    /// The options of the search.
+    search_options: SearchOptions,
+    /// The opening book, if a book file has been loaded.
+    book: Option<Arc<Book>>,
+    /// Play the moves of the book instead of searching.
+    own_book: bool,
+    /// How the moves of the book are chosen.
+    book_selection: BookSelection,
+    /// The time reserved for the communication with the GUI.
+    move_overhead: Duration,
 }
 
 impl Stonefish {
@@ -32,6 +73,27 @@ impl Stonefish {
         Stonefish {
             board: Board::start_pos(),
             repetition_table: RepetitionTable::new(),
+            hash_table: Arc::new(TranspositionTable::new(DEFAULT_HASH_SIZE_MB)),
+            search_options: SearchOptions::default(),
+            book: None,
+            own_book: false,
+            book_selection: BookSelection::default(),
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS as u64),
+        }
+    }
+
+    /// Choose a move of the opening book for the current position, if it is in the book.
+    ///
+    /// If the GUI restricted the moves to search, only these moves can be chosen.
+    fn book_move(&self, limits: &SearchLimits) -> Option<BitMove> {
+        let mv =
+            self.book
+                .as_ref()?
+                .choose_move(&self.board, self.book_selection, random_seed())?;
+
+        match &limits.search_moves {
+            Some(search_moves) if !search_moves.contains(&mv) => None,
+            _ => Some(mv),
         }
     }
 
@@ -83,6 +145,12 @@ impl Stonefish {
     }
 }
 
+impl Default for Stonefish {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl UciEngine for Stonefish {
     fn new() -> Self {
         Stonefish::new()
@@ -98,17 +166,204 @@ impl UciEngine for Stonefish {
 
     fn get_options(&self) -> Vec<UciOption> {
         vec![
-            // We don't use this yet, but it's mandatory for some GUIs
-            UciOption::new_with_default("Hash", UciOptionType::Spin, "32"),
+            // The size of the transposition table in MB
+            UciOption::new_spin(
+                "Hash",
+                DEFAULT_HASH_SIZE_MB,
+                MIN_HASH_SIZE_MB,
+                MAX_HASH_SIZE_MB,
+            ),
+            // The number of threads to search with
+            UciOption::new_spin("Threads", DEFAULT_THREADS, MIN_THREADS, MAX_THREADS),
+            // The number of best lines to report
+            UciOption::new_spin("MultiPV", DEFAULT_MULTI_PV, MIN_MULTI_PV, MAX_MULTI_PV),
+            // Switch the selectivity of the search on or off, e.g. to compare its strength
+            UciOption::new_with_default(
+                "NullMovePruning",
+                UciOptionType::Check,
+                &DEFAULT_NULL_MOVE_PRUNING.to_string(),
+            ),
+            UciOption::new_with_default(
+                "LateMoveReductions",
+                UciOptionType::Check,
+                &DEFAULT_LATE_MOVE_REDUCTIONS.to_string(),
+            ),
+            // A file with custom evaluation weights, the built-in weights are used if empty
+            UciOption::new_with_default("EvalParameters", UciOptionType::String, "<empty>"),
+            // The evaluation to use, the neural network needs a network file
+            UciOption::new_combo("Evaluator", EvaluatorType::NAMES[0], &EvaluatorType::NAMES),
+            UciOption::new_with_default("EvalFile", UciOptionType::String, "<empty>"),
+            // The directories with the endgame tablebases, separated like in the PATH variable
+            UciOption::new_with_default("SyzygyPath", UciOptionType::String, "<empty>"),
+            // Play the moves of a Polyglot opening book while the position is in the book
+            UciOption::new_with_default("OwnBook", UciOptionType::Check, "false"),
+            UciOption::new_with_default("BookFile", UciOptionType::String, "<empty>"),
+            UciOption::new_combo(
+                "BookSelection",
+                BookSelection::NAMES[0],
+                &BookSelection::NAMES,
+            ),
+            // The time in ms that is lost when sending the move, e.g. due to network delays
+            UciOption::new_spin(
+                "Move Overhead",
+                DEFAULT_MOVE_OVERHEAD_MS,
+                MIN_MOVE_OVERHEAD_MS,
+                MAX_MOVE_OVERHEAD_MS,
+            ),
+            // Let the GUI know that we can search on the opponent's time
+            UciOption::new_with_default("Ponder", UciOptionType::Check, "true"),
             // We don't change behavior, but we wanna do analysis
             UciOption::new("UCI_AnalyseMode", UciOptionType::Check),
         ]
     }
 
+    fn set_option(&mut self, name: String, value: Option<String>) {
+        // Option names are case insensitive
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                if let Some(size_mb) = value.and_then(|value| value.parse::<usize>().ok()) {
+                    // Allocate a new table with the requested size
+                    let size_mb = size_mb.clamp(MIN_HASH_SIZE_MB, MAX_HASH_SIZE_MB);
+                    self.hash_table = Arc::new(TranspositionTable::new(size_mb));
+                } else {
+                    println!("info string The hash size must be a number of MB.");
+                }
+            }
+            "multipv" => {
+                if let Some(multi_pv) = value.and_then(|value| value.parse::<usize>().ok()) {
+                    self.search_options.multi_pv = multi_pv.clamp(MIN_MULTI_PV, MAX_MULTI_PV);
+                } else {
+                    println!("info string The number of lines must be a number.");
+                }
+            }
+            "threads" => {
+                if let Some(threads) = value.and_then(|value| value.parse::<usize>().ok()) {
+                    self.search_options.threads = threads.clamp(MIN_THREADS, MAX_THREADS);
+                } else {
+                    println!("info string The number of threads must be a number.");
+                }
+            }
+            "move overhead" => {
+                if let Some(overhead_ms) = value.and_then(|value| value.parse::<usize>().ok()) {
+                    let overhead_ms = overhead_ms.clamp(MIN_MOVE_OVERHEAD_MS, MAX_MOVE_OVERHEAD_MS);
+                    self.move_overhead = Duration::from_millis(overhead_ms as u64);
+                } else {
+                    println!("info string The move overhead must be a number of ms.");
+                }
+            }
+            "nullmovepruning" => {
+                if let Some(enabled) = value.and_then(|value| value.parse::<bool>().ok()) {
+                    self.search_options.null_move_pruning = enabled;
+                } else {
+                    println!("info string Null move pruning must be true or false.");
+                }
+            }
+            "latemovereductions" => {
+                if let Some(enabled) = value.and_then(|value| value.parse::<bool>().ok()) {
+                    self.search_options.late_move_reductions = enabled;
+                } else {
+                    println!("info string Late move reductions must be true or false.");
+                }
+            }
+            "evalparameters" => {
+                let path = value.unwrap_or_default();
+
+                let parameters = if path.is_empty() || path == "<empty>" {
+                    Ok(EvalParameters::default())
+                } else {
+                    EvalParameters::from_file(&path)
+                };
+
+                match parameters {
+                    Ok(parameters) => self.search_options.eval_parameters = Arc::new(parameters),
+                    Err(error) => {
+                        println!("info string Invalid evaluation parameters '{path}': {error}.")
+                    }
+                }
+            }
+            "evaluator" => {
+                if let Some(evaluator) = value.and_then(|value| EvaluatorType::from_name(&value)) {
+                    self.search_options.evaluator = evaluator;
+                } else {
+                    println!(
+                        "info string The evaluator must be one of {}.",
+                        EvaluatorType::NAMES.join(", ")
+                    );
+                }
+            }
+            "evalfile" => {
+                let path = value.unwrap_or_default();
+
+                if path.is_empty() || path == "<empty>" {
+                    // Without a network, the handcrafted evaluation is used
+                    self.search_options.network = None;
+                } else {
+                    match Network::from_file(&path) {
+                        Ok(network) => self.search_options.network = Some(Arc::new(network)),
+                        Err(error) => println!("info string Invalid network '{path}': {error}."),
+                    }
+                }
+            }
+            "syzygypath" => {
+                let path = value.unwrap_or_default();
+
+                if path.is_empty() || path == "<empty>" {
+                    self.search_options.tablebases = None;
+                } else {
+                    match Tablebases::open(&path) {
+                        Ok(tablebases) => {
+                            println!("info string Found {} tablebases.", tablebases.len());
+                            self.search_options.tablebases =
+                                (!tablebases.is_empty()).then(|| Arc::new(tablebases));
+                        }
+                        Err(error) => {
+                            println!("info string Invalid Syzygy path: {error}.")
+                        }
+                    }
+                }
+            }
+            "ownbook" => {
+                if let Some(enabled) = value.and_then(|value| value.parse::<bool>().ok()) {
+                    self.own_book = enabled;
+                } else {
+                    println!("info string Own book must be true or false.");
+                }
+            }
+            "bookfile" => {
+                let path = value.unwrap_or_default();
+
+                if path.is_empty() || path == "<empty>" {
+                    self.book = None;
+                } else {
+                    match Book::from_file(&path) {
+                        Ok(book) => {
+                            println!("info string Loaded {} book entries.", book.len());
+                            self.book = (!book.is_empty()).then(|| Arc::new(book));
+                        }
+                        Err(error) => println!("info string Invalid book '{path}': {error}."),
+                    }
+                }
+            }
+            "bookselection" => {
+                if let Some(selection) = value.and_then(|value| BookSelection::from_name(&value)) {
+                    self.book_selection = selection;
+                } else {
+                    println!(
+                        "info string The book selection must be one of {}.",
+                        BookSelection::NAMES.join(", ")
+                    );
+                }
+            }
+            _ => (),
+        }
+    }
+
     fn new_game(&mut self) {
         // Reset the board
         self.board = Board::start_pos();
         self.repetition_table = RepetitionTable::new();
+        // Results from the previous game are not relevant anymore
+        self.hash_table.clear();
     }
 
     fn change_position(&mut self, pos: UciPosition, moves: Vec<String>) {
@@ -154,20 +409,41 @@ impl UciEngine for Stonefish {
         self.repetition_table = repetition_table;
     }
 
-    fn go(&mut self, go_config: UciGoConfig, stop_flag: AbortFlag) {
+    fn go(&mut self, go_config: UciGoConfig, stop_flag: AbortFlag, ponder_flag: PonderFlag) {
         let mut root = Node::new(self.board.clone());
+        // An infinite search is used for analysis, where book moves are not helpful
+        let use_book = self.own_book && !go_config.infinite;
+
+        // Determine search depth, time, nodes and moves
+        let limits = SearchLimits::from_go_config(go_config, &root.board, self.move_overhead);
+
+        if let Some(mv) = use_book.then(|| self.book_move(&limits)).flatten() {
+            // Answer instantly, the book knows the opening better than the search
+            println!("info string Book move {}.", mv.stringify());
+            root.best_line = vec![mv];
+        } else {
+            if self.search_options.evaluator == EvaluatorType::Nnue
+                && self.search_options.network.is_none()
+            {
+                println!("info string No network loaded with EvalFile, using the handcrafted evaluation.");
+            }
+
+            // Search for the best move
+            root.iterative_deepening(
+                limits,
+                &self.search_options,
+                self.repetition_table.clone(),
+                self.hash_table.clone(),
+                stop_flag.clone(),
+                ponder_flag.clone(),
+            );
+        }
+
+        // While pondering, the best move may only be sent after `ponderhit` or `stop`
+        while ponder_flag.load(Ordering::SeqCst) && !stop_flag.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
 
-        // Determine search depth and time
-        let max_depth = go_config.max_depth.or(go_config.search_mate);
-        let max_time = get_max_time(go_config, root.board.turn());
-
-        // Search for the best move
-        root.iterative_deepening(
-            max_depth,
-            max_time,
-            self.repetition_table.clone(),
-            stop_flag,
-        );
         root.send_best_move();
     }
 }
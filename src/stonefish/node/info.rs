@@ -1,9 +1,23 @@
 use std::time::Duration;
 
-use crate::stonefish::{evaluation::Evaluation, types::Line};
+use crate::stonefish::{
+    evaluation::Evaluation,
+    transposition_table::Bound,
+    types::{Children, Line},
+};
 
 use super::Node;
 
+/// The progress of the whole search, reported with every info line.
+pub struct SearchProgress {
+    /// The time since the search started.
+    pub duration: Duration,
+    /// The number of nodes searched since the search started.
+    pub nodes: usize,
+    /// The number of positions found in the endgame tablebases.
+    pub tb_hits: usize,
+}
+
 impl Node {
     /// Format a line of moves.
     fn format_line(line: &Line) -> String {
@@ -14,16 +28,27 @@ impl Node {
     }
 
     /// Send the best move to the engine.
+    ///
+    /// The expected reply of the opponent is sent as the move to ponder on.
     pub fn send_best_move(&self) {
-        if let Some(mv) = self.best_line.first() {
-            println!("bestmove {}", mv.stringify());
+        match self.best_line.as_slice() {
+            [mv, ponder_mv, ..] => {
+                println!(
+                    "bestmove {} ponder {}",
+                    mv.stringify(),
+                    ponder_mv.stringify()
+                )
+            }
+            [mv] => println!("bestmove {}", mv.stringify()),
+            [] => (),
         }
     }
 
-    /// Send info about the current position to the engine.
-    pub fn send_info(&self, duration: Duration) {
-        // The evaluation of the current position
-        let score = match self.evaluation {
+    /// Format an evaluation as UCI score.
+    ///
+    /// If the search failed outside of the aspiration window, the evaluation is only a bound.
+    fn format_score(evaluation: Evaluation, bound: Bound) -> String {
+        let score = match evaluation {
             Evaluation::Centipawns(cp) => format!("cp {cp}"),
             Evaluation::Draw => "cp 0".to_string(),
             Evaluation::PlayerCheckmate(plies) => {
@@ -36,6 +61,66 @@ impl Node {
             }
         };
 
+        match bound {
+            Bound::Exact => score,
+            Bound::Lower => format!("{score} lowerbound"),
+            Bound::Upper => format!("{score} upperbound"),
+        }
+    }
+
+    /// Send info about the current position to the engine.
+    ///
+    /// One line is sent for each of the `multi_pv` best moves in `children`.
+    /// `bound` is the kind of bound that the evaluation of the node represents.
+    pub fn send_info(
+        &self,
+        children: &Children,
+        multi_pv: usize,
+        bound: Bound,
+        progress: &SearchProgress,
+    ) {
+        if children.is_empty() || multi_pv <= 1 || bound != Bound::Exact {
+            // Only the best line is needed
+            self.send_info_line(
+                1,
+                Self::format_score(self.evaluation, bound),
+                self.sel_depth,
+                &self.best_line,
+                progress,
+            );
+            return;
+        }
+
+        // The child evaluations are from the opponent's view, so worse is better for us
+        let mut ranked_children: Vec<&Node> = children.iter().collect();
+        ranked_children.sort();
+
+        for (index, child) in ranked_children.into_iter().take(multi_pv).enumerate() {
+            let mut line = child.best_line.clone();
+            line.splice(0..0, child.board.last_move());
+
+            self.send_info_line(
+                index + 1,
+                Self::format_score(
+                    child.evaluation.for_opponent().previous_plie(),
+                    Bound::Exact,
+                ),
+                child.sel_depth + 1,
+                &line,
+                progress,
+            );
+        }
+    }
+
+    /// Send a single info line about the current position to the engine.
+    fn send_info_line(
+        &self,
+        multi_pv_index: usize,
+        score: String,
+        sel_depth: usize,
+        line: &Line,
+        progress: &SearchProgress,
+    ) {
         // Example from Stockfish:
         // info depth 1 seldepth 1 multipv 1 score cp 112 nodes 20 nps 20000 tbhits 0 time 1 pv e2e4
         println!(
@@ -43,21 +128,21 @@ impl Node {
             // Depth
             self.depth,
             // Seldepth
-            self.sel_depth,
-            // Multi PV (we can only show one line at a time at the moment)
-            1,
+            sel_depth,
+            // Multi PV
+            multi_pv_index,
             // Score
             score,
             // Nodes
-            self.size,
+            progress.nodes,
             // Nps
-            self.size as u64 / duration.as_secs().max(1),
-            // Tbhits (not implemented yet)
-            0,
+            progress.nodes as u128 * 1000 / progress.duration.as_millis().max(1),
+            // Tbhits
+            progress.tb_hits,
             // Time
-            duration.as_millis(),
+            progress.duration.as_millis(),
             // Pv
-            Self::format_line(&self.best_line),
+            Self::format_line(line),
         );
     }
 }
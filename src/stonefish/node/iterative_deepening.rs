@@ -1,31 +1,226 @@
 use std::{
     sync::{
-        atomic::{AtomicBool, Ordering},
-        mpsc, Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex, OnceLock,
     },
     thread,
     time::{Duration, Instant},
 };
 
+use pleco::BitMove;
+
 use crate::{
     stonefish::{
-        abort_flags::AbortFlags,
+        abort_flags::{AbortFlags, NodeCounter, SearchAborted},
+        draw_rules::is_rule_draw,
         evaluation::Evaluation,
-        types::{HashTable, RepetitionTable},
+        heuristic::PawnHashTable,
+        search_limits::SearchLimits,
+        search_options::SearchOptions,
+        time_management::{ClockStart, TimeManager},
+        transposition_table::{Bound, TableEntry, TranspositionTable},
+        types::{Children, RepetitionTable},
     },
-    uci::AbortFlag,
+    uci::{AbortFlag, PonderFlag},
 };
 
-use super::Node;
+use super::{info::SearchProgress, move_ordering::MoveOrdering, Node};
+
+/// Half the size of the initial aspiration window, in centipawns.
+const ASPIRATION_WINDOW: i32 = 25;
+/// Aspiration windows larger than this are replaced by the full window, in centipawns.
+const MAX_ASPIRATION_WINDOW: i32 = 1000;
+/// The minimum depth at which aspiration windows are used.
+///
+/// The evaluations of shallow searches are too unstable.
+const ASPIRATION_MIN_DEPTH: usize = 4;
+
+/// The result of an iteration.
+struct IterationResult {
+    /// The root node, with the evaluation and best line.
+    node: Node,
+    /// The searched root moves, to report the best lines.
+    children: Children,
+    /// The kind of bound that the evaluation represents.
+    bound: Bound,
+    /// The iteration was aborted, but only after some root moves had been searched completely.
+    ///
+    /// The result is limited to these moves.
+    is_partial: bool,
+}
+
+impl IterationResult {
+    /// Determine if the partial result found a better move than the previous result.
+    ///
+    /// This is only the case if the previous best move has been searched again
+    /// and another move has been proven to be better.
+    fn improves_on(&self, previous: &IterationResult) -> bool {
+        let best_move = self.node.best_line.first();
+        let previous_best_move = previous.node.best_line.first();
+
+        self.is_partial
+            && self.bound != Bound::Upper
+            && best_move != previous_best_move
+            && self
+                .children
+                .iter()
+                .any(|child| child.board.last_move().as_ref() == previous_best_move)
+    }
+}
+
+/// The state of a search that is shared between all workers.
+pub(super) struct SharedSearch<'a> {
+    /// The time when the search started.
+    start: Instant,
+    /// The nodes searched by all workers, over all iterations.
+    node_counter: NodeCounter,
+    /// The positions found in the endgame tablebases by all workers.
+    tb_hits: NodeCounter,
+    /// The options of the search, as configured by the GUI.
+    pub(super) options: &'a SearchOptions,
+    /// The maximum depth to search, in plies.
+    max_depth: Option<usize>,
+    /// Only search for a mate in at most this many moves.
+    mate_in: Option<usize>,
+    /// Restrict the search to these root moves.
+    search_moves: &'a Option<Vec<BitMove>>,
+    /// Table to cache search results.
+    pub(super) hash_table: &'a TranspositionTable,
+    /// Table to track threefold repetition.
+    repetition_table: &'a RepetitionTable,
+    /// The deepest iteration that any worker has finished.
+    best_result: Mutex<Option<IterationResult>>,
+}
+
+/// The state of a single search thread.
+pub(super) struct Worker {
+    /// The id of the worker, the main worker has id 0.
+    id: usize,
+    /// The flags to abort the search of this worker.
+    pub(super) abort_flags: AbortFlags,
+    /// Tables to order the moves, learned over all iterations.
+    pub(super) move_ordering: MoveOrdering,
+    /// The cached pawn structures, kept over all iterations.
+    pub(super) pawn_table: PawnHashTable,
+}
+
+impl Worker {
+    /// Create a new worker with empty move ordering and pawn tables.
+    pub(super) fn new(id: usize, abort_flags: AbortFlags) -> Self {
+        Self {
+            id,
+            abort_flags,
+            move_ordering: MoveOrdering::new(),
+            pawn_table: PawnHashTable::new(),
+        }
+    }
+}
+
+impl<'a> SharedSearch<'a> {
+    /// Create the state of a search that starts now, without limits on the depth or mates.
+    pub(super) fn new(
+        options: &'a SearchOptions,
+        hash_table: &'a TranspositionTable,
+        repetition_table: &'a RepetitionTable,
+        search_moves: &'a Option<Vec<BitMove>>,
+    ) -> Self {
+        Self {
+            start: Instant::now(),
+            node_counter: Arc::new(AtomicUsize::new(0)),
+            tb_hits: Arc::new(AtomicUsize::new(0)),
+            options,
+            max_depth: None,
+            mate_in: None,
+            search_moves,
+            hash_table,
+            repetition_table,
+            best_result: Mutex::new(None),
+        }
+    }
+
+    /// Report a finished iteration of a worker.
+    ///
+    /// The result is only kept if it is deeper than the results of the other workers.
+    fn report(&self, result: IterationResult) {
+        let mut best_result = self.best_result.lock().unwrap();
+
+        let is_deeper = best_result
+            .as_ref()
+            .is_none_or(|best_result| result.node.depth > best_result.node.depth);
+
+        if is_deeper {
+            *best_result = Some(result);
+        }
+    }
+
+    /// Update the GUI on the result of an iteration.
+    fn send_info(&self, result: &IterationResult) {
+        result.node.send_info(
+            &result.children,
+            self.options.multi_pv,
+            result.bound,
+            &SearchProgress {
+                duration: self.start.elapsed(),
+                nodes: self.node_counter.load(Ordering::Relaxed),
+                tb_hits: self.tb_hits.load(Ordering::Relaxed),
+            },
+        );
+    }
+}
 
 impl Node {
+    /// Restrict the root moves to the moves that keep the best result in the endgame tablebases.
+    ///
+    /// If none of the moves requested by the GUI keeps the result, they are searched anyway.
+    fn tablebase_root_moves(
+        &self,
+        options: &SearchOptions,
+        search_moves: Option<Vec<BitMove>>,
+        tb_hits: &NodeCounter,
+    ) -> Option<Vec<BitMove>> {
+        let Some(mut root_moves) = options
+            .tablebases
+            .as_ref()
+            .and_then(|tablebases| tablebases.best_root_moves(&self.board))
+        else {
+            return search_moves;
+        };
+
+        tb_hits.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(search_moves) = &search_moves {
+            root_moves.retain(|mv| search_moves.contains(mv));
+
+            if root_moves.is_empty() {
+                return Some(search_moves.clone());
+            }
+        }
+
+        Some(root_moves)
+    }
+
     /// Set a timer to abort the search.
     ///
     /// This function will set the time flag to true once the time runs out.
-    fn set_timer(max_time: Option<Duration>, time_flag: AbortFlag) {
+    /// The timer only starts once the engine stopped pondering,
+    /// which is recorded in `clock_start`.
+    fn set_timer(
+        max_time: Option<Duration>,
+        time_flag: AbortFlag,
+        stop_flag: AbortFlag,
+        ponder_flag: PonderFlag,
+        clock_start: ClockStart,
+    ) {
         if let Some(max_time) = max_time {
             // Start a new thread so that we don't block the main thread
             thread::spawn(move || {
+                // While pondering we are searching on the opponent's time
+                while ponder_flag.load(Ordering::SeqCst) && !stop_flag.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+
+                clock_start.get_or_init(Instant::now);
+
                 // Wait for the given time
                 thread::sleep(max_time);
                 // Set the time flag to true
@@ -34,94 +229,394 @@ impl Node {
         }
     }
 
+    /// Search a root move with principal variation search.
+    ///
+    /// The best lines are searched with the full window,
+    /// the other moves only have to prove that they are worse with a null window.
+    ///
+    /// Returns the evaluation from the view of the opponent.
+    fn search_root_move(
+        child: &mut Node,
+        is_best_line: bool,
+        depth: usize,
+        alpha: Evaluation,
+        beta: Evaluation,
+        shared: &SharedSearch,
+        worker: &mut Worker,
+    ) -> Result<Evaluation, SearchAborted> {
+        let mut repetition_table = shared.repetition_table.clone();
+
+        if repetition_table.insert_check_draw(&child.board) || is_rule_draw(&child.board) {
+            child.evaluation = Evaluation::Draw;
+            return Ok(Evaluation::Draw);
+        }
+
+        if !is_best_line {
+            let child_eval = child.minimax(
+                depth - 1,
+                alpha.next_better().for_opponent(),
+                alpha,
+                shared,
+                &mut repetition_table.clone(),
+                worker,
+            )?;
+            let evaluation = child_eval.for_opponent().previous_plie();
+
+            if evaluation <= alpha || evaluation.for_opponent() <= beta {
+                // The move is worse than the best lines or causes a cutoff anyway
+                return Ok(child_eval);
+            }
+        }
+
+        // We have to swap alpha and beta here, because it's the other player's turn
+        child.minimax(
+            depth - 1,
+            beta,
+            alpha,
+            shared,
+            &mut repetition_table,
+            worker,
+        )
+    }
+
+    /// Search all root moves at the given depth, limited to the given window.
+    ///
+    /// - `alpha`: Minimum value the current player is assured of
+    /// - `beta`: Minimum value the opponent player is assured of
+    ///
+    /// Returns the root node with its moves and if the search has been aborted.
+    fn search_iteration(
+        &self,
+        depth: usize,
+        alpha: Evaluation,
+        beta: Evaluation,
+        shared: &SharedSearch,
+        worker: &mut Worker,
+    ) -> (IterationResult, bool) {
+        let mut node = self.clone();
+        let parameters = &shared.options.eval_parameters;
+        let mut children =
+            node.reset(parameters)
+                .expand(parameters, &mut worker.pawn_table, shared.hash_table);
+
+        if let Some(search_moves) = shared.search_moves {
+            // Only search the moves requested by the GUI
+            children.retain(|child| search_moves.contains(&child.board.last_move().unwrap()));
+            node.update_attributes(&children);
+        }
+
+        // The helper workers search the moves in a different order,
+        // so that they don't do the same work as the main worker
+        if !children.is_empty() {
+            let rotation = worker.id % children.len();
+            children.rotate_left(rotation);
+        }
+
+        let original_alpha = alpha;
+        let mut alpha = alpha;
+        // The evaluations of the searched moves, from our point of view
+        let mut evaluations = vec![];
+        let mut abort = false;
+
+        for (index, child) in children.iter_mut().enumerate() {
+            let is_best_line = index < shared.options.multi_pv;
+            let result =
+                Self::search_root_move(child, is_best_line, depth, alpha, beta, shared, worker);
+
+            let Ok(child_eval) = result else {
+                abort = true;
+                break;
+            };
+
+            let evaluation = child_eval.for_opponent().previous_plie();
+            evaluations.push(evaluation);
+
+            if evaluation.for_opponent() <= beta {
+                // The evaluation is above the window
+                break;
+            }
+
+            if evaluations.len() >= shared.options.multi_pv {
+                // The other moves have to be better than the worst of the best lines
+                evaluations.sort_unstable_by(|a, b| b.cmp(a));
+                alpha = alpha.max(evaluations[shared.options.multi_pv - 1]);
+            }
+        }
+
+        // Without any finished move, the move ordering is all we have
+        let is_partial = abort && !evaluations.is_empty();
+
+        if !abort || is_partial {
+            // Moves that haven't been searched can't be compared to the others
+            children.truncate(evaluations.len());
+        }
+
+        // Update the node with the new evaluation
+        node.update_attributes(&children);
+
+        let bound = if node.evaluation.for_opponent() <= beta {
+            Bound::Lower
+        } else if node.evaluation <= original_alpha {
+            Bound::Upper
+        } else {
+            Bound::Exact
+        };
+
+        if !abort {
+            // Remember the best move for the move ordering of the next iteration
+            shared.hash_table.store(
+                node.board.zobrist(),
+                TableEntry {
+                    evaluation: node.evaluation,
+                    bound,
+                    depth,
+                    best_move: node.best_line.first().copied(),
+                },
+            );
+        }
+
+        (
+            IterationResult {
+                node,
+                children,
+                bound,
+                is_partial,
+            },
+            abort,
+        )
+    }
+
+    /// Search all root moves at the given depth, with an aspiration window.
+    ///
+    /// The window is centered around the evaluation of the previous iteration.
+    /// If the evaluation falls outside of the window, the search is repeated with a wider window.
+    /// See <https://www.chessprogramming.org/Aspiration_Windows>.
+    ///
+    /// Returns the root node with its moves and if the search has been aborted.
+    fn search_aspiration_window(
+        &self,
+        depth: usize,
+        shared: &SharedSearch,
+        worker: &mut Worker,
+    ) -> (IterationResult, bool) {
+        let previous_evaluation = shared
+            .best_result
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|result| result.node.evaluation);
+
+        let center = match previous_evaluation {
+            _ if depth < ASPIRATION_MIN_DEPTH || shared.mate_in.is_some() => None,
+            // The window would only fit the best line, the other lines would fail low
+            _ if shared.options.multi_pv > 1 => None,
+            Some(Evaluation::Centipawns(cp)) => Some(cp),
+            Some(Evaluation::Draw) => Some(0),
+            // The evaluation of mates is too unstable for a window
+            _ => None,
+        };
+        let mut delta = ASPIRATION_WINDOW;
+
+        loop {
+            let (alpha, beta) = match center {
+                Some(cp) if delta <= MAX_ASPIRATION_WINDOW => (
+                    Evaluation::Centipawns(cp - delta),
+                    Evaluation::Centipawns(-(cp + delta)),
+                ),
+                // In a mate search, only faster mates than the bound are interesting
+                _ => (
+                    shared
+                        .mate_in
+                        .map_or(Evaluation::OpponentCheckmate(0), |moves| {
+                            Evaluation::PlayerCheckmate(2 * moves)
+                        }),
+                    Evaluation::OpponentCheckmate(0),
+                ),
+            };
+
+            let (result, abort) = self.search_iteration(depth, alpha, beta, shared, worker);
+
+            let is_full_window = center.is_none() || delta > MAX_ASPIRATION_WINDOW;
+
+            if abort || result.bound == Bound::Exact || is_full_window {
+                return (result, abort);
+            }
+
+            if worker.id == 0 {
+                // Let the GUI know that the evaluation is outside of the window
+                shared.send_info(&result);
+            }
+
+            delta *= 2;
+        }
+    }
+
+    /// Search with iterative deepening as a helper of the main worker.
+    ///
+    /// Helpers start at different depths and only share their work via the transposition table.
+    /// They search until the depth limit is reached or they are aborted.
+    fn search_helper(&self, shared: &SharedSearch, mut worker: Worker) {
+        let mut depth = 1 + worker.id % 2;
+
+        while shared.max_depth.is_none_or(|max_depth| depth <= max_depth) {
+            let (result, abort) = self.search_aspiration_window(depth, shared, &mut worker);
+
+            if abort {
+                break;
+            }
+
+            shared.report(result);
+            depth += 1;
+        }
+    }
+
     /// The iterative deepening search algorithm.
+    ///
+    /// The search stops once any of the given limits is reached.
+    /// It runs on the configured number of threads, which share the transposition table.
+    /// See <https://www.chessprogramming.org/Lazy_SMP>.
     pub fn iterative_deepening(
         &mut self,
-        max_depth: Option<usize>,
-        max_time: Option<Duration>,
+        limits: SearchLimits,
+        options: &SearchOptions,
         repetition_table: RepetitionTable,
+        hash_table: Arc<TranspositionTable>,
         stop_flag: AbortFlag,
+        ponder_flag: PonderFlag,
     ) -> Evaluation {
         let start = Instant::now();
+        let SearchLimits {
+            max_depth,
+            max_time,
+            soft_time,
+            max_nodes,
+            mate_in,
+            search_moves,
+        } = limits;
+        // Set when our clock starts, after the ponder hit if we are pondering
+        let clock_start: ClockStart = Arc::new(OnceLock::new());
+        // Decides when to stop between iterations, the timer only enforces the hard limit
+        let mut time_manager = soft_time
+            .zip(max_time)
+            .map(|(soft, hard)| TimeManager::new(soft, hard, start, clock_start.clone()));
         // When this flag is set to true, time has run out
         let time_flag: AbortFlag = Arc::new(AtomicBool::new(false));
-        Self::set_timer(max_time, time_flag.clone());
-
-        let mut depth: usize = 1;
-
-        // Search at higher and higher depths
-        loop {
-            if let Some(max_depth) = max_depth {
-                if depth > max_depth {
-                    break;
-                }
-            }
-
-            let (tx, rx) = mpsc::channel();
-
-            let mut node = self.clone();
-            let children = node.reset().expand(&HashTable::new());
+        Self::set_timer(
+            max_time,
+            time_flag.clone(),
+            stop_flag.clone(),
+            ponder_flag.clone(),
+            clock_start,
+        );
+        // The nodes searched by all threads, over all iterations
+        let node_counter: NodeCounter = Arc::new(AtomicUsize::new(0));
+        // The positions found in the endgame tablebases by all threads
+        let tb_hits: NodeCounter = Arc::new(AtomicUsize::new(0));
+        let search_moves = self.tablebase_root_moves(options, search_moves, &tb_hits);
+        // The mates of composed problems often start with quiet moves,
+        // the selective search would reduce them below the mate depth
+        let mate_options;
+        let options = if mate_in.is_some() {
+            mate_options = SearchOptions {
+                null_move_pruning: false,
+                late_move_reductions: false,
+                ..options.clone()
+            };
+            &mate_options
+        } else {
+            options
+        };
+        // When this flag is set to true, the main worker has finished
+        let helper_stop_flag: AbortFlag = Arc::new(AtomicBool::new(false));
 
-            // Search every move in a separate thread
-            for child in &children {
-                let tx = tx.clone();
-                let mut child = child.clone();
+        let shared = SharedSearch {
+            start,
+            node_counter: node_counter.clone(),
+            tb_hits: tb_hits.clone(),
+            max_depth,
+            mate_in,
+            ..SharedSearch::new(options, &hash_table, &repetition_table, &search_moves)
+        };
 
-                let mut hash_table = HashTable::new();
-                let mut repetition_table = repetition_table.clone();
-                if repetition_table.insert_check_draw(&child.board) {
-                    repetition_table.remove(&self.board);
-                    child.evaluation = Evaluation::Draw;
-                    tx.send((child, Ok(Evaluation::Draw))).unwrap();
-                    continue;
-                }
-
-                let abort_flags = AbortFlags::from_flags(stop_flag.clone(), time_flag.clone());
+        thread::scope(|scope| {
+            for worker_id in 1..options.threads {
+                let abort_flags = AbortFlags::from_flags(
+                    helper_stop_flag.clone(),
+                    time_flag.clone(),
+                    node_counter.clone(),
+                    max_nodes,
+                    tb_hits.clone(),
+                );
+                let shared = &shared;
+                let root = &*self;
 
                 thread::Builder::new()
-                    .name(child.board.last_move().unwrap().stringify())
-                    .spawn(move || {
-                        let result = child.minimax(
-                            depth - 1,
-                            &mut hash_table,
-                            &mut repetition_table,
-                            abort_flags,
-                        );
-                        repetition_table.remove(&child.board);
-                        tx.send((child, result)).unwrap();
+                    .name(format!("Search worker {worker_id}"))
+                    .spawn_scoped(scope, move || {
+                        root.search_helper(shared, Worker::new(worker_id, abort_flags))
                     })
                     .unwrap();
             }
 
-            let mut updated_children = vec![];
-            let mut abort = false;
+            let abort_flags = AbortFlags::from_flags(
+                stop_flag.clone(),
+                time_flag.clone(),
+                node_counter.clone(),
+                max_nodes,
+                tb_hits.clone(),
+            );
+            let mut worker = Worker::new(0, abort_flags);
+            let mut depth: usize = 1;
 
-            // Aggregate the results
-            for _ in &children {
-                let (child, result) = rx.recv().unwrap();
-                if result.is_err() {
-                    abort = true;
+            // Search at higher and higher depths
+            while max_depth.is_none_or(|max_depth| depth <= max_depth) {
+                let (result, abort) = self.search_aspiration_window(depth, &shared, &mut worker);
+
+                if !abort {
+                    shared.report(result);
+                } else if shared
+                    .best_result
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .is_none_or(|best_result| result.improves_on(best_result))
+                {
+                    // Not even the first iteration finished, fall back to the move ordering
+                    // so that we can still respond with a move.
+                    // Or the moves searched before the abort found a better move, play it instead
+                    shared.report(result);
                 }
-                updated_children.push(child);
-            }
 
-            if !abort {
-                // Update the node with the new evaluation
-                node.update_attributes(&updated_children);
-                self.copy_values(&node);
-            }
+                let best_result = shared.best_result.lock().unwrap();
+                let best_result = best_result.as_ref().unwrap();
 
-            // Update the GUI on the current evaluation
-            self.send_info(start.elapsed());
-            depth += 1;
+                // Update the GUI on the current evaluation
+                shared.send_info(best_result);
 
-            // If the time is limited and there is a forced mate, just play it out
-            let play_forced_mate =
-                self.evaluation.is_game_over() && (max_depth.is_some() || max_time.is_some());
+                // Continue after the deepest iteration of all workers
+                depth = depth.max(best_result.node.depth) + 1;
 
-            if abort || play_forced_mate {
-                break;
+                // If the search is limited and there is a forced mate, just play it out.
+                // In a mate search, the evaluation is only a bound until a mate has been found
+                let play_forced_mate = best_result.node.evaluation.is_game_over()
+                    && best_result.bound == Bound::Exact
+                    && (max_depth.is_some() || max_time.is_some() || max_nodes.is_some());
+
+                let out_of_time = time_manager.as_mut().is_some_and(|time_manager| {
+                    time_manager.should_stop(&best_result.node, &best_result.children)
+                });
+
+                if abort || play_forced_mate || out_of_time {
+                    break;
+                }
             }
+
+            // Stop the helpers, their results aren't needed anymore
+            helper_stop_flag.store(true, Ordering::SeqCst);
+        });
+
+        if let Some(best_result) = shared.best_result.into_inner().unwrap() {
+            self.copy_values(&best_result.node);
         }
 
         self.evaluation
@@ -130,19 +625,42 @@ impl Node {
 
 #[cfg(test)]
 mod tests {
-    use std::sync::{atomic::AtomicBool, Arc};
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use pleco::{BitMove, Board};
 
-    use pleco::Board;
+    use crate::stonefish::{
+        abort_flags::AbortFlags,
+        evaluation::Evaluation,
+        heuristic::{EvalParameters, PawnHashTable},
+        node::Node,
+        search_limits::SearchLimits,
+        search_options::SearchOptions,
+        transposition_table::{Bound, TranspositionTable},
+        types::RepetitionTable,
+    };
 
-    use crate::stonefish::{evaluation::Evaluation, node::Node, types::RepetitionTable};
+    use super::{IterationResult, SharedSearch, Worker};
 
     fn assert_forced_mate(fen: &str, plies: usize) {
         let board = Board::from_fen(fen).unwrap();
         let mut node = Node::new(board);
         node.iterative_deepening(
-            Some(plies),
-            None,
+            SearchLimits {
+                max_depth: Some(plies),
+                ..Default::default()
+            },
+            &SearchOptions::default(),
             RepetitionTable::new(),
+            Arc::new(TranspositionTable::new(1)),
+            Arc::new(AtomicBool::new(false)),
             Arc::new(AtomicBool::new(false)),
         );
 
@@ -200,6 +718,79 @@ mod tests {
         }
     }
 
+    /// Search for a mate in the given number of moves.
+    fn mate_search(fen: &str, moves: usize) -> Node {
+        let mut node = Node::new(Board::from_fen(fen).unwrap());
+        node.iterative_deepening(
+            SearchLimits {
+                max_depth: Some(2 * moves - 1),
+                mate_in: Some(moves),
+                ..Default::default()
+            },
+            &SearchOptions::default(),
+            RepetitionTable::new(),
+            Arc::new(TranspositionTable::new(1)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+        node
+    }
+
+    #[test]
+    fn should_find_mates_with_mate_search() {
+        let parameters = [
+            // See https://wtharvey.com/m8n2.txt
+            ("1rb4r/pkPp3p/1b1P3n/1Q6/N3Pp2/8/P1P3PP/7K w - - 1 1", 2, 3),
+            (
+                "5rkr/pp2Rp2/1b1p1Pb1/3P2Q1/2n3P1/2p5/P4P2/4R1K1 w - - 1 1",
+                2,
+                3,
+            ),
+            // See https://wtharvey.com/m8n3.txt
+            (
+                "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1",
+                3,
+                5,
+            ),
+            // A mate in 2 is also found when searching for a mate in 3
+            ("1rb4r/pkPp3p/1b1P3n/1Q6/N3Pp2/8/P1P3PP/7K w - - 1 1", 3, 3),
+        ];
+
+        for (fen, moves, plies) in parameters {
+            let node = mate_search(fen, moves);
+
+            assert_eq!(node.evaluation, Evaluation::PlayerCheckmate(plies), "{fen}");
+            // The search stops once the mate is proven
+            assert!(node.depth <= plies, "{fen}: depth {}", node.depth);
+        }
+    }
+
+    #[test]
+    fn should_not_find_mates_beyond_the_bound() {
+        let parameters = [
+            // A mate in 3, but not in 2
+            (
+                "r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1",
+                2,
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                2,
+            ),
+        ];
+
+        for (fen, moves) in parameters {
+            let node = mate_search(fen, moves);
+
+            assert!(
+                node.evaluation < Evaluation::PlayerCheckmate(2 * moves),
+                "{fen}: {:?}",
+                node.evaluation
+            );
+            assert!(!node.best_line.is_empty(), "{fen}");
+        }
+    }
+
     #[test]
     fn should_not_wrongly_assume_mate() {
         let paramerters = [
@@ -215,9 +806,14 @@ mod tests {
         for (fen, depth) in paramerters {
             let mut node = Node::new(Board::from_fen(fen).unwrap());
             node.iterative_deepening(
-                Some(depth),
-                None,
+                SearchLimits {
+                    max_depth: Some(depth),
+                    ..Default::default()
+                },
+                &SearchOptions::default(),
                 RepetitionTable::new(),
+                Arc::new(TranspositionTable::new(1)),
+                Arc::new(AtomicBool::new(false)),
                 Arc::new(AtomicBool::new(false)),
             );
 
@@ -230,6 +826,57 @@ mod tests {
         }
     }
 
+    #[test]
+    fn should_apply_draw_rules() {
+        let parameters = [
+            (
+                "no mate before the fifty-move rule",
+                "7k/8/8/8/8/8/8/K1Q5 w - - 99 120",
+                None,
+                true,
+            ),
+            (
+                "capture resets the fifty-move counter",
+                "7k/8/8/8/3n4/2Q5/8/K7 w - - 99 120",
+                Some("c3d4"),
+                false,
+            ),
+            (
+                "bishop can't mate",
+                "8/8/8/4k3/8/8/3BK3/8 w - - 0 1",
+                None,
+                true,
+            ),
+            (
+                "capturing the rook leaves insufficient material",
+                "8/8/8/8/8/k7/3r4/3K4 w - - 0 1",
+                Some("d1d2"),
+                true,
+            ),
+        ];
+
+        for (name, fen, expected_move, is_draw) in parameters {
+            let mut node = Node::new(Board::from_fen(fen).unwrap());
+            node.iterative_deepening(
+                SearchLimits {
+                    max_depth: Some(4),
+                    ..Default::default()
+                },
+                &SearchOptions::default(),
+                RepetitionTable::new(),
+                Arc::new(TranspositionTable::new(1)),
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(false)),
+            );
+
+            if let Some(expected_move) = expected_move {
+                assert_eq!(node.best_line[0].stringify(), expected_move, "{name}");
+            }
+
+            assert_eq!(node.evaluation == Evaluation::Draw, is_draw, "{name}");
+        }
+    }
+
     #[test]
     fn should_always_respond_with_move() {
         let params = [(
@@ -251,14 +898,19 @@ mod tests {
             // Construct a node and start searching
             let mut node = Node::new(board);
             node.iterative_deepening(
-                Some(3),
-                None,
+                SearchLimits {
+                    max_depth: Some(3),
+                    ..Default::default()
+                },
+                &SearchOptions::default(),
                 repetition_table,
+                Arc::new(TranspositionTable::new(1)),
+                Arc::new(AtomicBool::new(false)),
                 Arc::new(AtomicBool::new(false)),
             );
 
             // The bot should give a response
-            assert!(node.best_line.len() > 0);
+            assert!(!node.best_line.is_empty());
         }
     }
 
@@ -294,14 +946,307 @@ mod tests {
             // Construct a node and start searching
             let mut node = Node::new(board);
             node.iterative_deepening(
-                Some(3),
-                None,
+                SearchLimits {
+                    max_depth: Some(3),
+                    ..Default::default()
+                },
+                &SearchOptions::default(),
                 repetition_table,
+                Arc::new(TranspositionTable::new(1)),
+                Arc::new(AtomicBool::new(false)),
                 Arc::new(AtomicBool::new(false)),
             );
 
             // The bot should give a response
-            assert!(node.best_line.len() > 0);
+            assert!(!node.best_line.is_empty());
+        }
+    }
+
+    #[test]
+    fn should_respect_node_limit() {
+        let mut node = Node::new(Board::start_pos());
+        node.iterative_deepening(
+            SearchLimits {
+                max_nodes: Some(1_000),
+                ..Default::default()
+            },
+            &SearchOptions::default(),
+            RepetitionTable::new(),
+            Arc::new(TranspositionTable::new(1)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        // The budget is too small to search deep
+        assert!(node.depth < 5, "depth: {}", node.depth);
+        // The bot should give a response
+        assert!(!node.best_line.is_empty());
+    }
+
+    #[test]
+    fn should_respond_with_move_if_first_iteration_is_aborted() {
+        let mut node = Node::new(Board::start_pos());
+        node.iterative_deepening(
+            SearchLimits {
+                max_nodes: Some(1),
+                ..Default::default()
+            },
+            &SearchOptions::default(),
+            RepetitionTable::new(),
+            Arc::new(TranspositionTable::new(1)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        // The bot should give a response
+        assert!(!node.best_line.is_empty());
+    }
+
+    #[test]
+    fn should_only_search_given_moves() {
+        let board = Board::start_pos();
+        let search_move = board
+            .generate_moves()
+            .iter()
+            .find(|mv| mv.stringify() == "a2a3")
+            .copied()
+            .unwrap();
+
+        let mut node = Node::new(board);
+        node.iterative_deepening(
+            SearchLimits {
+                max_depth: Some(3),
+                search_moves: Some(vec![search_move]),
+                ..Default::default()
+            },
+            &SearchOptions::default(),
+            RepetitionTable::new(),
+            Arc::new(TranspositionTable::new(1)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        assert_eq!(node.best_line.first(), Some(&search_move));
+    }
+
+    #[test]
+    fn should_not_run_out_of_time_while_pondering() {
+        let ponder_flag = Arc::new(AtomicBool::new(true));
+        let ponder_hit_flag = ponder_flag.clone();
+
+        // The opponent plays the expected move after a while
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(200));
+            ponder_hit_flag.store(false, Ordering::SeqCst);
+        });
+
+        let start = Instant::now();
+        let mut node = Node::new(Board::start_pos());
+        node.iterative_deepening(
+            SearchLimits {
+                max_time: Some(Duration::from_millis(10)),
+                ..Default::default()
+            },
+            &SearchOptions::default(),
+            RepetitionTable::new(),
+            Arc::new(TranspositionTable::new(1)),
+            Arc::new(AtomicBool::new(false)),
+            ponder_flag,
+        );
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(!node.best_line.is_empty());
+    }
+
+    #[test]
+    fn should_solve_mate_in_2_puzzles_with_multiple_threads() {
+        let puzzle_fens = [
+            "1rb4r/pkPp3p/1b1P3n/1Q6/N3Pp2/8/P1P3PP/7K w - - 1 1",
+            "5rkr/pp2Rp2/1b1p1Pb1/3P2Q1/2n3P1/2p5/P4P2/4R1K1 w - - 1 1",
+        ];
+
+        for fen in puzzle_fens {
+            let mut node = Node::new(Board::from_fen(fen).unwrap());
+            node.iterative_deepening(
+                SearchLimits {
+                    max_depth: Some(3),
+                    ..Default::default()
+                },
+                &SearchOptions {
+                    threads: 4,
+                    ..Default::default()
+                },
+                RepetitionTable::new(),
+                Arc::new(TranspositionTable::new(1)),
+                Arc::new(AtomicBool::new(false)),
+                Arc::new(AtomicBool::new(false)),
+            );
+
+            assert_eq!(
+                node.evaluation,
+                Evaluation::PlayerCheckmate(3),
+                "fen: {fen}"
+            );
+            assert!(!node.best_line.is_empty());
+        }
+    }
+
+    #[test]
+    fn should_report_exact_scores_for_multiple_lines() {
+        // The lines of the Italian opening are some centipawns apart
+        let node = Node::new(
+            Board::from_fen("r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4")
+                .unwrap(),
+        );
+        let options = SearchOptions {
+            multi_pv: 3,
+            ..Default::default()
+        };
+        let hash_table = TranspositionTable::new(16);
+        let repetition_table = RepetitionTable::new();
+        let depth = 5;
+
+        // Search with iterative deepening
+        let all_moves = SharedSearch::new(&options, &hash_table, &repetition_table, &None);
+        let mut worker = Worker::new(0, AbortFlags::new());
+
+        for depth in 1..=depth {
+            let (result, abort) = node.search_aspiration_window(depth, &all_moves, &mut worker);
+            assert!(!abort);
+            all_moves.report(result);
         }
+
+        let result = all_moves.best_result.into_inner().unwrap().unwrap();
+        let mut children = result.children;
+        children.sort();
+        let lines: Vec<(BitMove, Evaluation)> = children
+            .iter()
+            .take(3)
+            .map(|child| {
+                let evaluation = child.evaluation.for_opponent().previous_plie();
+                (child.board.last_move().unwrap(), evaluation)
+            })
+            .collect();
+
+        assert_eq!(result.bound, Bound::Exact);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].1, result.node.evaluation);
+        assert!(
+            lines[0].1 > lines[1].1 && lines[1].1 > lines[2].1,
+            "{lines:?}"
+        );
+
+        for (mv, evaluation) in lines {
+            // Scores that are only bounds change when the move is searched with the full window
+            let search_moves = Some(vec![mv]);
+            let (single_result, _) = node.search_iteration(
+                depth,
+                Evaluation::OpponentCheckmate(0),
+                Evaluation::OpponentCheckmate(0),
+                &SharedSearch::new(&options, &hash_table, &repetition_table, &search_moves),
+                &mut Worker::new(0, AbortFlags::new()),
+            );
+
+            assert_eq!(
+                single_result.node.evaluation,
+                evaluation,
+                "{}",
+                mv.stringify()
+            );
+        }
+    }
+
+    #[test]
+    fn should_report_bound_outside_of_window() {
+        // White can take the undefended queen
+        let node = Node::new(Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap());
+        let options = SearchOptions::default();
+        let hash_table = TranspositionTable::new(1);
+        let repetition_table = RepetitionTable::new();
+        let shared = SharedSearch {
+            max_depth: Some(2),
+            ..SharedSearch::new(&options, &hash_table, &repetition_table, &None)
+        };
+
+        let search = |alpha, beta| {
+            let (result, abort) = node.search_iteration(
+                2,
+                alpha,
+                beta,
+                &shared,
+                &mut Worker::new(0, AbortFlags::new()),
+            );
+            assert!(!abort);
+            result
+        };
+
+        // The evaluation is far above the window
+        let result = search(Evaluation::Centipawns(-10), Evaluation::Centipawns(-10));
+        assert_eq!(result.bound, Bound::Lower);
+        assert!(result.node.evaluation > Evaluation::Centipawns(10));
+
+        // The evaluation is far below the window
+        let result = search(Evaluation::Centipawns(2000), Evaluation::Centipawns(-3000));
+        assert_eq!(result.bound, Bound::Upper);
+        assert!(result.node.evaluation < Evaluation::Centipawns(2000));
+
+        let result = search(
+            Evaluation::OpponentCheckmate(0),
+            Evaluation::OpponentCheckmate(0),
+        );
+        assert_eq!(result.bound, Bound::Exact);
+        assert_eq!(result.node.best_line[0].stringify(), "d2d5");
+    }
+
+    #[test]
+    fn should_only_use_partial_results_with_better_moves() {
+        // White can take the undefended queen
+        let mut node = Node::new(Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap());
+        let all_children = node.expand(
+            &EvalParameters::default(),
+            &mut PawnHashTable::new(),
+            &TranspositionTable::new(1),
+        );
+        let find_child = |uci_move: &str| {
+            all_children
+                .iter()
+                .find(|child| child.board.last_move().unwrap().stringify() == uci_move)
+                .unwrap()
+                .clone()
+        };
+
+        // The previous best move has been searched first, then the capture
+        let mut children = vec![find_child("e1f1"), find_child("d2d5")];
+        children[0].evaluation = Evaluation::Centipawns(0);
+        children[1].evaluation = Evaluation::Centipawns(-900);
+        node.update_attributes(&children);
+
+        let result = |best_move: &str, bound, is_partial| {
+            let mut node = node.clone();
+            node.best_line = vec![find_child(best_move).board.last_move().unwrap()];
+
+            IterationResult {
+                node,
+                children: children.clone(),
+                bound,
+                is_partial,
+            }
+        };
+        let previous = result("e1f1", Bound::Exact, false);
+
+        assert!(result("d2d5", Bound::Exact, true).improves_on(&previous));
+        assert!(result("d2d5", Bound::Lower, true).improves_on(&previous));
+        // The best move didn't change
+        assert!(!result("e1f1", Bound::Exact, true).improves_on(&previous));
+        // The evaluation is below the window, the moves are only upper bounds
+        assert!(!result("d2d5", Bound::Upper, true).improves_on(&previous));
+        // The iteration was aborted before any move finished
+        assert!(!result("d2d5", Bound::Exact, false).improves_on(&previous));
+        // The previous best move hasn't been searched again
+        assert!(!result("d2d5", Bound::Exact, true).improves_on(&result(
+            "d2d1",
+            Bound::Exact,
+            false
+        )));
     }
 }
@@ -1,129 +1,554 @@
+use pleco::{BitMove, PieceType};
+
 use crate::stonefish::{
-    abort_flags::{AbortFlags, SearchAborted},
+    abort_flags::SearchAborted,
+    draw_rules::is_rule_draw,
     evaluation::Evaluation,
-    heuristic::final_heuristic,
-    types::{HashTable, HashTableEntry, RepetitionTable},
+    tablebase::Wdl,
+    transposition_table::{Bound, TableEntry, TranspositionTable},
+    types::RepetitionTable,
 };
 
-use super::Node;
+use super::{
+    iterative_deepening::{SharedSearch, Worker},
+    move_ordering::MoveOrdering,
+    search::{Search, MAX_PLY},
+    Node,
+};
 
-impl Node {
+/// The minimum remaining depth to try null move pruning.
+const NULL_MOVE_MIN_DEPTH: usize = 3;
+/// The depth reduction of the search after a null move.
+const NULL_MOVE_REDUCTION: usize = 2;
+/// The depth reduction of the search after a null move, if enough depth is remaining.
+const DEEP_NULL_MOVE_REDUCTION: usize = 3;
+/// The minimum remaining depth to use the deeper null move reduction.
+const DEEP_NULL_MOVE_MIN_DEPTH: usize = 7;
+
+/// The minimum remaining depth to reduce late moves.
+const LATE_MOVE_MIN_DEPTH: usize = 3;
+/// The number of moves that are searched with the full depth.
+const LATE_MOVE_MIN_INDEX: usize = 3;
+/// The number of moves after which late moves are reduced further.
+const VERY_LATE_MOVE_MIN_INDEX: usize = 8;
+/// The minimum remaining depth to reduce very late moves further.
+const VERY_LATE_MOVE_MIN_DEPTH: usize = 6;
+
+/// The depth added to tablebase results in the transposition table.
+///
+/// They are exact, so they should not be replaced by the results of shallow searches.
+const TABLEBASE_DEPTH_BONUS: usize = 6;
+
+impl Search<'_> {
     /// The implementation of minimax with alpha-beta-pruning.
     ///
+    /// - `depth`: The remaining depth to search
+    /// - `ply`: The number of moves made since the start of the search
+    /// - `evaluation`: The heuristic evaluation of the current position
     /// - `alpha`: Minimum value the current player is assured of
     /// - `beta`: Minimum value the opponent player is assured of
-    fn minimax_helper(
+    pub(super) fn minimax(
         &mut self,
         depth: usize,
+        ply: usize,
+        evaluation: Evaluation,
         alpha: Evaluation,
         beta: Evaluation,
-        hash_table: &mut HashTable,
-        repetition_table: &mut RepetitionTable,
-        abort_flags: AbortFlags,
     ) -> Result<Evaluation, SearchAborted> {
-        // Check for repetition
-        if repetition_table.insert_check_draw(&self.board) {
+        self.pv_table.clear(ply);
+
+        // Check for repetition, the fifty-move rule and insufficient material
+        if self.repetition_table.insert_check_draw(&self.board) || is_rule_draw(&self.board) {
             return Ok(Evaluation::Draw);
         }
 
-        if depth == 0 {
-            // Update the evaluation with a more expensive analysis
-            self.evaluation = final_heuristic(self.evaluation, &self.board);
-            return Ok(self.evaluation);
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            // Resolve all captures before evaluating the position
+            return self.quiescence(ply, evaluation, alpha, beta);
         }
 
+        self.size += 1;
+        self.abort_flags.add_nodes(1);
+
         // Check if the search has been aborted
-        abort_flags.check()?;
+        self.abort_flags.check()?;
+
+        // Even a mate with the next move can't improve alpha anymore
+        if alpha >= Evaluation::PlayerCheckmate(1) {
+            return Ok(alpha);
+        }
 
         // Check if the value has been cached
-        // TODO: Fix this breaking the evaluation
-        // if let Some(HashTableEntry {
-        //     evaluation: cache_eval,
-        //     best_line: cache_line,
-        //     depth: cache_depth,
-        // }) = hash_table.get(&self.board.zobrist())
-        // {
-        //     // Only use the cached value if it has sufficient depth
-        //     if cache_eval.is_forced_mate() || *cache_depth >= depth {
-        //         self.evaluation = *cache_eval;
-        //         self.best_line = cache_line.clone();
-        //         repetition_table.remove(&self.board);
-        //         return Ok(self.evaluation);
-        //     }
-        // }
+        let zobrist = self.board.zobrist();
+        let hash_entry = self.hash_table.probe(zobrist);
 
-        // Expect the worst
-        let mut cur_evaluation = Evaluation::OpponentCheckmate(0);
-        let mut alpha = alpha;
+        if let Some(entry) = hash_entry {
+            // Only use the cached value if it has sufficient depth
+            // and its bound allows a cutoff with the current window
+            let is_usable = entry.depth >= depth
+                && match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => entry.evaluation.for_opponent() <= beta,
+                    Bound::Upper => entry.evaluation <= alpha,
+                };
 
-        // Expand the node
-        let mut children = self.expand(hash_table);
+            if is_usable {
+                if let Some(mv) = entry.best_move {
+                    self.pv_table.clear(ply + 1);
+                    self.pv_table.update(ply, mv);
+                }
 
-        if children.is_empty() {
-            // Update the evaluation with a more expensive analysis
-            self.evaluation = final_heuristic(self.evaluation, &self.board);
-            return Ok(self.evaluation);
+                return Ok(entry.evaluation);
+            }
         }
 
-        // Search through all moves to find the best option
-        for child in &mut children {
-            let child_eval = child
-                // We have to swap alpha and beta here, because it's the other player's turn
-                .minimax_helper(
-                    depth - 1,
-                    beta,
-                    alpha,
-                    hash_table,
-                    repetition_table,
-                    abort_flags.clone(),
-                );
+        if let Some(tablebase_eval) = self.tablebase_cutoff(depth, alpha, beta) {
+            return Ok(tablebase_eval);
+        }
 
-            // The child inserted their board in the repetition table, remove it again
-            repetition_table.remove(&child.board);
+        let in_check = self.board.in_check();
 
-            // Check if the search has been aborted
-            if let Err(err) = child_eval {
-                self.update_attributes(&children);
-                return Err(err);
+        if depth >= NULL_MOVE_MIN_DEPTH && self.can_pass_turn(ply, in_check, evaluation, beta) {
+            if let Some(null_move_eval) = self.null_move_search(depth, ply, evaluation, beta)? {
+                return Ok(null_move_eval);
             }
+        }
+
+        // Generate all possible moves with their heuristic evaluation
+        let mut moves: Vec<(BitMove, Evaluation)> = self
+            .board
+            .generate_moves()
+            .iter()
+            .map(|mv| {
+                (
+                    *mv,
+                    self.evaluator.move_evaluation(evaluation, &self.board, *mv),
+                )
+            })
+            .collect();
+
+        if moves.is_empty() {
+            // Update the evaluation with a more expensive analysis
+            return Ok(self.evaluator.final_evaluation(evaluation, &self.board));
+        }
+
+        // Only a mate with the next move can still improve alpha, e.g. at the end of a mate search.
+        // Moves without check can't deliver it, they would only fail low
+        let only_checks = alpha >= Evaluation::PlayerCheckmate(3)
+            || (depth == 1 && matches!(alpha, Evaluation::PlayerCheckmate(_)));
+
+        if only_checks {
+            moves.retain(|(mv, _)| self.board.gives_check(*mv));
+        }
+
+        // Order the moves for better alpha beta pruning
+        // The best move of a previous search should be tried first
+        let hash_move = hash_entry.and_then(|entry| entry.best_move);
+        self.move_ordering
+            .order_moves(&self.board, ply, hash_move, &mut moves);
+
+        // Expect the worst
+        let mut cur_evaluation = Evaluation::OpponentCheckmate(0);
+        let mut best_move = None;
+        let original_alpha = alpha;
+        let mut alpha = alpha;
+        // The quiet moves that didn't cause a cutoff
+        let mut searched_quiets = vec![];
+
+        // Search through all moves to find the best option
+        for (index, (mv, child_eval)) in moves.into_iter().enumerate() {
+            self.make_move(mv);
+            let reduction = self.late_move_reduction(depth, index, mv, in_check);
+            let child_eval = self
+                .late_move_search(reduction, depth - 1, ply + 1, child_eval, alpha)
+                .unwrap_or_else(|| {
+                    self.principal_variation_search(
+                        index == 0,
+                        depth - 1,
+                        ply + 1,
+                        child_eval,
+                        alpha,
+                        beta,
+                    )
+                });
+            self.undo_move();
 
             // Convert the evaluation to this player's point of view and take the best value
-            cur_evaluation = cur_evaluation.max(child_eval.unwrap().for_opponent().previous_plie());
+            let evaluation = child_eval?.for_opponent().previous_plie();
+
+            if evaluation > cur_evaluation {
+                cur_evaluation = evaluation;
+                best_move = Some(mv);
+                self.pv_table.update(ply, mv);
+            }
 
             if cur_evaluation.for_opponent() <= beta {
+                // Remember the refutation to try it first in other positions
+                if MoveOrdering::is_quiet(mv) {
+                    self.move_ordering
+                        .update(&self.board, ply, depth, mv, &searched_quiets);
+                }
+
                 // The opponent has a better option in another branch, they won't choose this one
                 break;
             }
 
+            if MoveOrdering::is_quiet(mv) {
+                searched_quiets.push(mv);
+            }
+
             // Update what our current best option is
             alpha = alpha.max(cur_evaluation);
         }
 
-        // Keep depth and size up-to-date
-        self.update_attributes(&children);
-        self.evaluation = cur_evaluation;
-        hash_table.insert(self.board.zobrist(), HashTableEntry::from_node(self));
-        Ok(self.evaluation)
+        if only_checks {
+            // The pruned moves are at most as good as alpha
+            cur_evaluation = cur_evaluation.max(original_alpha);
+        }
+
+        // Remember the result for transpositions and later iterations
+        let bound = if cur_evaluation.for_opponent() <= beta {
+            Bound::Lower
+        } else if cur_evaluation <= original_alpha {
+            Bound::Upper
+        } else {
+            Bound::Exact
+        };
+
+        self.hash_table.store(
+            zobrist,
+            TableEntry {
+                evaluation: cur_evaluation,
+                bound,
+                depth,
+                best_move,
+            },
+        );
+
+        Ok(cur_evaluation)
+    }
+
+    /// Search the position after a move with principal variation search.
+    ///
+    /// The first move is expected to be the best one and is searched with the full window.
+    /// All other moves only have to prove that they are worse with a null window.
+    /// If that fails, they are searched again with the full window.
+    /// See <https://www.chessprogramming.org/Principal_Variation_Search>.
+    ///
+    /// - `alpha`: Minimum value the current player is assured of
+    /// - `beta`: Minimum value the opponent player is assured of
+    ///
+    /// Returns the evaluation from the view of the opponent.
+    fn principal_variation_search(
+        &mut self,
+        is_first_move: bool,
+        depth: usize,
+        ply: usize,
+        evaluation: Evaluation,
+        alpha: Evaluation,
+        beta: Evaluation,
+    ) -> Result<Evaluation, SearchAborted> {
+        if !is_first_move {
+            // The opponent only has to show that they can prevent us from improving alpha
+            let null_window_alpha = alpha.next_better().for_opponent();
+            let child_eval = self.minimax(depth, ply, evaluation, null_window_alpha, alpha);
+            // The child inserted their board in the repetition table, remove it again
+            self.repetition_table.remove(&self.board);
+
+            let child_eval = child_eval?;
+            let evaluation = child_eval.for_opponent().previous_plie();
+
+            if evaluation <= alpha || evaluation.for_opponent() <= beta {
+                // The move is worse than the best move so far or causes a cutoff anyway
+                return Ok(child_eval);
+            }
+        }
+
+        // We have to swap alpha and beta here, because it's the other player's turn
+        let child_eval = self.minimax(depth, ply, evaluation, beta, alpha);
+        // The child inserted their board in the repetition table, remove it again
+        self.repetition_table.remove(&self.board);
+
+        child_eval
+    }
+
+    /// Look up the result of the position in the endgame tablebases.
+    ///
+    /// The tables don't know how many moves have been played without progress,
+    /// so they are only probed directly after a capture or pawn move.
+    ///
+    /// Returns the evaluation for the cutoff, if there is one.
+    fn tablebase_cutoff(
+        &mut self,
+        depth: usize,
+        alpha: Evaluation,
+        beta: Evaluation,
+    ) -> Option<Evaluation> {
+        let options = self.options;
+        let tablebases = options.tablebases.as_ref()?;
+
+        if self.board.rule_50() != 0 {
+            return None;
+        }
+
+        let wdl = tablebases.probe_wdl(&mut self.board)?;
+        self.abort_flags.add_tb_hit();
+
+        // A won position can still be mated faster, a lost position can still be mated
+        let evaluation = wdl.evaluation();
+        let bound = match wdl {
+            Wdl::Win => Bound::Lower,
+            Wdl::Loss => Bound::Upper,
+            _ => Bound::Exact,
+        };
+        let is_cutoff = match bound {
+            Bound::Exact => true,
+            Bound::Lower => evaluation.for_opponent() <= beta,
+            Bound::Upper => evaluation <= alpha,
+        };
+
+        if !is_cutoff {
+            return None;
+        }
+
+        self.hash_table.store(
+            self.board.zobrist(),
+            TableEntry {
+                evaluation,
+                bound,
+                depth: (depth + TABLEBASE_DEPTH_BONUS).min(MAX_PLY - 1),
+                best_move: None,
+            },
+        );
+
+        Some(evaluation)
+    }
+
+    /// Determine if passing the turn can be used to prune the current position.
+    ///
+    /// Not allowed when in check, where passing is illegal, or directly after another null move.
+    /// Positions where the current player only has pawns left are likely zugzwang,
+    /// where passing would be better than every legal move.
+    fn can_pass_turn(
+        &self,
+        ply: usize,
+        in_check: bool,
+        evaluation: Evaluation,
+        beta: Evaluation,
+    ) -> bool {
+        if !self.options.null_move_pruning
+            || in_check
+            || self
+                .null_move_ply
+                .is_some_and(|null_move_ply| null_move_ply + 1 == ply)
+        {
+            return false;
+        }
+
+        // Passing only makes sense if we are already above beta
+        let is_mate_window = matches!(
+            beta,
+            Evaluation::PlayerCheckmate(_) | Evaluation::OpponentCheckmate(_)
+        );
+
+        if evaluation.for_opponent() > beta || is_mate_window {
+            return false;
+        }
+
+        let player = self.board.turn();
+        let pieces = self.board.count_pieces_player(player);
+        let pawns = self.board.count_piece(player, PieceType::P);
+
+        // There must be more than the pawns and the king
+        pieces > pawns + 1
     }
 
-    /// The minimax search algorithm with alpha-beta-pruning.
+    /// Pass the turn and search the position with reduced depth.
+    ///
+    /// If the opponent still can't get below beta, a real move will very likely cause a cutoff too.
+    /// See <https://www.chessprogramming.org/Null_Move_Pruning>.
+    ///
+    /// Returns the evaluation for the cutoff, if there is one.
+    fn null_move_search(
+        &mut self,
+        depth: usize,
+        ply: usize,
+        evaluation: Evaluation,
+        beta: Evaluation,
+    ) -> Result<Option<Evaluation>, SearchAborted> {
+        let reduction = if depth >= DEEP_NULL_MOVE_MIN_DEPTH {
+            DEEP_NULL_MOVE_REDUCTION
+        } else {
+            NULL_MOVE_REDUCTION
+        };
+        let previous_null_move_ply = self.null_move_ply.replace(ply);
+
+        // SAFETY: We are not in check, which is the only case where passing the turn is invalid
+        unsafe {
+            self.board.apply_null_move();
+        }
+        self.evaluator.make_null_move();
+        // The opponent only has to show that they can get to beta
+        let child_eval = self.minimax(
+            depth.saturating_sub(reduction + 1),
+            ply + 1,
+            evaluation.for_opponent(),
+            beta,
+            beta.next_better().for_opponent(),
+        );
+        // The child inserted their board in the repetition table, remove it again
+        self.repetition_table.remove(&self.board);
+        unsafe {
+            self.board.undo_null_move();
+        }
+        self.evaluator.undo_move();
+
+        self.null_move_ply = previous_null_move_ply;
+
+        let null_move_eval = child_eval?.for_opponent().previous_plie();
+
+        if null_move_eval.for_opponent() > beta {
+            return Ok(None);
+        }
+
+        if null_move_eval.is_game_over() {
+            // Mates after passing the turn are not proven, only return the cutoff value
+            Ok(Some(beta.for_opponent()))
+        } else {
+            Ok(Some(null_move_eval))
+        }
+    }
+
+    /// The depth reduction for the move that has just been made.
+    ///
+    /// Quiet moves late in the move ordering are unlikely to be the best move,
+    /// so they are searched with reduced depth first.
+    /// Captures, promotions, checks and check evasions are always searched with the full depth.
+    fn late_move_reduction(
+        &self,
+        depth: usize,
+        index: usize,
+        mv: BitMove,
+        in_check: bool,
+    ) -> usize {
+        if !self.options.late_move_reductions
+            || depth < LATE_MOVE_MIN_DEPTH
+            || index < LATE_MOVE_MIN_INDEX
+            || in_check
+            || mv.is_capture()
+            || mv.is_promo()
+            || self.board.in_check()
+        {
+            return 0;
+        }
+
+        if index >= VERY_LATE_MOVE_MIN_INDEX && depth >= VERY_LATE_MOVE_MIN_DEPTH {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Search a late move with reduced depth and a null window.
+    ///
+    /// See <https://www.chessprogramming.org/Late_Move_Reductions>.
+    ///
+    /// Returns `None` if the move is not reduced or if it might be better than the best move so far.
+    /// Then it has to be searched again with the full depth.
+    fn late_move_search(
+        &mut self,
+        reduction: usize,
+        depth: usize,
+        ply: usize,
+        evaluation: Evaluation,
+        alpha: Evaluation,
+    ) -> Option<Result<Evaluation, SearchAborted>> {
+        if reduction == 0 {
+            return None;
+        }
+
+        let child_eval = self.minimax(
+            depth.saturating_sub(reduction),
+            ply,
+            evaluation,
+            alpha.next_better().for_opponent(),
+            alpha,
+        );
+        // The child inserted their board in the repetition table, remove it again
+        self.repetition_table.remove(&self.board);
+
+        match child_eval {
+            Ok(child_eval) if child_eval.for_opponent().previous_plie() > alpha => None,
+            result => Some(result),
+        }
+    }
+}
+
+impl Node {
+    /// Continue the best line with the best moves stored in the transposition table.
+    ///
+    /// Cutoffs from the transposition table end the best line early,
+    /// even if the position has been searched deeper.
+    fn extend_best_line(&mut self, depth: usize, hash_table: &TranspositionTable) {
+        let mut board = self.board.clone();
+
+        for mv in &self.best_line {
+            board.apply_move(*mv);
+        }
+
+        while self.best_line.len() < depth {
+            let hash_move = hash_table
+                .probe(board.zobrist())
+                .and_then(|entry| entry.best_move);
+
+            match hash_move {
+                // Make sure that the move wasn't stored for another position
+                Some(mv) if board.generate_moves().contains(&mv) => {
+                    board.apply_move(mv);
+                    self.best_line.push(mv);
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// The minimax search algorithm with alpha-beta-pruning, limited to the given window.
     ///
     /// See https://en.wikipedia.org/wiki/Alpha%E2%80%93beta_pruning.
-    pub fn minimax(
+    ///
+    /// - `alpha`: Minimum value the current player is assured of
+    /// - `beta`: Minimum value the opponent player is assured of
+    ///
+    /// If the evaluation is outside of the window, it is only a bound of the real evaluation.
+    pub(super) fn minimax(
         &mut self,
         depth: usize,
-        hash_table: &mut HashTable,
+        alpha: Evaluation,
+        beta: Evaluation,
+        shared: &SharedSearch,
         repetition_table: &mut RepetitionTable,
-        abort_flags: AbortFlags,
+        worker: &mut Worker,
     ) -> Result<Evaluation, SearchAborted> {
-        self.minimax_helper(
-            depth,
-            Evaluation::OpponentCheckmate(0),
-            Evaluation::OpponentCheckmate(0),
-            hash_table,
+        let mut search = Search::new(
+            self.board.clone(),
+            shared.hash_table,
             repetition_table,
-            abort_flags,
-        )
+            worker.abort_flags.clone(),
+            shared.options,
+            &mut worker.move_ordering,
+            &mut worker.pawn_table,
+        );
+        let result = search.minimax(depth, 0, self.evaluation, alpha, beta);
+
+        // Keep the attributes up-to-date
+        self.size = search.size;
+        self.depth = depth;
+        self.sel_depth = search.sel_depth;
+        self.best_line = search.pv_table.line(0);
+        self.evaluation = result?;
+        self.extend_best_line(depth, shared.hash_table);
+
+        Ok(self.evaluation)
     }
 }
 
@@ -132,23 +557,40 @@ mod test {
     use pleco::Board;
 
     use crate::stonefish::{
-        abort_flags::AbortFlags,
-        evaluation::Evaluation,
-        node::{minimax::HashTable, Node},
+        abort_flags::AbortFlags, evaluation::Evaluation, heuristic::PawnHashTable, node::Node,
+        search_options::SearchOptions, transposition_table::TranspositionTable,
         types::RepetitionTable,
     };
 
+    use super::{MoveOrdering, Search, SearchAborted, SharedSearch, Worker};
+
+    /// Search the given position to the given depth with the full window.
+    fn minimax(
+        node: &mut Node,
+        depth: usize,
+        options: &SearchOptions,
+    ) -> Result<Evaluation, SearchAborted> {
+        node.minimax(
+            depth,
+            Evaluation::OpponentCheckmate(0),
+            Evaluation::OpponentCheckmate(0),
+            &SharedSearch::new(
+                options,
+                &TranspositionTable::new(1),
+                &RepetitionTable::new(),
+                &None,
+            ),
+            &mut RepetitionTable::new(),
+            &mut Worker::new(0, AbortFlags::new()),
+        )
+    }
+
     #[test]
     fn should_find_mate_in_one_opponent() {
         // Mate in 1 (0 plies)
         let board = Board::from_fen("3Q1k2/5p1p/p3p2P/3p4/8/2Pq2P1/1P3PK1/8 b - - 2 37").unwrap();
         let mut node = Node::new(board);
-        let actual = node.minimax(
-            0,
-            &mut HashTable::new(),
-            &mut RepetitionTable::new(),
-            AbortFlags::new(),
-        );
+        let actual = minimax(&mut node, 0, &SearchOptions::default());
         let expected = Ok(Evaluation::OpponentCheckmate(0));
 
         assert_eq!(actual, expected);
@@ -159,12 +601,7 @@ mod test {
         // Mate in 1 (1 plie)
         let board = Board::from_fen("5k2/5p1p/p3p2P/3p2Q1/8/2Pq2P1/1P3PK1/8 w - - 1 37").unwrap();
         let mut node = Node::new(board);
-        let actual = node.minimax(
-            1,
-            &mut HashTable::new(),
-            &mut RepetitionTable::new(),
-            AbortFlags::new(),
-        );
+        let actual = minimax(&mut node, 1, &SearchOptions::default());
         let expected = Ok(Evaluation::PlayerCheckmate(1));
 
         assert_eq!(actual, expected);
@@ -175,12 +612,7 @@ mod test {
         // Mate in 2 (2 plies)
         let board = Board::from_fen("8/8/1r3p2/1p6/p5kR/2rB2P1/5P1K/8 b - - 21 47").unwrap();
         let mut node = Node::new(board);
-        let actual = node.minimax(
-            2,
-            &mut HashTable::new(),
-            &mut RepetitionTable::new(),
-            AbortFlags::new(),
-        );
+        let actual = minimax(&mut node, 2, &SearchOptions::default());
         let expected = Ok(Evaluation::OpponentCheckmate(2));
 
         assert_eq!(actual, expected);
@@ -191,12 +623,7 @@ mod test {
         // Mate in 2 (3 plies)
         let board = Board::from_fen("8/7R/1r3p2/1p6/p5k1/2rB2P1/5P1K/8 w - - 20 47").unwrap();
         let mut node = Node::new(board);
-        let actual = node.minimax(
-            3,
-            &mut HashTable::new(),
-            &mut RepetitionTable::new(),
-            AbortFlags::new(),
-        );
+        let actual = minimax(&mut node, 3, &SearchOptions::default());
         let expected = Ok(Evaluation::PlayerCheckmate(3));
 
         assert_eq!(actual, expected);
@@ -208,14 +635,59 @@ mod test {
         let board =
             Board::from_fen("6k1/pp4pp/4p3/3p4/1P1qn3/N3Q3/P2B2PP/2r3K1 w - - 0 21").unwrap();
         let mut node = Node::new(board);
-        let actual = node.minimax(
-            4,
-            &mut HashTable::new(),
-            &mut RepetitionTable::new(),
-            AbortFlags::new(),
-        );
+        let actual = minimax(&mut node, 4, &SearchOptions::default());
         let expected = Ok(Evaluation::OpponentCheckmate(4));
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn should_only_pass_turn_without_zugzwang_risk() {
+        let hash_table = TranspositionTable::new(1);
+        let mut repetition_table = RepetitionTable::new();
+        let options = SearchOptions::default();
+        let mut move_ordering = MoveOrdering::new();
+        let mut pawn_table = PawnHashTable::new();
+        let beta = Evaluation::Centipawns(0);
+
+        let mut can_pass_turn = |fen: &str| {
+            let board = Board::from_fen(fen).unwrap();
+            let search = Search::new(
+                board,
+                &hash_table,
+                &mut repetition_table,
+                AbortFlags::new(),
+                &options,
+                &mut move_ordering,
+                &mut pawn_table,
+            );
+            let in_check = search.board.in_check();
+            search.can_pass_turn(1, in_check, Evaluation::Centipawns(100), beta)
+        };
+
+        // White has a rook left
+        assert!(can_pass_turn("4k3/4p3/8/8/8/8/4P3/R3K3 w - - 0 1"));
+        // White only has pawns left
+        assert!(!can_pass_turn("4k3/4p3/8/8/8/8/4P3/4K3 w - - 0 1"));
+        // White is in check
+        assert!(!can_pass_turn("4k3/8/8/8/8/8/4r3/R3K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn should_find_mate_in_three_without_selectivity() {
+        // Mate in 3 (5 plies), see https://wtharvey.com/m8n3.txt
+        let board =
+            Board::from_fen("r1b1kb1r/pppp1ppp/5q2/4n3/3KP3/2N3PN/PPP4P/R1BQ1B1R b kq - 0 1")
+                .unwrap();
+        let options = SearchOptions {
+            null_move_pruning: false,
+            late_move_reductions: false,
+            ..Default::default()
+        };
+        let mut node = Node::new(board);
+        let actual = minimax(&mut node, 5, &options);
+        let expected = Ok(Evaluation::PlayerCheckmate(5));
+
+        assert_eq!(actual, expected);
+    }
 }
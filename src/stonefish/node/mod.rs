@@ -2,13 +2,20 @@ use pleco::{BitMove, Board};
 
 use super::{
     evaluation::Evaluation,
-    heuristic::move_heuristic,
-    types::{Children, HashTable, HashTableEntry, Line},
+    heuristic::{
+        initial_heuristic, move_heuristic, piece_square_score, static_exchange_evaluation,
+        EvalParameters, PawnHashTable, TaperedScore,
+    },
+    transposition_table::TranspositionTable,
+    types::{Children, Line},
 };
 
 mod info;
 mod iterative_deepening;
 mod minimax;
+mod move_ordering;
+mod quiescence;
+mod search;
 
 /// A node of a search tree.
 #[derive(Debug, Clone)]
@@ -33,12 +40,12 @@ pub struct Node {
     pub sel_depth: usize,
 }
 
-use crate::stonefish::heuristic::initial_heuristic;
-
 impl Node {
     /// Create a new node with move order heuristic.
+    ///
+    /// The position is evaluated with the default parameters, until the node is reset.
     pub fn new(state: Board) -> Self {
-        let evaluation = initial_heuristic(&state);
+        let evaluation = initial_heuristic(&EvalParameters::default(), &state);
 
         Self {
             board: state,
@@ -61,10 +68,26 @@ impl Node {
     }
 
     /// Create a new node from a given move.
-    pub fn new_from_move(old_eval: Evaluation, old_board: &Board, mv: BitMove) -> Self {
+    ///
+    /// The piece squares are the sum of the piece positions of the old board.
+    pub fn new_from_move(
+        parameters: &EvalParameters,
+        pawn_table: &mut PawnHashTable,
+        piece_squares: TaperedScore,
+        old_eval: Evaluation,
+        old_board: &Board,
+        mv: BitMove,
+    ) -> Self {
+        let evaluation = move_heuristic(
+            parameters,
+            pawn_table,
+            piece_squares,
+            old_eval,
+            old_board,
+            mv,
+        );
         let mut board = old_board.clone();
         board.apply_move(mv);
-        let evaluation = move_heuristic(old_eval, old_board, mv, &board);
 
         Self {
             board,
@@ -76,9 +99,9 @@ impl Node {
         }
     }
 
-    /// Reset the evaluation of the node.
-    pub fn reset(&mut self) -> &mut Self {
-        self.evaluation = initial_heuristic(&self.board);
+    /// Reset the evaluation of the node with the given parameters.
+    pub fn reset(&mut self, parameters: &EvalParameters) -> &mut Self {
+        self.evaluation = initial_heuristic(parameters, &self.board);
         self.depth = 0;
         self.sel_depth = 0;
         self.size = 0;
@@ -92,11 +115,13 @@ impl Node {
     pub fn update_attributes(&mut self, children: &Children) {
         let mut size: usize = 1;
         let mut depth: usize = 0;
+        let mut sel_depth: usize = 0;
         let mut best_child: Option<&Node> = None;
 
         for child in children {
             size += child.size;
             depth = depth.max(child.depth + 1);
+            sel_depth = sel_depth.max(child.sel_depth + 1);
 
             best_child = if let Some(prev_best) = best_child {
                 // The child eval is out of the perspective from the opponent, so worse is better for us
@@ -126,35 +151,58 @@ impl Node {
             self.best_line = vec![];
         }
 
-        self.sel_depth = self.best_line.len();
+        // The quiescence search can go deeper than the best line
+        self.sel_depth = sel_depth.max(self.best_line.len());
     }
 
     /// Expands this node.
     ///
-    /// This will generate all children of this node.
-    pub fn expand(&mut self, hash_table: &HashTable) -> Children {
+    /// This will generate all children of this node, evaluated with the given parameters.
+    pub fn expand(
+        &mut self,
+        parameters: &EvalParameters,
+        pawn_table: &mut PawnHashTable,
+        hash_table: &TranspositionTable,
+    ) -> Children {
+        let piece_squares = piece_square_score(parameters, &self.board);
         let mut children: Children = self
             .board
             // Generate all possible moves
             .generate_moves()
             .iter()
             // Create a new child for each move
-            .map(|mv| Node::new_from_move(self.evaluation, &self.board, *mv))
+            .map(|mv| {
+                Node::new_from_move(
+                    parameters,
+                    pawn_table,
+                    piece_squares,
+                    self.evaluation,
+                    &self.board,
+                    *mv,
+                )
+            })
             .collect();
 
+        // The best move of a previous search should be tried first
+        let hash_move = hash_table
+            .probe(self.board.zobrist())
+            .and_then(|entry| entry.best_move);
+
         // Order the moves for better alpha beta pruning
         // If any cache value is available, take that for better accuracy
-        children.sort_unstable_by_key(|child| {
-            if let Some(HashTableEntry {
-                evaluation: cache_eval,
-                best_line: _,
-                depth: _,
-            }) = hash_table.get(&child.board.zobrist())
-            {
-                *cache_eval
+        children.sort_by_cached_key(|child| {
+            let mv = child.board.last_move().unwrap();
+            let is_hash_move = Some(mv) == hash_move;
+            let evaluation = if let Some(entry) = hash_table.probe(child.board.zobrist()) {
+                entry.evaluation
             } else {
                 child.evaluation
-            }
+            };
+            // Captures that lose material in the exchange are tried last
+            let is_losing_capture =
+                mv.is_capture() && static_exchange_evaluation(&self.board, mv) < 0;
+
+            (!is_hash_move, is_losing_capture, evaluation)
         });
 
         // Important: Keep attributes up-to-date
@@ -188,7 +236,12 @@ impl PartialEq for Node {
 mod tests {
     use pleco::Board;
 
-    use crate::stonefish::{evaluation::Evaluation, node::Node, types::HashTable};
+    use crate::stonefish::{
+        evaluation::Evaluation,
+        heuristic::{EvalParameters, PawnHashTable},
+        node::Node,
+        transposition_table::TranspositionTable,
+    };
 
     #[test]
     fn should_expand_startpos() {
@@ -199,7 +252,11 @@ mod tests {
         assert_eq!(startpos.sel_depth, 0);
         assert_eq!(startpos.best_line.len(), 0);
 
-        let children = startpos.expand(&HashTable::new());
+        let children = startpos.expand(
+            &EvalParameters::default(),
+            &mut PawnHashTable::new(),
+            &TranspositionTable::new(1),
+        );
 
         for child in children {
             assert_eq!(child.size, 1);
@@ -227,7 +284,11 @@ mod tests {
         assert_eq!(pos.best_line.len(), 0);
         assert_eq!(pos.evaluation, Evaluation::OpponentCheckmate(0));
 
-        let children = pos.expand(&HashTable::new());
+        let children = pos.expand(
+            &EvalParameters::default(),
+            &mut PawnHashTable::new(),
+            &TranspositionTable::new(1),
+        );
         assert_eq!(children.len(), 0);
 
         assert_eq!(pos.depth, 0);
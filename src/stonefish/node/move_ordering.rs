@@ -0,0 +1,295 @@
+use pleco::{BitMove, Board};
+
+use crate::stonefish::{evaluation::Evaluation, heuristic::static_exchange_evaluation};
+
+use super::search::MAX_PLY;
+
+/// The number of killer moves that are remembered per ply.
+const KILLER_SLOTS: usize = 2;
+/// The maximum absolute value of a history score.
+const MAX_HISTORY: i32 = 16384;
+/// The maximum history bonus for a single cutoff.
+const MAX_HISTORY_BONUS: i32 = 1024;
+
+/// The categories of the move ordering, the moves are searched in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MoveCategory {
+    /// The best move of a previous search of the position.
+    HashMove,
+    /// Captures that don't lose material and promotions.
+    GoodCapture,
+    /// A quiet move that caused a cutoff at the same ply.
+    Killer,
+    /// The quiet move that refuted the previous move in another position.
+    CounterMove,
+    /// All other quiet moves.
+    Quiet,
+    /// Captures that lose material in the exchange.
+    LosingCapture,
+}
+
+/// Tables to order the quiet moves of the search, learned from previous beta cutoffs.
+///
+/// See <https://www.chessprogramming.org/Killer_Heuristic>,
+/// <https://www.chessprogramming.org/History_Heuristic>
+/// and <https://www.chessprogramming.org/Countermove_Heuristic>.
+pub struct MoveOrdering {
+    /// Quiet moves that caused a cutoff at the same ply, the most recent first.
+    killers: Vec<[BitMove; KILLER_SLOTS]>,
+    /// How often quiet moves caused a cutoff, indexed by player, source and destination square.
+    history: Vec<[[i32; 64]; 64]>,
+    /// The quiet move that refuted a move, indexed by its source and destination square.
+    counter_moves: Vec<[BitMove; 64]>,
+}
+
+impl MoveOrdering {
+    /// Create new, empty move ordering tables.
+    pub fn new() -> Self {
+        Self {
+            killers: vec![[BitMove::null(); KILLER_SLOTS]; MAX_PLY],
+            history: vec![[[0; 64]; 64]; 2],
+            counter_moves: vec![[BitMove::null(); 64]; 64],
+        }
+    }
+
+    /// Determine if the move is ordered by the tables.
+    ///
+    /// Captures and promotions are ordered by their static exchange evaluation instead.
+    pub fn is_quiet(mv: BitMove) -> bool {
+        !mv.is_capture() && !mv.is_promo()
+    }
+
+    /// The history score of a quiet move for the current player.
+    fn history_score(&self, board: &Board, mv: BitMove) -> i32 {
+        self.history[board.turn() as usize][mv.get_src().0 as usize][mv.get_dest().0 as usize]
+    }
+
+    /// The move that refuted the last move in another position.
+    fn counter_move(&self, board: &Board) -> Option<BitMove> {
+        let last_move = board.last_move().filter(|mv| !mv.is_null())?;
+        let counter_move =
+            self.counter_moves[last_move.get_src().0 as usize][last_move.get_dest().0 as usize];
+
+        (!counter_move.is_null()).then_some(counter_move)
+    }
+
+    /// Sort the moves of the position, so that the best moves are searched first.
+    ///
+    /// The evaluations are the heuristic evaluations of the moves, from the view of the opponent.
+    pub fn order_moves(
+        &self,
+        board: &Board,
+        ply: usize,
+        hash_move: Option<BitMove>,
+        moves: &mut [(BitMove, Evaluation)],
+    ) {
+        let killers = &self.killers[ply];
+        let counter_move = self.counter_move(board);
+
+        moves.sort_by_cached_key(|(mv, evaluation)| {
+            let exchange_value = if Self::is_quiet(*mv) {
+                0
+            } else {
+                static_exchange_evaluation(board, *mv)
+            };
+
+            let category = if Some(*mv) == hash_move {
+                MoveCategory::HashMove
+            } else if !Self::is_quiet(*mv) && (exchange_value >= 0 || mv.is_promo()) {
+                MoveCategory::GoodCapture
+            } else if !Self::is_quiet(*mv) {
+                MoveCategory::LosingCapture
+            } else if killers.contains(mv) {
+                MoveCategory::Killer
+            } else if Some(*mv) == counter_move {
+                MoveCategory::CounterMove
+            } else {
+                MoveCategory::Quiet
+            };
+
+            // The captures are sorted by the material they win,
+            // the killers by recency and the other quiet moves by their history
+            let score = match category {
+                MoveCategory::GoodCapture | MoveCategory::LosingCapture => -exchange_value,
+                MoveCategory::Killer => {
+                    killers.iter().position(|killer| killer == mv).unwrap() as i32
+                }
+                MoveCategory::Quiet => -self.history_score(board, *mv),
+                _ => 0,
+            };
+
+            (category, score, *evaluation)
+        });
+    }
+
+    /// Remember a quiet move that caused a beta cutoff.
+    ///
+    /// The quiet moves that have been searched before it without a cutoff are penalized.
+    /// The board is the position before the move.
+    pub fn update(
+        &mut self,
+        board: &Board,
+        ply: usize,
+        depth: usize,
+        mv: BitMove,
+        searched_quiets: &[BitMove],
+    ) {
+        // Remember the move as killer of this ply
+        let killers = &mut self.killers[ply];
+
+        if killers[0] != mv {
+            killers.rotate_right(1);
+            killers[0] = mv;
+        }
+
+        // Deeper cutoffs are more valuable
+        let bonus = (depth * depth).min(MAX_HISTORY_BONUS as usize) as i32;
+        let history = &mut self.history[board.turn() as usize];
+
+        Self::update_history(history, mv, bonus);
+
+        for quiet in searched_quiets {
+            Self::update_history(history, *quiet, -bonus);
+        }
+
+        // Remember the move as refutation of the opponent's last move
+        if let Some(last_move) = board.last_move().filter(|mv| !mv.is_null()) {
+            self.counter_moves[last_move.get_src().0 as usize][last_move.get_dest().0 as usize] =
+                mv;
+        }
+    }
+
+    /// Add the bonus to the history score of the move.
+    ///
+    /// The score is dampened when it gets close to the limits,
+    /// so that it stays in range and new cutoffs still have an effect.
+    fn update_history(history: &mut [[i32; 64]; 64], mv: BitMove, bonus: i32) {
+        let score = &mut history[mv.get_src().0 as usize][mv.get_dest().0 as usize];
+        *score += bonus - *score * bonus.abs() / MAX_HISTORY;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use pleco::{BitMove, Board};
+
+    use crate::stonefish::{
+        evaluation::Evaluation,
+        evaluator::EvaluatorType,
+        heuristic::{EvalParameters, PawnHashTable},
+    };
+
+    use super::MoveOrdering;
+
+    /// Order the moves of the position and convert them to UCI notation.
+    fn ordered_moves(
+        move_ordering: &MoveOrdering,
+        board: &Board,
+        ply: usize,
+        hash_move: Option<BitMove>,
+    ) -> Vec<String> {
+        let parameters = Arc::new(EvalParameters::default());
+        let mut pawn_table = PawnHashTable::new();
+        let mut evaluator =
+            EvaluatorType::Handcrafted.create(board, &parameters, None, &mut pawn_table);
+        let mut moves: Vec<(BitMove, Evaluation)> = board
+            .generate_moves()
+            .iter()
+            .map(|mv| {
+                let evaluation = evaluator.move_evaluation(Evaluation::Centipawns(0), board, *mv);
+                (*mv, evaluation)
+            })
+            .collect();
+
+        move_ordering.order_moves(board, ply, hash_move, &mut moves);
+        moves.iter().map(|(mv, _)| mv.stringify()).collect()
+    }
+
+    fn find_move(board: &Board, uci_move: &str) -> BitMove {
+        *board
+            .generate_moves()
+            .iter()
+            .find(|mv| mv.stringify() == uci_move)
+            .unwrap()
+    }
+
+    #[test]
+    fn should_order_hash_move_and_captures_first() {
+        // White can capture the knight on e5
+        let board =
+            Board::from_fen("r1bqkb1r/pppp1ppp/2n5/4n3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 0 5")
+                .unwrap();
+        let move_ordering = MoveOrdering::new();
+        let hash_move = find_move(&board, "d2d4");
+
+        let moves = ordered_moves(&move_ordering, &board, 1, Some(hash_move));
+
+        assert_eq!(moves[0], "d2d4");
+        assert_eq!(moves[1], "f3e5");
+    }
+
+    #[test]
+    fn should_order_losing_captures_last() {
+        // The pawn on d5 is defended
+        let board = Board::from_fen("4k3/8/2p5/3p4/8/4N3/8/4K3 w - - 0 1").unwrap();
+        let move_ordering = MoveOrdering::new();
+
+        let moves = ordered_moves(&move_ordering, &board, 1, None);
+
+        assert_eq!(moves.last().unwrap(), "e3d5");
+    }
+
+    #[test]
+    fn should_order_killers_before_other_quiet_moves() {
+        let board = Board::start_pos();
+        let mut move_ordering = MoveOrdering::new();
+        let first_killer = find_move(&board, "a2a3");
+        let second_killer = find_move(&board, "h2h3");
+
+        move_ordering.update(&board, 1, 1, first_killer, &[]);
+        move_ordering.update(&board, 1, 1, second_killer, &[]);
+        move_ordering.history = vec![[[0; 64]; 64]; 2];
+
+        // The most recent killer is tried first
+        let moves = ordered_moves(&move_ordering, &board, 1, None);
+        assert_eq!(moves[..2], ["h2h3", "a2a3"]);
+
+        // Killers are only used at the same ply
+        let moves = ordered_moves(&move_ordering, &board, 2, None);
+        assert!(!moves[..2].contains(&"h2h3".to_string()));
+        assert!(!moves[..2].contains(&"a2a3".to_string()));
+    }
+
+    #[test]
+    fn should_order_quiet_moves_by_history() {
+        let board = Board::start_pos();
+        let mut move_ordering = MoveOrdering::new();
+        let good_move = find_move(&board, "b1c3");
+        let bad_move = find_move(&board, "e2e4");
+
+        // The bad move was searched before the good move caused a cutoff
+        move_ordering.update(&board, 5, 4, good_move, &[bad_move]);
+
+        let moves = ordered_moves(&move_ordering, &board, 1, None);
+        assert_eq!(moves[0], "b1c3");
+        assert_eq!(moves.last().unwrap(), "e2e4");
+    }
+
+    #[test]
+    fn should_order_counter_move_after_killers() {
+        let mut board = Board::start_pos();
+        assert!(board.apply_uci_move("e2e4"));
+        let mut move_ordering = MoveOrdering::new();
+        let counter_move = find_move(&board, "c7c5");
+
+        // The move refuted e2e4 at another ply
+        move_ordering.update(&board, 3, 1, counter_move, &[]);
+        move_ordering.killers[3] = [BitMove::null(); 2];
+        move_ordering.history = vec![[[0; 64]; 64]; 2];
+
+        let moves = ordered_moves(&move_ordering, &board, 1, None);
+        assert_eq!(moves[0], "c7c5");
+    }
+}
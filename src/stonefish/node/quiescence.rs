@@ -0,0 +1,268 @@
+use pleco::BitMove;
+
+use crate::stonefish::{
+    abort_flags::SearchAborted, evaluation::Evaluation, heuristic::static_exchange_evaluation,
+};
+
+use super::search::{Search, MAX_PLY};
+
+/// The number of quiescence plies in which quiet checks are searched as well.
+///
+/// Set this to 0 to only search captures and promotions.
+const CHECK_PLIES: usize = 1;
+
+/// The safety margin for delta pruning, in centipawns.
+///
+/// Captures that can't raise the evaluation above alpha even with this margin are skipped.
+const DELTA_MARGIN: i32 = 200;
+
+impl Search<'_> {
+    /// The quiescence search algorithm.
+    ///
+    /// Resolves captures, promotions and checks until the position is quiet,
+    /// so that the evaluation isn't taken in the middle of a capture sequence.
+    pub(super) fn quiescence(
+        &mut self,
+        ply: usize,
+        evaluation: Evaluation,
+        alpha: Evaluation,
+        beta: Evaluation,
+    ) -> Result<Evaluation, SearchAborted> {
+        let size = self.size;
+        let evaluation = self.quiescence_helper(ply, 0, evaluation, alpha, beta);
+
+        // Count the nodes at once to avoid contention between the search threads
+        self.abort_flags.add_nodes(self.size - size);
+
+        // Check if the search has been aborted
+        self.abort_flags.check()?;
+
+        Ok(evaluation)
+    }
+
+    /// The implementation of the quiescence search.
+    ///
+    /// - `ply`: The number of moves made since the start of the search
+    /// - `q_ply`: The number of quiescence plies that have already been searched
+    /// - `evaluation`: The heuristic evaluation of the current position
+    /// - `alpha`: Minimum value the current player is assured of
+    /// - `beta`: Minimum value the opponent player is assured of
+    fn quiescence_helper(
+        &mut self,
+        ply: usize,
+        q_ply: usize,
+        evaluation: Evaluation,
+        alpha: Evaluation,
+        beta: Evaluation,
+    ) -> Evaluation {
+        self.size += 1;
+        self.sel_depth = self.sel_depth.max(ply);
+        self.pv_table.clear(ply);
+
+        let moves = self.board.generate_moves();
+        let in_check = self.board.in_check();
+
+        if moves.is_empty() {
+            return if in_check {
+                // The player got checkmated
+                Evaluation::OpponentCheckmate(0)
+            } else {
+                Evaluation::Draw
+            };
+        }
+
+        if ply >= MAX_PLY - 1 {
+            // We can't go any deeper
+            return evaluation;
+        }
+
+        let mut alpha = alpha;
+
+        // When in check, every move has to be considered as there is no quiet alternative.
+        // Otherwise the player can decide to not make a capture, so the current evaluation
+        // is a lower bound for the real evaluation ("stand pat")
+        let mut cur_evaluation = if in_check {
+            Evaluation::OpponentCheckmate(0)
+        } else {
+            let static_evaluation = self.evaluator.static_evaluation(evaluation, &self.board);
+
+            if static_evaluation.for_opponent() <= beta {
+                // The opponent won't allow us to reach this position
+                return static_evaluation;
+            }
+
+            alpha = alpha.max(static_evaluation);
+            static_evaluation
+        };
+
+        let mut moves: Vec<(BitMove, bool, Evaluation)> = moves
+            .iter()
+            .filter_map(|mv| {
+                let is_tactical = mv.is_capture() || mv.is_promo();
+
+                if !in_check && !is_tactical && q_ply >= CHECK_PLIES {
+                    return None;
+                }
+
+                let gives_check = self.board.gives_check(*mv);
+
+                if !in_check && !is_tactical && !gives_check {
+                    // Quiet moves are only considered when they give check
+                    return None;
+                }
+
+                if !in_check
+                    && !gives_check
+                    && mv.is_capture()
+                    && !mv.is_promo()
+                    && static_exchange_evaluation(&self.board, *mv) < 0
+                {
+                    // Captures that lose material in the exchange won't improve the position
+                    return None;
+                }
+
+                let child_eval = self.evaluator.move_evaluation(evaluation, &self.board, *mv);
+                Some((*mv, gives_check, child_eval))
+            })
+            .collect();
+
+        // Look at the most promising moves first
+        moves.sort_unstable_by_key(|(_, _, child_eval)| *child_eval);
+        let delta_pruning = !in_check && self.evaluator.estimates_static_evaluation();
+
+        for (mv, gives_check, child_eval) in moves {
+            // Delta pruning: Skip captures that can't improve the position enough
+            if delta_pruning && !gives_check {
+                if let Evaluation::Centipawns(value) = child_eval.for_opponent() {
+                    if Evaluation::Centipawns(value + DELTA_MARGIN) <= alpha {
+                        continue;
+                    }
+                }
+            }
+
+            self.make_move(mv);
+            // We have to swap alpha and beta here, because it's the other player's turn
+            let child_eval = self.quiescence_helper(ply + 1, q_ply + 1, child_eval, beta, alpha);
+            self.undo_move();
+
+            // Convert the evaluation to this player's point of view and take the best value
+            cur_evaluation = cur_evaluation.max(child_eval.for_opponent().previous_plie());
+
+            if cur_evaluation.for_opponent() <= beta {
+                // The opponent has a better option in another branch, they won't choose this one
+                break;
+            }
+
+            // Update what our current best option is
+            alpha = alpha.max(cur_evaluation);
+        }
+
+        cur_evaluation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc,
+    };
+
+    use pleco::Board;
+
+    use crate::stonefish::{
+        abort_flags::{AbortFlags, SearchAborted},
+        evaluation::Evaluation,
+        heuristic::{static_heuristic, EvalParameters},
+        node::{
+            iterative_deepening::{SharedSearch, Worker},
+            Node,
+        },
+        search_options::SearchOptions,
+        transposition_table::TranspositionTable,
+        types::RepetitionTable,
+    };
+
+    /// Run the quiescence search on the given position.
+    fn quiescence(node: &mut Node) -> Evaluation {
+        node.minimax(
+            0,
+            Evaluation::OpponentCheckmate(0),
+            Evaluation::OpponentCheckmate(0),
+            &SharedSearch::new(
+                &SearchOptions::default(),
+                &TranspositionTable::new(1),
+                &RepetitionTable::new(),
+                &None,
+            ),
+            &mut RepetitionTable::new(),
+            &mut Worker::new(0, AbortFlags::new()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn should_capture_hanging_piece() {
+        // White can take the undefended queen
+        let mut node = Node::new(Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap());
+        assert!(node.evaluation < Evaluation::Centipawns(0));
+
+        let evaluation = quiescence(&mut node);
+
+        assert!(evaluation > Evaluation::Centipawns(0), "{evaluation:?}");
+        assert!(node.sel_depth >= 1);
+        assert!(node.size > 1);
+    }
+
+    #[test]
+    fn should_not_capture_defended_piece() {
+        // Taking the pawn with the queen loses the queen
+        let mut node = Node::new(Board::from_fen("4k3/8/2p5/3p4/8/8/3Q4/4K3 w - - 0 1").unwrap());
+        let static_eval =
+            static_heuristic(&EvalParameters::default(), node.evaluation, &node.board);
+
+        let evaluation = quiescence(&mut node);
+
+        assert_eq!(evaluation, static_eval);
+    }
+
+    #[test]
+    fn should_recognize_checkmate() {
+        let mut node = Node::new(
+            Board::from_fen("3Q1k2/5p1p/p3p2P/3p4/8/2Pq2P1/1P3PK1/8 b - - 2 37").unwrap(),
+        );
+
+        let evaluation = quiescence(&mut node);
+
+        assert_eq!(evaluation, Evaluation::OpponentCheckmate(0));
+    }
+
+    #[test]
+    fn should_abort_when_node_limit_is_reached() {
+        // The capture sequence needs more than a single node
+        let mut node = Node::new(Board::from_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1").unwrap());
+        let abort_flags = AbortFlags::from_flags(
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicUsize::new(0)),
+            Some(1),
+            Arc::new(AtomicUsize::new(0)),
+        );
+
+        let result = node.minimax(
+            0,
+            Evaluation::OpponentCheckmate(0),
+            Evaluation::OpponentCheckmate(0),
+            &SharedSearch::new(
+                &SearchOptions::default(),
+                &TranspositionTable::new(1),
+                &RepetitionTable::new(),
+                &None,
+            ),
+            &mut RepetitionTable::new(),
+            &mut Worker::new(0, abort_flags),
+        );
+
+        assert_eq!(result, Err(SearchAborted));
+    }
+}
@@ -0,0 +1,159 @@
+use pleco::{BitMove, Board};
+
+use crate::stonefish::{
+    abort_flags::AbortFlags,
+    evaluator::Evaluator,
+    heuristic::PawnHashTable,
+    search_options::SearchOptions,
+    transposition_table::TranspositionTable,
+    types::{Line, RepetitionTable},
+};
+
+use super::move_ordering::MoveOrdering;
+
+/// The maximum number of plies that can be searched, including the quiescence search.
+pub const MAX_PLY: usize = 128;
+
+/// A triangular table to collect the best line during the search.
+///
+/// See <https://www.chessprogramming.org/Triangular_PV-Table>.
+pub struct PvTable {
+    /// The best line found for every ply.
+    lines: Vec<[BitMove; MAX_PLY]>,
+    /// The length of the best line for every ply.
+    lengths: [usize; MAX_PLY],
+}
+
+impl PvTable {
+    /// Create a new, empty PV table.
+    pub fn new() -> Self {
+        Self {
+            lines: vec![[BitMove::null(); MAX_PLY]; MAX_PLY],
+            lengths: [0; MAX_PLY],
+        }
+    }
+
+    /// Remove the best line of the given ply.
+    pub fn clear(&mut self, ply: usize) {
+        self.lengths[ply] = 0;
+    }
+
+    /// Set the best move of the given ply.
+    ///
+    /// The best line of the next ply is appended to the move.
+    pub fn update(&mut self, ply: usize, mv: BitMove) {
+        let child_length = self.lengths[ply + 1].min(MAX_PLY - 1);
+        let (lines, child_lines) = self.lines.split_at_mut(ply + 1);
+
+        lines[ply][0] = mv;
+        lines[ply][1..=child_length].copy_from_slice(&child_lines[0][..child_length]);
+        self.lengths[ply] = child_length + 1;
+    }
+
+    /// The best line of the given ply.
+    pub fn line(&self, ply: usize) -> Line {
+        self.lines[ply][..self.lengths[ply]].to_vec()
+    }
+}
+
+/// The state of a search from a single position.
+///
+/// Moves are applied to and undone on the same board,
+/// so that no new positions have to be allocated during the search.
+pub struct Search<'a> {
+    /// The board of the position that is currently searched.
+    pub(super) board: Board,
+    /// The evaluator of the positions, following the moves on the board.
+    pub(super) evaluator: Box<dyn Evaluator + 'a>,
+    /// Table to cache search results, shared between all search threads.
+    pub(super) hash_table: &'a TranspositionTable,
+    /// Table to track threefold repetition.
+    pub(super) repetition_table: &'a mut RepetitionTable,
+    /// The flags to abort the search.
+    pub(super) abort_flags: AbortFlags,
+    /// The options of the search, as configured by the GUI.
+    pub(super) options: &'a SearchOptions,
+    /// Tables to order the moves, kept by the worker over all searches.
+    pub(super) move_ordering: &'a mut MoveOrdering,
+    /// The ply at which the current line passed the turn, if any.
+    pub(super) null_move_ply: Option<usize>,
+    /// The best lines found during the search.
+    pub(super) pv_table: PvTable,
+    /// The number of nodes that have been searched.
+    pub(super) size: usize,
+    /// The maximum ply that has been reached.
+    pub(super) sel_depth: usize,
+}
+
+impl<'a> Search<'a> {
+    /// Create a new search from the given position.
+    pub fn new(
+        board: Board,
+        hash_table: &'a TranspositionTable,
+        repetition_table: &'a mut RepetitionTable,
+        abort_flags: AbortFlags,
+        options: &'a SearchOptions,
+        move_ordering: &'a mut MoveOrdering,
+        pawn_table: &'a mut PawnHashTable,
+    ) -> Self {
+        Self {
+            evaluator: options.evaluator.create(
+                &board,
+                &options.eval_parameters,
+                options.network.as_ref(),
+                pawn_table,
+            ),
+            board,
+            hash_table,
+            repetition_table,
+            abort_flags,
+            options,
+            move_ordering,
+            null_move_ply: None,
+            pv_table: PvTable::new(),
+            size: 0,
+            sel_depth: 0,
+        }
+    }
+
+    /// Apply the move to the board and the evaluator.
+    pub(super) fn make_move(&mut self, mv: BitMove) {
+        self.evaluator.make_move(&self.board, mv);
+        self.board.apply_move(mv);
+    }
+
+    /// Take back the last move on the board and in the evaluator.
+    pub(super) fn undo_move(&mut self) {
+        self.board.undo_move();
+        self.evaluator.undo_move();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use super::PvTable;
+
+    #[test]
+    fn should_collect_best_line() {
+        let mut board = Board::start_pos();
+        let mut line = vec![];
+
+        for uci_move in ["e2e4", "e7e5", "g1f3"] {
+            assert!(board.apply_uci_move(uci_move));
+            line.push(board.last_move().unwrap());
+        }
+
+        let mut pv_table = PvTable::new();
+        pv_table.clear(3);
+
+        // The best line is collected from the leaf to the root
+        for (ply, mv) in line.iter().enumerate().rev() {
+            pv_table.update(ply, *mv);
+        }
+
+        assert_eq!(pv_table.line(0), line);
+        assert_eq!(pv_table.line(1), line[1..]);
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use pleco::{BitMove, Board};
+
+use crate::uci::uci_command::UciGoConfig;
+
+use super::time_management::get_time_limits;
+
+/// The limits of a search.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    /// The maximum depth to search, in plies.
+    pub max_depth: Option<usize>,
+    /// The maximum time to search for.
+    pub max_time: Option<Duration>,
+    /// The time after which no new iteration should be started.
+    ///
+    /// It is adapted to the stability of the search.
+    pub soft_time: Option<Duration>,
+    /// The maximum number of nodes to search.
+    pub max_nodes: Option<usize>,
+    /// Only search for a mate in at most this many moves.
+    pub mate_in: Option<usize>,
+    /// Only search these moves in the root position.
+    pub search_moves: Option<Vec<BitMove>>,
+}
+
+impl SearchLimits {
+    /// Determine the search limits from the configuration of the GUI.
+    ///
+    /// `move_overhead` is the time reserved for the communication with the GUI.
+    pub fn from_go_config(go_config: UciGoConfig, board: &Board, move_overhead: Duration) -> Self {
+        let mate_in = go_config.search_mate;
+        // A mate in N moves takes 2N - 1 plies
+        let mate_plies = mate_in.map(|moves| (2 * moves).saturating_sub(1).max(1));
+        let max_depth = [go_config.max_depth, mate_plies]
+            .into_iter()
+            .flatten()
+            .min();
+        let max_nodes = go_config.max_nodes;
+        let search_moves = go_config
+            .search_moves
+            .as_ref()
+            .and_then(|move_strs| Self::parse_search_moves(move_strs, board));
+        let time_limits = get_time_limits(go_config, board.turn(), move_overhead);
+
+        Self {
+            max_depth,
+            max_time: time_limits.map(|limits| limits.hard),
+            soft_time: time_limits.and_then(|limits| limits.soft),
+            max_nodes,
+            mate_in,
+            search_moves,
+        }
+    }
+
+    /// Convert the moves to search to legal moves of the given position.
+    ///
+    /// Returns `None` if none of the moves are legal, so that all moves are searched instead.
+    fn parse_search_moves(move_strs: &[String], board: &Board) -> Option<Vec<BitMove>> {
+        let legal_moves = board.generate_moves();
+        let mut search_moves = vec![];
+
+        for move_str in move_strs {
+            // Convert to lowercase to make sure it can be compared
+            let move_str = move_str.to_lowercase();
+
+            if let Some(mv) = legal_moves.iter().find(|mv| mv.stringify() == move_str) {
+                search_moves.push(*mv);
+            } else {
+                println!("info string '{}' is not a legal move.", move_str);
+            }
+        }
+
+        if search_moves.is_empty() {
+            None
+        } else {
+            Some(search_moves)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pleco::Board;
+
+    use crate::uci::uci_command::UciGoConfig;
+
+    use super::SearchLimits;
+
+    fn go_config_with_search_moves(search_moves: &[&str]) -> UciGoConfig {
+        UciGoConfig {
+            search_moves: Some(search_moves.iter().map(|mv| mv.to_string()).collect()),
+            ponder: false,
+            white_time_ms: None,
+            black_time_ms: None,
+            white_increment_ms: 0,
+            black_increment_ms: 0,
+            moves_to_go: 0,
+            max_depth: Some(3),
+            max_nodes: None,
+            search_mate: None,
+            move_time_ms: None,
+            infinite: false,
+        }
+    }
+
+    #[test]
+    fn should_parse_search_moves() {
+        let board = Board::start_pos();
+        let limits = SearchLimits::from_go_config(
+            go_config_with_search_moves(&["e2e4", "G1F3"]),
+            &board,
+            Duration::ZERO,
+        );
+
+        let search_moves: Vec<String> = limits
+            .search_moves
+            .unwrap()
+            .iter()
+            .map(|mv| mv.stringify())
+            .collect();
+
+        assert_eq!(search_moves, vec!["e2e4", "g1f3"]);
+    }
+
+    #[test]
+    fn should_ignore_illegal_search_moves() {
+        let board = Board::start_pos();
+        let limits = SearchLimits::from_go_config(
+            go_config_with_search_moves(&["e2e5", "d2d4"]),
+            &board,
+            Duration::ZERO,
+        );
+
+        let search_moves: Vec<String> = limits
+            .search_moves
+            .unwrap()
+            .iter()
+            .map(|mv| mv.stringify())
+            .collect();
+
+        assert_eq!(search_moves, vec!["d2d4"]);
+    }
+
+    #[test]
+    fn should_search_all_moves_without_legal_search_moves() {
+        let board = Board::start_pos();
+        let limits = SearchLimits::from_go_config(
+            go_config_with_search_moves(&["e2e5", "a1a8"]),
+            &board,
+            Duration::ZERO,
+        );
+
+        assert_eq!(limits.search_moves, None);
+    }
+
+    #[test]
+    fn should_search_mates_in_moves() {
+        let go_config = UciGoConfig {
+            max_depth: None,
+            search_mate: Some(3),
+            ..go_config_with_search_moves(&[])
+        };
+        let limits = SearchLimits::from_go_config(go_config, &Board::start_pos(), Duration::ZERO);
+
+        assert_eq!(limits.mate_in, Some(3));
+        assert_eq!(limits.max_depth, Some(5));
+        assert_eq!(limits.max_time, None);
+    }
+}
@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use super::{
+    evaluator::{EvaluatorType, Network},
+    heuristic::EvalParameters,
+    tablebase::Tablebases,
+};
+
+/// The default number of best lines to report.
+pub const DEFAULT_MULTI_PV: usize = 1;
+/// The minimum number of best lines to report.
+pub const MIN_MULTI_PV: usize = 1;
+/// The maximum number of best lines to report.
+pub const MAX_MULTI_PV: usize = 256;
+
+/// The default number of search threads.
+pub const DEFAULT_THREADS: usize = 1;
+/// The minimum number of search threads.
+pub const MIN_THREADS: usize = 1;
+/// The maximum number of search threads.
+pub const MAX_THREADS: usize = 256;
+
+/// Null move pruning is enabled by default.
+pub const DEFAULT_NULL_MOVE_PRUNING: bool = true;
+/// Late move reductions are enabled by default.
+pub const DEFAULT_LATE_MOVE_REDUCTIONS: bool = true;
+
+/// The options of the search, as configured by the GUI.
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    /// The number of best lines to report.
+    pub multi_pv: usize,
+    /// The number of threads to search with.
+    pub threads: usize,
+    /// Prune positions in which passing the turn still causes a cutoff.
+    pub null_move_pruning: bool,
+    /// Search moves late in the move ordering with reduced depth.
+    pub late_move_reductions: bool,
+    /// The evaluator to score the positions with.
+    pub evaluator: EvaluatorType,
+    /// The weights of the handcrafted evaluation, shared by all search threads.
+    pub eval_parameters: Arc<EvalParameters>,
+    /// The network of the neural network evaluation, if one has been loaded.
+    pub network: Option<Arc<Network>>,
+    /// The endgame tablebases to probe, if a path has been configured.
+    pub tablebases: Option<Arc<Tablebases>>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            multi_pv: DEFAULT_MULTI_PV,
+            threads: DEFAULT_THREADS,
+            null_move_pruning: DEFAULT_NULL_MOVE_PRUNING,
+            late_move_reductions: DEFAULT_LATE_MOVE_REDUCTIONS,
+            evaluator: EvaluatorType::default(),
+            eval_parameters: Arc::new(EvalParameters::default()),
+            network: None,
+            tablebases: None,
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::Display;
+
+use pleco::{Board, PieceType, Player};
+
+/// The piece types in the order of the table names.
+const NAME_ORDER: [PieceType; 6] = [
+    PieceType::K,
+    PieceType::Q,
+    PieceType::R,
+    PieceType::B,
+    PieceType::N,
+    PieceType::P,
+];
+
+/// The letter of the piece type in the table names.
+fn piece_letter(piece: PieceType) -> char {
+    match piece {
+        PieceType::P => 'P',
+        PieceType::N => 'N',
+        PieceType::B => 'B',
+        PieceType::R => 'R',
+        PieceType::Q => 'Q',
+        _ => 'K',
+    }
+}
+
+/// The number of pieces of every type for both players, e.g. `KRPvKR`.
+///
+/// The tables are named after the material of their positions, with White first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Material {
+    /// The number of pieces, indexed by player and piece type.
+    counts: [[u8; 6]; 2],
+}
+
+impl Material {
+    /// The material on the board.
+    pub fn from_board(board: &Board) -> Self {
+        let mut counts = [[0; 6]; 2];
+
+        for player in [Player::White, Player::Black] {
+            for piece in NAME_ORDER {
+                counts[player as usize][piece as usize - 1] = board.count_piece(player, piece);
+            }
+        }
+
+        Self { counts }
+    }
+
+    /// Parse the material from the name of a table, e.g. `KRvK`.
+    ///
+    /// Both players must have exactly one king.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [[0; 6]; 2];
+
+        for (player, pieces) in [white, black].into_iter().enumerate() {
+            for letter in pieces.chars() {
+                let piece = NAME_ORDER
+                    .into_iter()
+                    .find(|piece| piece_letter(*piece) == letter)?;
+                counts[player][piece as usize - 1] += 1;
+            }
+
+            if counts[player][PieceType::K as usize - 1] != 1 {
+                return None;
+            }
+        }
+
+        Some(Self { counts })
+    }
+
+    /// The material with the colors of the players swapped.
+    pub fn flipped(&self) -> Self {
+        Self {
+            counts: [self.counts[1], self.counts[0]],
+        }
+    }
+
+    /// The number of pieces of the player.
+    pub fn count(&self, player: Player, piece: PieceType) -> u8 {
+        self.counts[player as usize][piece as usize - 1]
+    }
+
+    /// The number of pieces on the board, including the kings.
+    pub fn piece_count(&self) -> usize {
+        self.counts
+            .iter()
+            .flatten()
+            .map(|count| *count as usize)
+            .sum()
+    }
+
+    /// Determine if any player has a piece other than the king that is unique on the board.
+    pub fn has_unique_pieces(&self) -> bool {
+        self.counts
+            .iter()
+            .any(|counts| counts[..PieceType::K as usize - 1].contains(&1))
+    }
+}
+
+impl Display for Material {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, player) in [Player::White, Player::Black].into_iter().enumerate() {
+            if index > 0 {
+                write!(f, "v")?;
+            }
+
+            for piece in NAME_ORDER {
+                for _ in 0..self.count(player, piece) {
+                    write!(f, "{}", piece_letter(piece))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use super::Material;
+
+    #[test]
+    fn should_name_material() {
+        let parameters = [
+            ("8/8/8/4k3/8/8/3QK3/8 w - - 0 1", "KQvK", "KvKQ"),
+            ("8/2p5/8/4k3/1n6/8/3RK1P1/8 b - - 0 1", "KRPvKNP", "KNPvKRP"),
+        ];
+
+        for (fen, name, flipped_name) in parameters {
+            let material = Material::from_board(&Board::from_fen(fen).unwrap());
+
+            assert_eq!(material.to_string(), name);
+            assert_eq!(material.flipped().to_string(), flipped_name);
+            assert_eq!(Material::from_name(name), Some(material));
+        }
+
+        // Both players need a king
+        assert_eq!(Material::from_name("KQvQ"), None);
+        assert_eq!(Material::from_name("KQK"), None);
+    }
+}
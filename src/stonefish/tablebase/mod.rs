@@ -0,0 +1,636 @@
+//! Probing of Syzygy endgame tablebases.
+//!
+//! The WDL tables store if a position is won, drawn or lost with perfect play,
+//! the DTZ tables store the distance to the next capture or pawn move that keeps the result.
+//! Both consider the fifty-move rule: wins that take too long are cursed wins
+//! and losses that can be delayed long enough are blessed losses.
+//!
+//! See <https://www.chessprogramming.org/Syzygy_Bases>.
+
+mod material;
+mod table;
+
+use std::{collections::HashMap, env, ffi::OsStr, fmt::Display, fs, path::PathBuf, sync::OnceLock};
+
+use pleco::{BitMove, Board, PieceType};
+
+use crate::stonefish::evaluation::{Evaluation, TABLEBASE_WIN};
+
+use self::{
+    material::Material,
+    table::{Table, TableKind, MAX_PIECES},
+};
+
+/// Distances to zeroing are ranked below this value.
+const MAX_DTZ: i32 = 1 << 18;
+
+/// An error while opening the tablebases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TablebaseError {
+    /// A directory could not be read.
+    Io(String),
+}
+
+impl Display for TablebaseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+/// The result of a position with perfect play, from the view of the player to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    /// The player to move loses.
+    Loss,
+    /// The player to move loses without the fifty-move rule.
+    BlessedLoss,
+    /// Neither player can win.
+    Draw,
+    /// The player to move wins without the fifty-move rule.
+    CursedWin,
+    /// The player to move wins.
+    Win,
+}
+
+impl Wdl {
+    /// The result for the value stored in the tables, from -2 for a loss to 2 for a win.
+    fn from_value(value: i32) -> Option<Self> {
+        match value {
+            -2 => Some(Self::Loss),
+            -1 => Some(Self::BlessedLoss),
+            0 => Some(Self::Draw),
+            1 => Some(Self::CursedWin),
+            2 => Some(Self::Win),
+            _ => None,
+        }
+    }
+
+    /// Convert the result to the view of the opponent.
+    pub fn for_opponent(self) -> Self {
+        match self {
+            Self::Loss => Self::Win,
+            Self::BlessedLoss => Self::CursedWin,
+            Self::Draw => Self::Draw,
+            Self::CursedWin => Self::BlessedLoss,
+            Self::Win => Self::Loss,
+        }
+    }
+
+    /// The sign of the result, 1 for wins and -1 for losses.
+    fn signum(self) -> i32 {
+        match self {
+            Self::Loss | Self::BlessedLoss => -1,
+            Self::Draw => 0,
+            Self::CursedWin | Self::Win => 1,
+        }
+    }
+
+    /// The distance to zeroing before a capture or pawn move with this result.
+    fn dtz_before_zeroing(self) -> i32 {
+        match self {
+            Self::Loss => -1,
+            Self::BlessedLoss => -101,
+            Self::Draw => 0,
+            Self::CursedWin => 101,
+            Self::Win => 1,
+        }
+    }
+
+    /// The evaluation of the result in the position.
+    ///
+    /// It doesn't depend on the position in the search, so it can be stored in the transposition table.
+    /// Results that the fifty-move rule turns into a draw are only evaluated slightly different from a draw.
+    pub fn evaluation(self) -> Evaluation {
+        match self {
+            Self::Loss => Evaluation::Centipawns(-TABLEBASE_WIN),
+            Self::BlessedLoss => Evaluation::Centipawns(-1),
+            Self::Draw => Evaluation::Draw,
+            Self::CursedWin => Evaluation::Centipawns(1),
+            Self::Win => Evaluation::Centipawns(TABLEBASE_WIN),
+        }
+    }
+}
+
+/// The rank of a root move by its distance to zeroing, better moves have higher ranks.
+///
+/// Wins are ranked by how fast they reset the fifty-move counter, losses by how long they delay it.
+/// Wins that can't be converted in time rank below the other wins, but above draws.
+fn root_rank(dtz: i32, halfmove_clock: i32) -> i32 {
+    let in_time = dtz.abs() + halfmove_clock < 100;
+
+    match dtz.signum() {
+        1 if in_time => 2 * MAX_DTZ - dtz,
+        1 => MAX_DTZ - dtz,
+        -1 if in_time => -2 * MAX_DTZ - dtz,
+        -1 => -MAX_DTZ - dtz,
+        _ => 0,
+    }
+}
+
+/// The WDL and DTZ table of a material combination, opened on first use.
+#[derive(Debug)]
+struct TableEntry {
+    /// The material as in the names of the files.
+    material: Material,
+    /// The path of the WDL table.
+    wdl_path: PathBuf,
+    /// The path of the DTZ table, if there is one.
+    dtz_path: Option<PathBuf>,
+    /// The WDL table, `None` if it is invalid.
+    wdl: OnceLock<Option<Table>>,
+    /// The DTZ table, `None` if it is missing or invalid.
+    dtz: OnceLock<Option<Table>>,
+}
+
+impl TableEntry {
+    /// The WDL table, opened on first use.
+    fn wdl(&self) -> Option<&Table> {
+        self.wdl
+            .get_or_init(|| Table::open(&self.wdl_path, TableKind::Wdl, self.material).ok())
+            .as_ref()
+    }
+
+    /// The DTZ table, opened on first use.
+    fn dtz(&self) -> Option<&Table> {
+        self.dtz
+            .get_or_init(|| {
+                let path = self.dtz_path.as_ref()?;
+                Table::open(path, TableKind::Dtz, self.material).ok()
+            })
+            .as_ref()
+    }
+}
+
+/// The Syzygy tables found in a set of directories.
+#[derive(Debug, Default)]
+pub struct Tablebases {
+    /// The tables of every material combination.
+    entries: Vec<TableEntry>,
+    /// The index of the entry for the material of a position, with either player as White.
+    index: HashMap<Material, usize>,
+    /// The maximum number of pieces in the tables.
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    /// Find the tables in the given directories, separated like in the `PATH` variable.
+    ///
+    /// The tables are only opened when they are probed.
+    pub fn open(paths: &str) -> Result<Self, TablebaseError> {
+        let mut files: HashMap<(Material, &str), PathBuf> = HashMap::new();
+
+        for directory in env::split_paths(paths) {
+            let entries = fs::read_dir(&directory).map_err(|error| {
+                TablebaseError::Io(format!("could not read '{}': {error}", directory.display()))
+            })?;
+
+            for path in entries.flatten().map(|entry| entry.path()) {
+                let name = path.file_stem().and_then(OsStr::to_str);
+                let Some(material) = name.and_then(Material::from_name) else {
+                    continue;
+                };
+
+                if !(3..=MAX_PIECES).contains(&material.piece_count()) {
+                    continue;
+                }
+
+                match path.extension().and_then(OsStr::to_str) {
+                    Some("rtbw") => files.entry((material, "rtbw")).or_insert(path),
+                    Some("rtbz") => files.entry((material, "rtbz")).or_insert(path),
+                    _ => continue,
+                };
+            }
+        }
+
+        let mut tablebases = Self::default();
+
+        for ((material, extension), wdl_path) in &files {
+            if *extension != "rtbw" {
+                continue;
+            }
+
+            let index = tablebases.entries.len();
+            tablebases.index.insert(*material, index);
+            tablebases.index.insert(material.flipped(), index);
+            tablebases.max_pieces = tablebases.max_pieces.max(material.piece_count());
+            tablebases.entries.push(TableEntry {
+                material: *material,
+                wdl_path: wdl_path.clone(),
+                dtz_path: files.get(&(*material, "rtbz")).cloned(),
+                wdl: OnceLock::new(),
+                dtz: OnceLock::new(),
+            });
+        }
+
+        Ok(tablebases)
+    }
+
+    /// The number of material combinations with a WDL table.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determine if there are no tables.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Determine if the position can be in the tables.
+    ///
+    /// The tables don't contain positions in which a player can still castle.
+    pub fn can_probe(&self, board: &Board) -> bool {
+        board.count_all_pieces() as usize <= self.max_pieces && board.castling_bits() == 0
+    }
+
+    /// The entry of the tables for the material of the position.
+    fn entry(&self, board: &Board) -> Option<&TableEntry> {
+        let index = self.index.get(&Material::from_board(board))?;
+        Some(&self.entries[*index])
+    }
+
+    /// Look up the result of the position in the WDL table, without considering captures.
+    fn probe_wdl_table(&self, board: &Board) -> Option<Wdl> {
+        if board.count_all_pieces() == 2 {
+            // Only the kings are left
+            return Some(Wdl::Draw);
+        }
+
+        self.entry(board)?.wdl()?.probe_wdl(board).ok()
+    }
+
+    /// Look up the distance to zeroing of the position in the DTZ table.
+    ///
+    /// Returns `Some(None)` if the table only stores the positions of the other player to move.
+    fn probe_dtz_table(&self, board: &Board, wdl: Wdl) -> Option<Option<i32>> {
+        self.entry(board)?.dtz()?.probe_dtz(board, wdl).ok()
+    }
+
+    /// The result of the position after resolving the captures.
+    ///
+    /// The tables can store any value for positions in which a capture is the best move,
+    /// so the captures have to be searched first. With `with_pawn_moves`, the pawn moves too.
+    ///
+    /// Returns the result and if the best move resets the fifty-move counter.
+    fn search(&self, board: &mut Board, with_pawn_moves: bool) -> Option<(Wdl, bool)> {
+        let moves = board.generate_moves();
+        let mut best_wdl = Wdl::Loss;
+        let mut searched_moves = 0;
+
+        for mv in moves.iter() {
+            let is_pawn_move = board.piece_at_sq(mv.get_src()).type_of() == PieceType::P;
+
+            if !(mv.is_capture() || with_pawn_moves && is_pawn_move) {
+                continue;
+            }
+
+            searched_moves += 1;
+
+            board.apply_move(*mv);
+            let result = self.search(board, false);
+            board.undo_move();
+
+            let wdl = result?.0.for_opponent();
+
+            if wdl > best_wdl {
+                best_wdl = wdl;
+
+                if wdl == Wdl::Win {
+                    return Some((wdl, true));
+                }
+            }
+        }
+
+        // If all moves have been searched, the stored value is not needed,
+        // e.g. positions with en passant captures are not stored at all
+        let all_searched = searched_moves > 0 && searched_moves == moves.len();
+        let wdl = if all_searched {
+            best_wdl
+        } else {
+            self.probe_wdl_table(board)?
+        };
+
+        if best_wdl >= wdl {
+            Some((best_wdl, best_wdl > Wdl::Draw || all_searched))
+        } else {
+            Some((wdl, false))
+        }
+    }
+
+    /// The distance to the next capture or pawn move with the best result, in plies.
+    ///
+    /// The distance is positive for wins and negative for losses,
+    /// cursed wins and blessed losses are 100 plies further away.
+    fn dtz(&self, board: &mut Board) -> Option<i32> {
+        let (wdl, is_zeroing_best) = self.search(board, true)?;
+
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+
+        if is_zeroing_best {
+            return Some(wdl.dtz_before_zeroing());
+        }
+
+        if let Some(dtz) = self.probe_dtz_table(board, wdl)? {
+            let is_cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+            return Some((dtz + 100 * is_cursed as i32) * wdl.signum());
+        }
+
+        // The table only stores the other player to move, so look one move ahead
+        let mut min_dtz = None;
+
+        for mv in board.generate_moves() {
+            let is_zeroing =
+                mv.is_capture() || board.piece_at_sq(mv.get_src()).type_of() == PieceType::P;
+
+            board.apply_move(mv);
+            let result = if is_zeroing {
+                // The distance of the move before the capture or pawn move
+                self.search(board, false)
+                    .map(|(wdl, _)| -wdl.dtz_before_zeroing())
+            } else {
+                self.dtz(board).map(|dtz| -dtz - dtz.signum())
+            };
+            let is_mate = board.checkmate();
+            board.undo_move();
+
+            let dtz = result?;
+
+            if is_mate {
+                min_dtz = Some(1);
+            } else if dtz.signum() == wdl.signum() && min_dtz.is_none_or(|min_dtz| dtz < min_dtz) {
+                min_dtz = Some(dtz);
+            }
+        }
+
+        // Without moves, the player to move is checkmated
+        Some(min_dtz.unwrap_or(-1))
+    }
+
+    /// The result of the position for the player to move, if it is in the tables.
+    pub fn probe_wdl(&self, board: &mut Board) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        self.search(board, false).map(|(wdl, _)| wdl)
+    }
+
+    /// The moves that keep the best result in the root position, if it is in the tables.
+    ///
+    /// Wins are converted as fast as possible, losses delayed as long as possible,
+    /// considering the fifty-move rule.
+    pub fn best_root_moves(&self, board: &Board) -> Option<Vec<BitMove>> {
+        if !self.can_probe(board) {
+            return None;
+        }
+
+        let mut board = board.clone();
+        let halfmove_clock = board.rule_50() as i32;
+        let mut best_moves = vec![];
+        let mut best_rank = i32::MIN;
+
+        for mv in board.generate_moves() {
+            board.apply_move(mv);
+            let dtz = if board.rule_50() == 0 {
+                self.search(&mut board, false)
+                    .map(|(wdl, _)| wdl.for_opponent().dtz_before_zeroing())
+            } else {
+                self.dtz(&mut board).map(|dtz| -dtz - dtz.signum())
+            };
+            let is_mate = board.checkmate();
+            board.undo_move();
+
+            let rank = root_rank(if is_mate { 1 } else { dtz? }, halfmove_clock);
+
+            if rank > best_rank {
+                best_rank = rank;
+                best_moves.clear();
+            }
+
+            if rank == best_rank {
+                best_moves.push(mv);
+            }
+        }
+
+        Some(best_moves).filter(|moves| !moves.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use pleco::Board;
+
+    use super::{
+        material::Material,
+        table::{writer::single_value_table, TableKind},
+        Tablebases, Wdl,
+    };
+
+    /// The directory with the Syzygy tables of KQvK, KRvK and KPvK.
+    fn syzygy_directory() -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/syzygy")
+    }
+
+    /// A directory with tables of the given material that store a single value.
+    ///
+    /// The WDL values are stored for White and Black to move, the DTZ value only for White.
+    /// Every test needs its own directory, because the tests run in parallel.
+    fn single_value_tablebases(
+        test: &str,
+        name: &str,
+        wdl_values: &[u8],
+        dtz_value: u8,
+    ) -> PathBuf {
+        let directory = env::temp_dir().join(format!("stonefish-{}-{test}", process::id()));
+        let material = Material::from_name(name).unwrap();
+        fs::create_dir_all(&directory).unwrap();
+
+        fs::write(
+            directory.join(format!("{name}.rtbw")),
+            single_value_table(TableKind::Wdl, material, wdl_values),
+        )
+        .unwrap();
+        fs::write(
+            directory.join(format!("{name}.rtbz")),
+            single_value_table(TableKind::Dtz, material, &[dtz_value]),
+        )
+        .unwrap();
+
+        directory
+    }
+
+    #[test]
+    fn should_find_tables() {
+        let directory = single_value_tablebases("find-tables", "KRvK", &[4, 0], 5);
+        fs::write(directory.join("README.txt"), "Not a table").unwrap();
+        let tablebases = Tablebases::open(directory.to_str().unwrap()).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(tablebases.len(), 1);
+        assert_eq!(tablebases.max_pieces, 3);
+        assert!(Tablebases::open("/does/not/exist").is_err());
+    }
+
+    #[test]
+    fn should_probe_results() {
+        // KQ against K is won for White with either player to move
+        let directory = single_value_tablebases("probe-results", "KQvK", &[4, 0], 5);
+        let tablebases = Tablebases::open(directory.to_str().unwrap()).unwrap();
+
+        let parameters = [
+            (
+                "white to move",
+                "8/8/8/4k3/8/8/3QK3/8 w - - 0 1",
+                Some(Wdl::Win),
+            ),
+            (
+                "black to move",
+                "8/8/8/4k3/8/8/3QK3/8 b - - 0 1",
+                Some(Wdl::Loss),
+            ),
+            (
+                "colors swapped",
+                "8/8/8/4K3/8/8/3qk3/8 w - - 0 1",
+                Some(Wdl::Loss),
+            ),
+            (
+                "queen can be taken",
+                "8/8/8/8/4k3/8/3q4/4K3 w - - 0 1",
+                Some(Wdl::Draw),
+            ),
+            (
+                "only kings",
+                "8/8/8/4k3/8/8/4K3/8 w - - 0 1",
+                Some(Wdl::Draw),
+            ),
+            ("no table", "8/8/8/4k3/8/8/3RK3/8 w - - 0 1", None),
+            ("castling", "4k3/8/8/8/8/8/8/4K2R w K - 0 1", None),
+        ];
+
+        for (name, fen, expected) in parameters {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert_eq!(tablebases.probe_wdl(&mut board), expected, "{name}");
+        }
+
+        let mut board = Board::from_fen("8/8/8/4k3/8/8/3QK3/8 w - - 0 1").unwrap();
+        // The stored distance of 5 moves is converted to plies
+        assert_eq!(tablebases.dtz(&mut board), Some(11));
+
+        let mut board = Board::from_fen("8/8/8/4k3/8/8/3QK3/8 b - - 0 1").unwrap();
+        // The distance is only stored for White to move, so it is derived from the next move
+        assert_eq!(tablebases.dtz(&mut board), Some(-12));
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn should_keep_result_with_root_moves() {
+        let directory = single_value_tablebases("root-moves", "KQvK", &[4, 0], 5);
+        let tablebases = Tablebases::open(directory.to_str().unwrap()).unwrap();
+
+        // The queen can move next to the black king
+        let board = Board::from_fen("8/8/4k3/8/3Q4/8/8/K7 w - - 0 1").unwrap();
+        let root_moves: Vec<String> = tablebases
+            .best_root_moves(&board)
+            .unwrap()
+            .iter()
+            .map(|mv| mv.stringify())
+            .collect();
+
+        assert!(root_moves.contains(&"d4d8".to_string()), "{root_moves:?}");
+        // The queen would be lost
+        assert!(!root_moves.contains(&"d4d5".to_string()), "{root_moves:?}");
+        assert!(!root_moves.contains(&"d4e5".to_string()), "{root_moves:?}");
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    #[ignore = "needs the Syzygy tables of KQvK, KRvK and KPvK in tests/syzygy"]
+    fn should_probe_syzygy_tables() {
+        let tablebases = Tablebases::open(syzygy_directory().to_str().unwrap()).unwrap();
+        assert_eq!(tablebases.len(), 3);
+
+        let parameters = [
+            ("queen", "8/8/8/4k3/8/8/3QK3/8 w - - 0 1", Wdl::Win),
+            (
+                "queen can be taken",
+                "8/8/8/8/4k3/8/3q4/4K3 w - - 0 1",
+                Wdl::Draw,
+            ),
+            (
+                "queen stalemate",
+                "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1",
+                Wdl::Draw,
+            ),
+            ("rook", "8/8/8/4k3/8/8/3RK3/8 b - - 0 1", Wdl::Loss),
+            (
+                "rook stalemate",
+                "k7/1R6/1K6/8/8/8/8/8 b - - 0 1",
+                Wdl::Draw,
+            ),
+            ("king in front", "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1", Wdl::Win),
+            (
+                "king in front with black to move",
+                "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1",
+                Wdl::Loss,
+            ),
+            ("mirrored file", "6k1/8/6K1/6P1/8/8/8/8 w - - 0 1", Wdl::Win),
+            ("black pawn", "8/8/8/8/3p4/3k4/8/3K4 b - - 0 1", Wdl::Win),
+            (
+                "pawn stalemate",
+                "4k3/4P3/4K3/8/8/8/8/8 b - - 0 1",
+                Wdl::Draw,
+            ),
+            (
+                "pawn on seventh",
+                "4k3/4P3/4K3/8/8/8/8/8 w - - 0 1",
+                Wdl::Win,
+            ),
+            ("rook pawn", "k7/8/8/8/8/8/P7/K7 w - - 0 1", Wdl::Draw),
+        ];
+
+        for (name, fen, expected) in parameters {
+            let mut board = Board::from_fen(fen).unwrap();
+            assert_eq!(tablebases.probe_wdl(&mut board), Some(expected), "{name}");
+        }
+
+        // The pawn is blocked, so the king steps aside, Black moves and then the pawn.
+        // Distances stored in moves can be one ply too long
+        let mut board = Board::from_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap();
+        let dtz = tablebases.dtz(&mut board);
+        assert!(matches!(dtz, Some(3 | 4)), "{dtz:?}");
+
+        let mut board = Board::from_fen("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap();
+        let dtz = tablebases.dtz(&mut board);
+        assert!(matches!(dtz, Some(-4 | -5)), "{dtz:?}");
+
+        // Only stepping aside next to the pawn wins without delay
+        let board = Board::from_fen("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").unwrap();
+        let mut root_moves: Vec<String> = tablebases
+            .best_root_moves(&board)
+            .unwrap()
+            .iter()
+            .map(|mv| mv.stringify())
+            .collect();
+        root_moves.sort();
+        assert_eq!(root_moves, ["e6d6", "e6f6"]);
+
+        // The queen must not be left to be taken
+        let board = Board::from_fen("8/8/4k3/8/3Q4/8/8/K7 w - - 0 1").unwrap();
+        let root_moves: Vec<String> = tablebases
+            .best_root_moves(&board)
+            .unwrap()
+            .iter()
+            .map(|mv| mv.stringify())
+            .collect();
+        assert!(!root_moves.is_empty());
+        assert!(!root_moves.contains(&"d4d5".to_string()), "{root_moves:?}");
+        assert!(!root_moves.contains(&"d4e5".to_string()), "{root_moves:?}");
+    }
+}
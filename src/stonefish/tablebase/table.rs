@@ -0,0 +1,1212 @@
+//! Reading Syzygy table files.
+//!
+//! A table stores a value for every placement of its pieces, indexed so that
+//! symmetric positions share the same index.
+//! The values are compressed with recursive pairing and canonical Huffman codes,
+//! split into blocks that are only read from the file when they are first probed.
+//!
+//! The format is not documented apart from its original probing code,
+//! this implementation follows the probing code of Stockfish.
+//! See <https://www.chessprogramming.org/Syzygy_Bases>.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+    sync::OnceLock,
+};
+
+use pleco::{BitBoard, Board, PieceType, Player};
+
+use super::{material::Material, Wdl};
+
+#[cfg(test)]
+pub(super) mod writer;
+
+/// The maximum number of pieces in a table, including the kings.
+pub const MAX_PIECES: usize = 7;
+
+/// The first bytes of a WDL table.
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+/// The first bytes of a DTZ table.
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+/// The DTZ table stores the positions with Black to move.
+const FLAG_STM: u8 = 1;
+/// The DTZ values are stored as indices into a map.
+const FLAG_MAPPED: u8 = 2;
+/// The DTZ values of wins are stored in plies instead of moves.
+const FLAG_WIN_PLIES: u8 = 4;
+/// The DTZ values of losses are stored in plies instead of moves.
+const FLAG_LOSS_PLIES: u8 = 8;
+/// The DTZ map stores 16-bit values.
+const FLAG_WIDE: u8 = 16;
+/// All positions have the same value.
+const FLAG_SINGLE_VALUE: u8 = 128;
+
+/// The table is split by the player to move.
+const HEADER_SPLIT: u8 = 1;
+/// The table has pawns and is split by the file of the leading pawn.
+const HEADER_HAS_PAWNS: u8 = 2;
+
+/// The symbol in the right half of a pair that marks a leaf.
+const LEAF_SYMBOL: u16 = 0xfff;
+
+/// The kind of values stored in a table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableKind {
+    /// Win, draw or loss, in files ending with `.rtbw`.
+    Wdl,
+    /// The distance to the next capture or pawn move, in files ending with `.rtbz`.
+    Dtz,
+}
+
+impl TableKind {
+    /// The first bytes of the table files.
+    fn magic(self) -> [u8; 4] {
+        match self {
+            Self::Wdl => WDL_MAGIC,
+            Self::Dtz => DTZ_MAGIC,
+        }
+    }
+}
+
+/// The distance of the square to the a1-h8 diagonal, positive above it.
+fn off_diagonal(sq: usize) -> i32 {
+    (sq >> 3) as i32 - (sq & 7) as i32
+}
+
+/// Mirror the square at the a1-h8 diagonal.
+fn flip_diagonal(sq: usize) -> usize {
+    ((sq >> 3) | (sq << 3)) & 63
+}
+
+/// Lookup tables to compute the index of a position in a table.
+#[derive(Debug)]
+struct Encoding {
+    /// The number of ways to choose `k` of `n` squares, indexed by `k` and `n`.
+    binomial: [[u64; 64]; MAX_PIECES + 1],
+    /// Maps the squares a2-h7 to 0..47, the leading pawn has the highest value.
+    map_pawns: [usize; 64],
+    /// The first index of the leading pawns, indexed by their number and the leading square.
+    lead_pawn_index: [[u64; 64]; MAX_PIECES + 1],
+    /// The number of indices of the leading pawns, indexed by their number and file.
+    lead_pawns_size: [[u64; 4]; MAX_PIECES + 1],
+    /// Maps the squares below the a1-h8 diagonal to 0..27.
+    map_b1h1h7: [u64; 64],
+    /// Maps the squares of the a1-d1-d4 triangle to 0..9, with the diagonal last.
+    map_a1d1d4: [usize; 64],
+    /// The index of both kings, by the square of the first in the a1-d1-d4 triangle.
+    map_kk: [[u64; 64]; 10],
+}
+
+impl Encoding {
+    /// Compute the lookup tables.
+    fn new() -> Self {
+        let mut encoding = Self {
+            binomial: [[0; 64]; MAX_PIECES + 1],
+            map_pawns: [0; 64],
+            lead_pawn_index: [[0; 64]; MAX_PIECES + 1],
+            lead_pawns_size: [[0; 4]; MAX_PIECES + 1],
+            map_b1h1h7: [0; 64],
+            map_a1d1d4: [0; 64],
+            map_kk: [[0; 64]; 10],
+        };
+
+        for (code, sq) in (0..64).filter(|sq| off_diagonal(*sq) < 0).enumerate() {
+            encoding.map_b1h1h7[sq] = code as u64;
+        }
+
+        // The squares of the triangle up to d4, the squares on the diagonal come last
+        let triangle: Vec<usize> = (0..28).filter(|sq| sq & 7 <= 3).collect();
+        let below = triangle.iter().filter(|sq| off_diagonal(**sq) < 0);
+        let on_diagonal = triangle.iter().filter(|sq| off_diagonal(**sq) == 0);
+
+        for (code, sq) in below.chain(on_diagonal).enumerate() {
+            encoding.map_a1d1d4[*sq] = code;
+        }
+
+        // The legal placements of the kings, if the first one is on the diagonal
+        // the second one can't be above it
+        let mut code = 0;
+        let mut both_on_diagonal = vec![];
+
+        for index in 0..10 {
+            let first = triangle
+                .iter()
+                .find(|sq| encoding.map_a1d1d4[**sq] == index)
+                .copied()
+                .unwrap();
+
+            for second in 0..64 {
+                let is_adjacent = (first & 7).abs_diff(second & 7) <= 1
+                    && (first >> 3).abs_diff(second >> 3) <= 1;
+
+                if is_adjacent || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                    continue;
+                }
+
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((index, second));
+                } else {
+                    encoding.map_kk[index][second] = code;
+                    code += 1;
+                }
+            }
+        }
+
+        for (index, second) in both_on_diagonal {
+            encoding.map_kk[index][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+
+        for n in 1..64 {
+            for k in 0..=MAX_PIECES.min(n) {
+                encoding.binomial[k][n] = if k > 0 {
+                    encoding.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n {
+                    encoding.binomial[k][n - 1]
+                } else {
+                    0
+                };
+            }
+        }
+
+        // The pawns closer to the edge and to the second rank lead
+        for file in 0..4 {
+            for rank in 1..7 {
+                let sq = rank * 8 + file;
+                let code = 2 * (file * 6 + rank - 1);
+
+                encoding.map_pawns[sq] = 47 - code;
+                encoding.map_pawns[sq ^ 7] = 46 - code;
+            }
+        }
+
+        for lead_pawns in 1..=MAX_PIECES {
+            for file in 0..4 {
+                let mut index = 0;
+
+                for rank in 1..7 {
+                    let sq = rank * 8 + file;
+                    encoding.lead_pawn_index[lead_pawns][sq] = index;
+                    index += encoding.binomial[lead_pawns - 1][encoding.map_pawns[sq]];
+                }
+
+                encoding.lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+
+        encoding
+    }
+}
+
+/// The lookup tables to compute the index of a position, computed on first use.
+fn encoding() -> &'static Encoding {
+    static ENCODING: OnceLock<Encoding> = OnceLock::new();
+    ENCODING.get_or_init(Encoding::new)
+}
+
+/// An error for invalid data in a table file.
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Read bytes at the given offset of the file, without moving its cursor.
+fn read_at(file: &File, offset: u64, buffer: &mut [u8]) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileExt;
+        file.read_exact_at(buffer, offset)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::fs::FileExt;
+        let mut read = 0;
+
+        while read < buffer.len() {
+            match file.seek_read(&mut buffer[read..], offset + read as u64)? {
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                count => read += count,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the header of a table file in order.
+struct HeaderReader<'a> {
+    /// The buffered file.
+    reader: BufReader<&'a File>,
+    /// The number of bytes that have been read.
+    offset: u64,
+}
+
+impl<'a> HeaderReader<'a> {
+    /// Read the header from the start of the file.
+    fn new(file: &'a File) -> Self {
+        Self {
+            reader: BufReader::new(file),
+            offset: 0,
+        }
+    }
+
+    /// Read the given number of bytes.
+    fn bytes(&mut self, count: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = vec![0; count];
+        self.reader.read_exact(&mut bytes)?;
+        self.offset += count as u64;
+        Ok(bytes)
+    }
+
+    /// Read a byte.
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Read a little-endian 16-bit number.
+    fn u16(&mut self) -> io::Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Read a little-endian 32-bit number.
+    fn u32(&mut self) -> io::Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Skip to the next offset that is a multiple of two.
+    fn align(&mut self) -> io::Result<()> {
+        if self.offset % 2 == 1 {
+            self.u8()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The information to decompress the values for one player to move and file of the leading pawn.
+#[derive(Debug, Clone, Default)]
+struct PairsData {
+    /// The flags of the values, e.g. [`FLAG_SINGLE_VALUE`].
+    flags: u8,
+    /// The length of the shortest Huffman code, or the value of all positions.
+    min_symbol_length: u8,
+    /// The number of bytes of a block.
+    block_size: u64,
+    /// The number of values between the entries of the sparse index.
+    span: u64,
+    /// The number of blocks.
+    block_count: u64,
+    /// The number of entries of the sparse index.
+    sparse_index_size: u64,
+    /// The number of block lengths, padded so that the sparse index stays in range.
+    block_lengths_size: u64,
+    /// The lowest symbol of each code length, starting with the shortest.
+    lowest_symbols: Vec<u16>,
+    /// The lowest code of each length, padded to 64 bits.
+    base64: Vec<u64>,
+    /// The number of values (minus one) that each symbol expands to.
+    symbol_lengths: Vec<u32>,
+    /// The pair of symbols that each symbol expands to.
+    pairs: Vec<[u16; 2]>,
+    /// The block and the offset in it of every span-th value.
+    sparse_index: Vec<(u32, u16)>,
+    /// The number of values (minus one) of each block.
+    block_lengths: Vec<u16>,
+    /// The file offset of the blocks.
+    data: u64,
+    /// The blocks that have already been read from the file.
+    blocks: Vec<OnceLock<Box<[u8]>>>,
+    /// The pieces in the order of their encoding.
+    pieces: [u8; MAX_PIECES],
+    /// The factor of the index of each group of pieces.
+    group_index: [u64; MAX_PIECES + 1],
+    /// The number of pieces of each group, terminated by zero.
+    group_length: [usize; MAX_PIECES + 1],
+    /// The start of the DTZ map of wins, losses, cursed wins and blessed losses.
+    map_index: [usize; 4],
+}
+
+impl PairsData {
+    /// The number of positions in the table, the index of a position is smaller.
+    fn size(&self) -> u64 {
+        let groups = self.group_length.iter().take_while(|length| **length > 0);
+        self.group_index[groups.count()]
+    }
+
+    /// Read the sizes and the Huffman codes.
+    fn read_sizes(&mut self, reader: &mut HeaderReader) -> io::Result<()> {
+        self.flags = reader.u8()?;
+
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            self.min_symbol_length = reader.u8()?;
+            return Ok(());
+        }
+
+        let shift = |bits: u8| {
+            1u64.checked_shl(bits as u32)
+                .ok_or_else(|| invalid_data("invalid block size"))
+        };
+        self.block_size = shift(reader.u8()?)?;
+        self.span = shift(reader.u8()?)?;
+        self.sparse_index_size = self.size().div_ceil(self.span);
+        let padding = reader.u8()?;
+        self.block_count = reader.u32()? as u64;
+        self.block_lengths_size = self.block_count + padding as u64;
+
+        let max_symbol_length = reader.u8()?;
+        self.min_symbol_length = reader.u8()?;
+
+        if max_symbol_length < self.min_symbol_length || max_symbol_length > 32 {
+            return Err(invalid_data("invalid symbol lengths"));
+        }
+
+        let lengths = (max_symbol_length - self.min_symbol_length + 1) as usize;
+        self.lowest_symbols = (0..lengths)
+            .map(|_| reader.u16())
+            .collect::<io::Result<_>>()?;
+
+        // Longer codes have lower values, so the codes of each length start
+        // below the codes of the next shorter length
+        self.base64 = vec![0; lengths];
+
+        for i in (0..lengths - 1).rev() {
+            self.base64[i] = self.base64[i + 1]
+                .wrapping_add(self.lowest_symbols[i] as u64)
+                .wrapping_sub(self.lowest_symbols[i + 1] as u64)
+                / 2;
+        }
+
+        for (i, base) in self.base64.iter_mut().enumerate() {
+            let shift = 64 - i - self.min_symbol_length as usize;
+            *base = base.checked_shl(shift as u32).unwrap_or(0);
+        }
+
+        let symbols = reader.u16()? as usize;
+        let bytes = reader.bytes(3 * symbols)?;
+        self.pairs = bytes
+            .chunks_exact(3)
+            .map(|pair| {
+                [
+                    (((pair[1] & 0xf) as u16) << 8) | pair[0] as u16,
+                    ((pair[2] as u16) << 4) | (pair[1] >> 4) as u16,
+                ]
+            })
+            .collect();
+
+        let is_valid = self.pairs.iter().all(|[left, right]| {
+            *right == LEAF_SYMBOL || (*left as usize) < symbols && (*right as usize) < symbols
+        });
+
+        if !is_valid {
+            return Err(invalid_data("invalid symbol pairs"));
+        }
+
+        self.symbol_lengths = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                self.symbol_lengths[symbol] = self.symbol_length(symbol, &mut visited);
+            }
+        }
+
+        if symbols % 2 == 1 {
+            reader.u8()?;
+        }
+
+        Ok(())
+    }
+
+    /// The number of values (minus one) that the symbol expands to.
+    fn symbol_length(&mut self, symbol: usize, visited: &mut [bool]) -> u32 {
+        visited[symbol] = true;
+        let [left, right] = self.pairs[symbol];
+
+        if right == LEAF_SYMBOL {
+            return 0;
+        }
+
+        for child in [left as usize, right as usize] {
+            if !visited[child] {
+                self.symbol_lengths[child] = self.symbol_length(child, visited);
+            }
+        }
+
+        self.symbol_lengths[left as usize] + self.symbol_lengths[right as usize] + 1
+    }
+}
+
+/// A WDL or DTZ table of one material combination.
+///
+/// The header and the indices of the blocks are read when the table is opened,
+/// each block is read from the file when it is first probed and then kept in memory.
+#[derive(Debug)]
+pub struct Table {
+    /// The kind of values in the table.
+    kind: TableKind,
+    /// The file of the table.
+    file: File,
+    /// The material of the table, as in its name.
+    material: Material,
+    /// Both players have the same pieces.
+    symmetric: bool,
+    /// Any player has pawns.
+    has_pawns: bool,
+    /// Any player has a piece other than the king that is unique on the board.
+    has_unique_pieces: bool,
+    /// The number of pawns of the leading player and of the other player.
+    pawn_counts: [u8; 2],
+    /// The information to decompress the values, by file of the leading pawn and player to move.
+    pairs_data: Vec<Vec<PairsData>>,
+    /// The map from the stored DTZ values to the real values.
+    dtz_map: Vec<u8>,
+}
+
+impl Table {
+    /// Open the table file and read its header.
+    pub fn open(path: &Path, kind: TableKind, material: Material) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let file_size = file.metadata()?.len();
+
+        if file_size % 64 != 16 {
+            return Err(invalid_data("invalid file size"));
+        }
+
+        let white_pawns = material.count(Player::White, PieceType::P);
+        let black_pawns = material.count(Player::Black, PieceType::P);
+        // The player with fewer pawns leads, because that compresses better
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+
+        let mut table = Self {
+            kind,
+            file,
+            material,
+            symmetric: material == material.flipped(),
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces: material.has_unique_pieces(),
+            pawn_counts: if white_leads {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            pairs_data: vec![],
+            dtz_map: vec![],
+        };
+
+        let mut reader = HeaderReader::new(&table.file);
+        let (pairs_data, dtz_map) = table.read_header(&mut reader, file_size)?;
+        table.pairs_data = pairs_data;
+        table.dtz_map = dtz_map;
+
+        Ok(table)
+    }
+
+    /// Read the header with the information to decompress the values and the DTZ map.
+    fn read_header(
+        &self,
+        reader: &mut HeaderReader,
+        file_size: u64,
+    ) -> io::Result<(Vec<Vec<PairsData>>, Vec<u8>)> {
+        if reader.bytes(4)? != self.kind.magic() {
+            return Err(invalid_data("invalid magic bytes"));
+        }
+
+        let header = reader.u8()?;
+
+        if (header & HEADER_HAS_PAWNS != 0) != self.has_pawns
+            || (header & HEADER_SPLIT != 0) == self.symmetric
+        {
+            return Err(invalid_data("the header doesn't match the material"));
+        }
+
+        let sides = if self.kind == TableKind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_have_pawns = self.has_pawns && self.pawn_counts[1] > 0;
+        let mut pairs_data = vec![vec![PairsData::default(); sides]; files];
+
+        for (file, file_data) in pairs_data.iter_mut().enumerate() {
+            let order = reader.bytes(1 + both_have_pawns as usize)?;
+            let remaining_pawns_order = |shift: u8| match order.get(1) {
+                Some(order) => (order >> shift) & 0xf,
+                None => 0xf,
+            };
+            let orders = [
+                [order[0] & 0xf, remaining_pawns_order(0)],
+                [order[0] >> 4, remaining_pawns_order(4)],
+            ];
+            let pieces = reader.bytes(self.material.piece_count())?;
+
+            for (side, data) in file_data.iter_mut().enumerate() {
+                for (index, piece) in pieces.iter().enumerate() {
+                    data.pieces[index] = if side == 0 { piece & 0xf } else { piece >> 4 };
+                }
+
+                self.set_groups(data, orders[side], file);
+            }
+        }
+
+        reader.align()?;
+
+        for data in pairs_data.iter_mut().flatten() {
+            data.read_sizes(reader)?;
+        }
+
+        let mut dtz_map = vec![];
+
+        if self.kind == TableKind::Dtz {
+            for file_data in &mut pairs_data {
+                let data = &mut file_data[0];
+
+                if data.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+
+                if data.flags & FLAG_WIDE != 0 {
+                    if reader.offset % 2 == 1 {
+                        dtz_map.push(reader.u8()?);
+                    }
+
+                    for map_index in &mut data.map_index {
+                        *map_index = dtz_map.len() / 2 + 1;
+                        let length = reader.u16()?;
+                        dtz_map.extend(length.to_le_bytes());
+                        dtz_map.extend(reader.bytes(2 * length as usize)?);
+                    }
+                } else {
+                    for map_index in &mut data.map_index {
+                        *map_index = dtz_map.len() + 1;
+                        let length = reader.u8()?;
+                        dtz_map.push(length);
+                        dtz_map.extend(reader.bytes(length as usize)?);
+                    }
+                }
+            }
+
+            reader.align()?;
+        }
+
+        for data in pairs_data.iter_mut().flatten() {
+            let entries = reader.bytes(6 * data.sparse_index_size as usize)?;
+            data.sparse_index = entries
+                .chunks_exact(6)
+                .map(|entry| {
+                    let block = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+                    (block, u16::from_le_bytes([entry[4], entry[5]]))
+                })
+                .collect();
+        }
+
+        for data in pairs_data.iter_mut().flatten() {
+            let lengths = reader.bytes(2 * data.block_lengths_size as usize)?;
+            data.block_lengths = lengths
+                .chunks_exact(2)
+                .map(|length| u16::from_le_bytes([length[0], length[1]]))
+                .collect();
+        }
+
+        // The blocks are only read when probing
+        let mut offset = reader.offset;
+
+        for data in pairs_data.iter_mut().flatten() {
+            offset = offset.next_multiple_of(64);
+            data.data = offset;
+            data.blocks = (0..data.block_count).map(|_| OnceLock::new()).collect();
+            offset += data.block_count * data.block_size;
+        }
+
+        if offset > file_size {
+            return Err(invalid_data("the file is too short"));
+        }
+
+        Ok((pairs_data, dtz_map))
+    }
+
+    /// Group the pieces that are encoded together and compute the factors of their indices.
+    ///
+    /// The leading group consists of the leading pawns or the first three unique pieces,
+    /// otherwise of the kings. The other groups are pieces of the same type and color.
+    /// `order` is the position of the leading group and of the remaining pawns in the index.
+    fn set_groups(&self, data: &mut PairsData, order: [u8; 2], file: usize) {
+        let encoding = encoding();
+        let piece_count = self.material.piece_count();
+        let mut first_length: i32 = if self.has_pawns {
+            0
+        } else if self.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut groups = 0;
+        data.group_length[0] = 1;
+
+        for i in 1..piece_count {
+            first_length -= 1;
+
+            if first_length > 0 || data.pieces[i] == data.pieces[i - 1] {
+                data.group_length[groups] += 1;
+            } else {
+                groups += 1;
+                data.group_length[groups] = 1;
+            }
+        }
+
+        groups += 1;
+        data.group_length[groups] = 0;
+
+        let both_have_pawns = self.has_pawns && self.pawn_counts[1] > 0;
+        let mut next = if both_have_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - data.group_length[0];
+
+        if both_have_pawns {
+            free_squares -= data.group_length[1];
+        }
+
+        let mut index = 1;
+        let mut k = 0;
+
+        while next < groups || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                data.group_index[0] = index;
+                index *= if self.has_pawns {
+                    encoding.lead_pawns_size[data.group_length[0]][file]
+                } else if self.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                data.group_index[1] = index;
+                index *= encoding.binomial[data.group_length[1]][48 - data.group_length[0]];
+            } else {
+                data.group_index[next] = index;
+                index *= encoding.binomial[data.group_length[next]][free_squares];
+                free_squares -= data.group_length[next];
+                next += 1;
+            }
+
+            k += 1;
+        }
+
+        data.group_index[groups] = index;
+    }
+
+    /// The index of the position in the table.
+    ///
+    /// Returns the file of the leading pawn, the data to decompress the value and the index,
+    /// or `None` if the DTZ table only stores the positions of the other player to move.
+    fn index(&self, board: &Board) -> Option<(usize, &PairsData, u64)> {
+        let encoding = encoding();
+        // The tables only store the positions with the stronger player as White,
+        // and symmetric tables only with White to move
+        let flip = (self.symmetric && board.turn() == Player::Black)
+            || Material::from_board(board) != self.material;
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let side = flip as usize ^ board.turn() as usize;
+
+        let mut squares = [0; MAX_PIECES];
+        let mut pieces = [0; MAX_PIECES];
+        let mut count = 0;
+        let mut lead_pawns = BitBoard(0);
+        let mut file = 0;
+
+        if self.has_pawns {
+            // The pawns of the leading player are encoded first, starting with the leading one
+            let player = match self.pairs_data[0][0].pieces[0] ^ flip_color {
+                piece if piece & 8 == 0 => Player::White,
+                _ => Player::Black,
+            };
+            lead_pawns = board.piece_bb(player, PieceType::P);
+
+            for sq in lead_pawns {
+                squares[count] = sq.0 as usize ^ flip_squares;
+                count += 1;
+            }
+
+            let lead = (0..count).max_by_key(|i| encoding.map_pawns[squares[*i]])?;
+            squares.swap(0, lead);
+            file = (squares[0] & 7).min(7 - (squares[0] & 7));
+        }
+
+        let lead_pawn_count = count;
+        let data = &self.pairs_data[file][side % self.pairs_data[file].len()];
+
+        if self.kind == TableKind::Dtz
+            && (data.flags & FLAG_STM) as usize != side
+            && (!self.symmetric || self.has_pawns)
+        {
+            return None;
+        }
+
+        for sq in board.occupied() ^ lead_pawns {
+            let piece = board.piece_at_sq(sq);
+            squares[count] = sq.0 as usize ^ flip_squares;
+            pieces[count] =
+                (piece.type_of() as u8 | (piece.player_lossy() as u8) << 3) ^ flip_color;
+            count += 1;
+        }
+
+        // Order the pieces like in the table
+        for i in lead_pawn_count..count - 1 {
+            if let Some(j) = (i + 1..count).find(|j| data.pieces[i] == pieces[*j]) {
+                pieces.swap(i, j);
+                squares.swap(i, j);
+            }
+        }
+
+        // The leading piece is mirrored to the a to d files
+        if squares[0] & 7 > 3 {
+            for sq in &mut squares[..count] {
+                *sq ^= 7;
+            }
+        }
+
+        let mut index = if self.has_pawns {
+            squares[1..lead_pawn_count].sort_by_key(|sq| encoding.map_pawns[*sq]);
+
+            (1..lead_pawn_count).fold(
+                encoding.lead_pawn_index[lead_pawn_count][squares[0]],
+                |index, i| index + encoding.binomial[i][encoding.map_pawns[squares[i]]],
+            )
+        } else {
+            // Without pawns, the leading piece is also mirrored to the first ranks
+            // and below the a1-h8 diagonal
+            if squares[0] >> 3 > 3 {
+                for sq in &mut squares[..count] {
+                    *sq ^= 56;
+                }
+            }
+
+            let first_off_diagonal =
+                (0..data.group_length[0]).find(|i| off_diagonal(squares[*i]) != 0);
+
+            if let Some(i) = first_off_diagonal.filter(|i| off_diagonal(squares[*i]) > 0) {
+                for sq in &mut squares[i..count] {
+                    *sq = flip_diagonal(*sq);
+                }
+            }
+
+            self.leading_pieces_index(&squares)
+        };
+
+        index *= data.group_index[0];
+
+        // The remaining groups are encoded by the squares that the previous groups left free
+        let mut group_start = data.group_length[0];
+        let mut remaining_pawns = self.has_pawns && self.pawn_counts[1] > 0;
+
+        for group in 1.. {
+            let length = data.group_length[group];
+
+            if length == 0 {
+                break;
+            }
+
+            squares[group_start..group_start + length].sort_unstable();
+            let mut group_index = 0;
+
+            for i in 0..length {
+                let sq = squares[group_start + i];
+                let taken = squares[..group_start].iter().filter(|other| sq > **other);
+                let mut free_index = sq - taken.count();
+
+                if remaining_pawns {
+                    free_index -= 8;
+                }
+
+                group_index += encoding.binomial[i + 1][free_index];
+            }
+
+            remaining_pawns = false;
+            index += group_index * data.group_index[group];
+            group_start += length;
+        }
+
+        Some((file, data, index))
+    }
+
+    /// The index of the leading group of a table without pawns.
+    ///
+    /// The first piece is in the a1-d1-d4 triangle and, like the following pieces,
+    /// not above the a1-h8 diagonal if the pieces before are on it.
+    fn leading_pieces_index(&self, squares: &[usize; MAX_PIECES]) -> u64 {
+        let encoding = encoding();
+
+        if !self.has_unique_pieces {
+            // Only the kings are encoded together
+            return encoding.map_kk[encoding.map_a1d1d4[squares[0]]][squares[1]];
+        }
+
+        let [first, second, third] = [squares[0], squares[1], squares[2]];
+        let rank = |sq: usize| (sq >> 3) as u64;
+        let adjust_second = (second > first) as u64;
+        let adjust_third = (third > first) as u64 + (third > second) as u64;
+
+        if off_diagonal(first) != 0 {
+            (encoding.map_a1d1d4[first] as u64 * 63 + second as u64 - adjust_second) * 62
+                + third as u64
+                - adjust_third
+        } else if off_diagonal(second) != 0 {
+            (6 * 63 + rank(first) * 28 + encoding.map_b1h1h7[second]) * 62 + third as u64
+                - adjust_third
+        } else if off_diagonal(third) != 0 {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + rank(first) * 7 * 28
+                + (rank(second) - adjust_second) * 28
+                + encoding.map_b1h1h7[third]
+        } else {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + 4 * 7 * 28
+                + rank(first) * 7 * 6
+                + (rank(second) - adjust_second) * 6
+                + rank(third)
+                - adjust_third
+        }
+    }
+
+    /// The bytes of the block, which are read from the file the first time the block is probed.
+    fn block<'a>(&self, data: &'a PairsData, block: u64) -> io::Result<&'a [u8]> {
+        let cached = data
+            .blocks
+            .get(block as usize)
+            .ok_or_else(|| invalid_data("invalid block"))?;
+
+        if let Some(bytes) = cached.get() {
+            return Ok(bytes);
+        }
+
+        // Another thread may read the same block at the same time, then its bytes are dropped
+        let mut bytes = vec![0; data.block_size as usize];
+        read_at(&self.file, data.data + block * data.block_size, &mut bytes)?;
+
+        Ok(cached.get_or_init(|| bytes.into_boxed_slice()))
+    }
+
+    /// Decompress the value with the given index.
+    fn decompress(&self, data: &PairsData, index: u64) -> io::Result<u16> {
+        if data.flags & FLAG_SINGLE_VALUE != 0 {
+            return Ok(data.min_symbol_length as u16);
+        }
+
+        // The sparse index points to the block and offset of every span-th value,
+        // from there we walk to the block of the index
+        let &(block, offset) = data
+            .sparse_index
+            .get((index / data.span) as usize)
+            .ok_or_else(|| invalid_data("invalid sparse index"))?;
+        let mut block = block as u64;
+        let mut offset = offset as i64 + (index % data.span) as i64 - (data.span / 2) as i64;
+        let block_length = |block: u64| {
+            data.block_lengths
+                .get(block as usize)
+                .map(|length| *length as i64)
+                .ok_or_else(|| invalid_data("invalid block lengths"))
+        };
+
+        while offset < 0 {
+            block = block
+                .checked_sub(1)
+                .ok_or_else(|| invalid_data("invalid sparse index"))?;
+            offset += block_length(block)? + 1;
+        }
+
+        loop {
+            let length = block_length(block)?;
+
+            if offset <= length {
+                break;
+            }
+
+            offset -= length + 1;
+            block += 1;
+        }
+
+        let bytes = self.block(data, block)?;
+        let read_u32 = |position: usize| {
+            let mut word = [0; 4];
+
+            for (i, byte) in word.iter_mut().enumerate() {
+                *byte = bytes.get(position + i).copied().unwrap_or(0);
+            }
+
+            u32::from_be_bytes(word) as u64
+        };
+
+        // Find the symbol that contains the offset, symbols expand to a varying number of values
+        let min_length = data.min_symbol_length as usize;
+        let mut buffer = (read_u32(0) << 32) | read_u32(4);
+        let mut buffer_size = 64;
+        let mut position = 8;
+        let mut offset = offset as u32;
+
+        let mut symbol = loop {
+            let length = data
+                .base64
+                .iter()
+                .position(|base| buffer >= *base)
+                .ok_or_else(|| invalid_data("invalid Huffman code"))?;
+            let shift = (64 - length - min_length) as u32;
+            let symbol = (buffer - data.base64[length])
+                .checked_shr(shift)
+                .unwrap_or(0) as u16;
+            let symbol = symbol.wrapping_add(data.lowest_symbols[length]) as usize;
+            let symbol_length = *data
+                .symbol_lengths
+                .get(symbol)
+                .ok_or_else(|| invalid_data("invalid symbol"))?;
+
+            if offset <= symbol_length {
+                break symbol;
+            }
+
+            offset -= symbol_length + 1;
+            buffer <<= length + min_length;
+            buffer_size -= length + min_length;
+
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= read_u32(position) << (64 - buffer_size);
+                position += 4;
+            }
+        };
+
+        // Expand the pairs of the symbol until the value is reached
+        while data.symbol_lengths[symbol] > 0 {
+            let [left, right] = data.pairs[symbol];
+            let left_length = data.symbol_lengths[left as usize];
+
+            if offset <= left_length {
+                symbol = left as usize;
+            } else {
+                offset -= left_length + 1;
+                symbol = right as usize;
+            }
+        }
+
+        Ok(data.pairs[symbol][0])
+    }
+
+    /// Look up the result of the position.
+    ///
+    /// The captures are not considered, the table may store any value if a capture is better.
+    pub fn probe_wdl(&self, board: &Board) -> io::Result<Wdl> {
+        let (_, data, index) = self
+            .index(board)
+            .ok_or_else(|| invalid_data("the position is not in the table"))?;
+        let value = self.decompress(data, index)?;
+
+        Wdl::from_value(value as i32 - 2).ok_or_else(|| invalid_data("invalid WDL value"))
+    }
+
+    /// Look up the distance to the next capture or pawn move of the position, in plies.
+    ///
+    /// The result of the position has to be known to decode the distance.
+    /// Returns `None` if the table only stores the positions of the other player to move.
+    pub fn probe_dtz(&self, board: &Board, wdl: Wdl) -> io::Result<Option<i32>> {
+        let Some((_, data, index)) = self.index(board) else {
+            return Ok(None);
+        };
+        let mut value = self.decompress(data, index)? as usize;
+
+        if data.flags & FLAG_MAPPED != 0 {
+            let map_index = match wdl {
+                Wdl::Win | Wdl::Draw => data.map_index[0],
+                Wdl::Loss => data.map_index[1],
+                Wdl::CursedWin => data.map_index[2],
+                Wdl::BlessedLoss => data.map_index[3],
+            } + value;
+
+            value = if data.flags & FLAG_WIDE != 0 {
+                self.dtz_map
+                    .get(2 * map_index..2 * map_index + 2)
+                    .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]) as usize)
+            } else {
+                self.dtz_map.get(map_index).map(|value| *value as usize)
+            }
+            .ok_or_else(|| invalid_data("invalid DTZ map"))?;
+        }
+
+        // Some distances are stored in moves instead of plies
+        let in_moves = match wdl {
+            Wdl::Win => data.flags & FLAG_WIN_PLIES == 0,
+            Wdl::Loss => data.flags & FLAG_LOSS_PLIES == 0,
+            Wdl::CursedWin | Wdl::BlessedLoss => true,
+            Wdl::Draw => false,
+        };
+
+        if in_moves {
+            value *= 2;
+        }
+
+        Ok(Some(value as i32 + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use pleco::Board;
+
+    use super::{
+        writer::{single_value_table, write_compressed_table},
+        Table, TableKind,
+    };
+    use crate::stonefish::tablebase::material::Material;
+    use crate::stonefish::tablebase::Wdl;
+
+    /// The FEN of a position with White to move.
+    fn fen(pieces: &[(char, usize)]) -> String {
+        let mut squares = ['1'; 64];
+
+        for (piece, sq) in pieces {
+            squares[*sq] = *piece;
+        }
+
+        let ranks: Vec<String> = squares
+            .chunks(8)
+            .rev()
+            .map(|rank| rank.iter().collect())
+            .collect();
+
+        format!("{} w - - 0 1", ranks.join("/"))
+    }
+
+    #[test]
+    fn should_encode_symmetric_positions_to_same_index() {
+        let material = Material::from_name("KQvKR").unwrap();
+        let path = env::temp_dir().join(format!("stonefish-{}-KQvKR.rtbw", process::id()));
+        fs::write(&path, single_value_table(TableKind::Wdl, material, &[2, 2])).unwrap();
+        let table = Table::open(&path, TableKind::Wdl, material).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let symmetries: [fn(usize) -> usize; 3] =
+            [|sq| sq ^ 7, |sq| sq ^ 56, |sq| ((sq >> 3) | (sq << 3)) & 63];
+        let mut positions = 0;
+
+        for white_king in 0..64 {
+            for queen in (0..64).step_by(3) {
+                for (black_king, rook) in [(0, 63), (20, 43), (35, 9), (62, 17)] {
+                    let pieces = [
+                        ('K', white_king),
+                        ('Q', queen),
+                        ('k', black_king),
+                        ('r', rook),
+                    ];
+                    let squares = [white_king, queen, black_king, rook];
+                    let is_occupied = |sq| squares.iter().filter(|other| **other == sq).count() > 1;
+
+                    if squares.into_iter().any(is_occupied) {
+                        continue;
+                    }
+
+                    let Ok(board) = Board::from_fen(&fen(&pieces)) else {
+                        continue;
+                    };
+                    let (_, _, index) = table.index(&board).unwrap();
+                    assert!(index < table.pairs_data[0][0].size(), "{}", fen(&pieces));
+
+                    // If the leading pieces are all on a diagonal, the other pieces are not mirrored to one side of it
+                    let on_diagonal =
+                        |diagonal: fn(usize) -> bool| squares[..3].iter().all(|sq| diagonal(*sq));
+                    let is_ambiguous = on_diagonal(|sq| sq >> 3 == sq & 7)
+                        || on_diagonal(|sq| (sq >> 3) + (sq & 7) == 7);
+
+                    for symmetry in symmetries.iter().take(if is_ambiguous { 2 } else { 3 }) {
+                        let mirrored = pieces.map(|(piece, sq)| (piece, symmetry(sq)));
+                        let board = Board::from_fen(&fen(&mirrored)).unwrap();
+
+                        assert_eq!(table.index(&board).unwrap().2, index, "{}", fen(&mirrored));
+                    }
+
+                    positions += 1;
+                }
+            }
+        }
+
+        assert!(positions > 1000, "{positions}");
+    }
+
+    #[test]
+    fn should_decompress_values() {
+        let wins: Vec<u16> = (0..40).map(|moves| 7 * moves).collect();
+        // The DTZ maps with wide values are stored with 16 bits
+        let parameters = [
+            (
+                "KPvK",
+                TableKind::Wdl,
+                [vec![], vec![], vec![], vec![]],
+                5,
+                None,
+            ),
+            (
+                "KPvK",
+                TableKind::Dtz,
+                [wins[..30].to_vec(), vec![3], vec![], vec![]],
+                30,
+                Some("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1"),
+            ),
+            (
+                "KRvK",
+                TableKind::Dtz,
+                [wins.clone(), vec![3], vec![], vec![]],
+                40,
+                Some("4k3/8/8/8/3R4/8/8/4K3 w - - 0 1"),
+            ),
+        ];
+
+        for (name, kind, dtz_map, symbols, fen) in parameters {
+            let material = Material::from_name(name).unwrap();
+            let path = env::temp_dir().join(format!("stonefish-{}-{name}-{kind:?}", process::id()));
+            // Runs of values, so that the symbols are paired and their codes have different lengths
+            let value = move |file: usize, side: usize, index: u64| {
+                ((index / 3 + index / 101) as usize + file + side) % symbols
+            };
+            write_compressed_table(&path, kind, material, &dtz_map, |file, side, index| {
+                value(file, side, index) as u16
+            });
+            let table = Table::open(&path, kind, material).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            for (file, file_data) in table.pairs_data.iter().enumerate() {
+                for (side, data) in file_data.iter().enumerate() {
+                    for index in 0..data.size() {
+                        let expected = value(file, side, index) as u16;
+                        assert_eq!(
+                            table.decompress(data, index).unwrap(),
+                            expected,
+                            "{name} {index}"
+                        );
+                    }
+                }
+            }
+
+            if let Some(fen) = fen {
+                // The distances to zeroing are looked up in the map and converted from moves to plies
+                let board = Board::from_fen(fen).unwrap();
+                let (file, _, index) = table.index(&board).unwrap();
+                let moves = dtz_map[0][value(file, 0, index)] as i32;
+                assert_eq!(
+                    table.probe_dtz(&board, Wdl::Win).unwrap(),
+                    Some(2 * moves + 1),
+                    "{name}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,437 @@
+//! Writing table files for the tests.
+//!
+//! The values are compressed like in the table files, with small blocks
+//! so that every part of the decompression is used.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    fs,
+    path::Path,
+};
+
+use pleco::{PieceType, Player};
+
+use super::{
+    Table, TableKind, FLAG_MAPPED, FLAG_SINGLE_VALUE, FLAG_WIDE, HEADER_HAS_PAWNS, HEADER_SPLIT,
+    LEAF_SYMBOL,
+};
+use crate::stonefish::tablebase::material::Material;
+
+/// The pieces of a table in the order of their encoding.
+///
+/// The pawns come first, the other pieces are ordered like in the material name.
+/// Only one player may have pawns.
+fn table_pieces(material: Material) -> Vec<u8> {
+    let white_pawns = material.count(Player::White, PieceType::P);
+    let black_pawns = material.count(Player::Black, PieceType::P);
+    assert!(white_pawns == 0 || black_pawns == 0);
+
+    let leader = if black_pawns > 0 {
+        Player::Black
+    } else {
+        Player::White
+    };
+    let code = |player: Player, piece: PieceType| piece as u8 | (player as u8) << 3;
+
+    let pawns = material.count(leader, PieceType::P) as usize;
+    let mut pieces = vec![code(leader, PieceType::P); pawns];
+
+    for player in [Player::White, Player::Black] {
+        for piece in [
+            PieceType::K,
+            PieceType::Q,
+            PieceType::R,
+            PieceType::B,
+            PieceType::N,
+        ] {
+            for _ in 0..material.count(player, piece) {
+                pieces.push(code(player, piece));
+            }
+        }
+    }
+
+    pieces
+}
+
+/// The bytes of the header of a table, up to the sizes of the values.
+fn table_header(kind: TableKind, material: Material) -> Vec<u8> {
+    let pieces = table_pieces(material);
+    let has_pawns = pieces[0] & 7 == PieceType::P as u8;
+    let mut bytes = kind.magic().to_vec();
+    let split = if material == material.flipped() {
+        0
+    } else {
+        HEADER_SPLIT
+    };
+    bytes.push(if has_pawns {
+        split | HEADER_HAS_PAWNS
+    } else {
+        split
+    });
+
+    for _ in 0..if has_pawns { 4 } else { 1 } {
+        // The order of the groups
+        bytes.push(0);
+        bytes.extend(pieces.iter().map(|piece| piece | piece << 4));
+    }
+
+    if bytes.len() % 2 == 1 {
+        bytes.push(0);
+    }
+
+    bytes
+}
+
+/// The bytes of a table that stores a single value
+/// for each file of the leading pawn and player to move.
+pub(crate) fn single_value_table(kind: TableKind, material: Material, values: &[u8]) -> Vec<u8> {
+    let mut bytes = table_header(kind, material);
+
+    for value in values {
+        bytes.extend([FLAG_SINGLE_VALUE, *value]);
+    }
+
+    bytes.resize(bytes.len().next_multiple_of(64) + 16, 0);
+    bytes
+}
+
+/// The parts of a table file with the compressed values for one player to move and file of the leading pawn.
+struct CompressedValues {
+    /// The sizes and the Huffman codes, starting with the flags.
+    sizes: Vec<u8>,
+    /// The sparse index of the blocks.
+    sparse_index: Vec<u8>,
+    /// The number of values (minus one) of each block.
+    block_lengths: Vec<u8>,
+    /// The blocks with the Huffman codes of the symbols.
+    blocks: Vec<u8>,
+}
+
+/// Compress the values with recursive pairing and canonical Huffman codes.
+///
+/// The blocks are small, so that the values are spread over many blocks.
+fn compress_values(values: &[u16], flags: u8) -> CompressedValues {
+    const BLOCK_SIZE_BITS: u8 = 5;
+    const SPAN_BITS: u8 = 7;
+    /// The maximum number of values of a symbol, so that the offsets in a block fit in 16 bits.
+    const MAX_SYMBOL_VALUES: u32 = 128;
+
+    if values.iter().all(|value| *value == values[0]) {
+        return CompressedValues {
+            sizes: vec![flags | FLAG_SINGLE_VALUE, values[0] as u8],
+            sparse_index: vec![],
+            block_lengths: vec![],
+            blocks: vec![],
+        };
+    }
+
+    // Every value is a leaf symbol, pairs of symbols that often follow each other get their own symbol
+    let mut leaves: Vec<u16> = values.to_vec();
+    leaves.sort_unstable();
+    leaves.dedup();
+    let mut pairs: Vec<[u16; 2]> = leaves.iter().map(|value| [*value, LEAF_SYMBOL]).collect();
+    let mut symbol_values = vec![1; pairs.len()];
+    let mut symbols: Vec<u16> = values
+        .iter()
+        .map(|value| leaves.binary_search(value).unwrap() as u16)
+        .collect();
+
+    for _ in 0..16 {
+        let mut counts: HashMap<(u16, u16), usize> = HashMap::new();
+
+        for pair in symbols.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_default() += 1;
+        }
+
+        let best_pair = counts
+            .into_iter()
+            .filter(|((left, right), _)| {
+                symbol_values[*left as usize] + symbol_values[*right as usize] <= MAX_SYMBOL_VALUES
+            })
+            .max_by_key(|(pair, count)| (*count, Reverse(*pair)));
+
+        let Some(((left, right), 4..)) = best_pair else {
+            break;
+        };
+
+        let symbol = pairs.len() as u16;
+        pairs.push([left, right]);
+        symbol_values.push(symbol_values[left as usize] + symbol_values[right as usize]);
+
+        let mut paired = Vec::with_capacity(symbols.len());
+        let mut i = 0;
+
+        while i < symbols.len() {
+            if symbols[i] == left && symbols.get(i + 1) == Some(&right) {
+                paired.push(symbol);
+                i += 2;
+            } else {
+                paired.push(symbols[i]);
+                i += 1;
+            }
+        }
+
+        symbols = paired;
+    }
+
+    // The lengths of the Huffman codes, a single symbol still needs one bit
+    let mut frequencies = vec![0usize; pairs.len()];
+
+    for symbol in &symbols {
+        frequencies[*symbol as usize] += 1;
+    }
+
+    let mut code_lengths = vec![0u32; pairs.len()];
+    let mut trees: Vec<Vec<usize>> = vec![];
+    let mut heap = BinaryHeap::new();
+
+    for (symbol, frequency) in frequencies.iter().enumerate() {
+        if *frequency > 0 {
+            heap.push(Reverse((*frequency, trees.len())));
+            trees.push(vec![symbol]);
+        }
+    }
+
+    while heap.len() > 1 {
+        let Reverse((first_frequency, first)) = heap.pop().unwrap();
+        let Reverse((second_frequency, second)) = heap.pop().unwrap();
+        let mut tree = std::mem::take(&mut trees[first]);
+        tree.append(&mut trees[second]);
+
+        for symbol in &tree {
+            code_lengths[*symbol] += 1;
+        }
+
+        heap.push(Reverse((first_frequency + second_frequency, trees.len())));
+        trees.push(tree);
+    }
+
+    for length in &mut code_lengths {
+        if *length == 0 {
+            *length = 1;
+        }
+    }
+
+    // The symbols are numbered from the longest codes to the shortest, the unused symbols last
+    let is_used = |symbol: &usize| frequencies[*symbol] > 0;
+    let mut order: Vec<usize> = (0..pairs.len()).collect();
+    order.sort_by_key(|symbol| (!is_used(symbol), Reverse(code_lengths[*symbol])));
+    let mut numbers = vec![0; pairs.len()];
+
+    for (number, symbol) in order.iter().enumerate() {
+        numbers[*symbol] = number as u16;
+    }
+
+    let used_lengths = || {
+        (0..pairs.len())
+            .filter(is_used)
+            .map(|symbol| code_lengths[symbol])
+    };
+    let min_length = used_lengths().min().unwrap();
+    let max_length = used_lengths().max().unwrap();
+    assert!(max_length <= 32, "the Huffman codes are too long");
+
+    let lengths = (max_length - min_length + 1) as usize;
+    let mut counts = vec![0u64; lengths];
+
+    for length in used_lengths() {
+        counts[(length - min_length) as usize] += 1;
+    }
+
+    let mut lowest_symbols = vec![0; lengths];
+    let mut bases = vec![0u64; lengths];
+
+    for i in (0..lengths - 1).rev() {
+        lowest_symbols[i] = lowest_symbols[i + 1] + counts[i + 1] as u16;
+        bases[i] = (bases[i + 1] + counts[i + 1]) / 2;
+    }
+
+    let code = |symbol: usize| {
+        let i = (code_lengths[symbol] - min_length) as usize;
+        let code = bases[i] + (numbers[symbol] - lowest_symbols[i]) as u64;
+        (code, code_lengths[symbol])
+    };
+
+    // The blocks hold whole symbols, with the bits of the codes from the highest
+    let block_size = 1 << BLOCK_SIZE_BITS;
+    let mut blocks = vec![];
+    let mut block_values = vec![];
+    let mut bits: Vec<bool> = vec![];
+    let mut values_in_block = 0;
+
+    let mut finish_block = |bits: &mut Vec<bool>, values_in_block: &mut u32| {
+        let mut block = vec![0; block_size];
+
+        for (i, bit) in bits.iter().enumerate() {
+            block[i / 8] |= (*bit as u8) << (7 - i % 8);
+        }
+
+        blocks.extend(block);
+        block_values.push(*values_in_block);
+        bits.clear();
+        *values_in_block = 0;
+    };
+
+    for symbol in &symbols {
+        let (code, length) = code(*symbol as usize);
+
+        if bits.len() + length as usize > 8 * block_size {
+            finish_block(&mut bits, &mut values_in_block);
+        }
+
+        bits.extend((0..length).rev().map(|bit| code >> bit & 1 == 1));
+        values_in_block += symbol_values[*symbol as usize];
+    }
+
+    finish_block(&mut bits, &mut values_in_block);
+
+    // The sparse index points to the block and offset of the middle of every span
+    let span = 1 << SPAN_BITS;
+    let mut block_starts = vec![0];
+
+    for values in &block_values {
+        block_starts.push(block_starts.last().unwrap() + *values as usize);
+    }
+
+    let mut sparse_index = vec![];
+
+    for entry in 0..values.len().div_ceil(span) {
+        let position = entry * span + span / 2;
+        let block = (block_starts.partition_point(|start| *start <= position) - 1)
+            .min(block_values.len() - 1);
+        let offset = u16::try_from(position - block_starts[block]).unwrap();
+        sparse_index.extend((block as u32).to_le_bytes());
+        sparse_index.extend(offset.to_le_bytes());
+    }
+
+    let mut sizes = vec![flags, BLOCK_SIZE_BITS, SPAN_BITS, 0];
+    sizes.extend((block_values.len() as u32).to_le_bytes());
+    sizes.extend([max_length as u8, min_length as u8]);
+    sizes.extend(
+        lowest_symbols
+            .iter()
+            .flat_map(|symbol| symbol.to_le_bytes()),
+    );
+    sizes.extend((pairs.len() as u16).to_le_bytes());
+
+    let mut numbered_pairs = vec![[0; 2]; pairs.len()];
+
+    for (symbol, [left, right]) in pairs.iter().enumerate() {
+        numbered_pairs[numbers[symbol] as usize] = if *right == LEAF_SYMBOL {
+            [*left, LEAF_SYMBOL]
+        } else {
+            [numbers[*left as usize], numbers[*right as usize]]
+        };
+    }
+
+    for [left, right] in numbered_pairs {
+        sizes.extend([
+            left as u8,
+            (left >> 8) as u8 | (right << 4) as u8,
+            (right >> 4) as u8,
+        ]);
+    }
+
+    if pairs.len() % 2 == 1 {
+        sizes.push(0);
+    }
+
+    CompressedValues {
+        sizes,
+        sparse_index,
+        block_lengths: block_values
+            .iter()
+            .flat_map(|values| (*values as u16 - 1).to_le_bytes())
+            .collect(),
+        blocks,
+    }
+}
+
+/// Write a table whose values are compressed like in the table files.
+///
+/// The function gives the stored value by file of the leading pawn, player to move and index.
+/// DTZ tables store the positions with White to move and use the DTZ map if it is not empty.
+pub(crate) fn write_compressed_table(
+    path: &Path,
+    kind: TableKind,
+    material: Material,
+    dtz_map: &[Vec<u16>; 4],
+    value: impl Fn(usize, usize, u64) -> u16,
+) {
+    // A table with single values has the same number of positions
+    let has_pawns = table_pieces(material)[0] & 7 == PieceType::P as u8;
+    let files = if has_pawns { 4 } else { 1 };
+    let sides = if kind == TableKind::Wdl && material != material.flipped() {
+        2
+    } else {
+        1
+    };
+    fs::write(
+        path,
+        single_value_table(kind, material, &vec![0; files * sides]),
+    )
+    .unwrap();
+    let layout = Table::open(path, kind, material).unwrap();
+
+    let is_mapped = kind == TableKind::Dtz && dtz_map.iter().any(|map| !map.is_empty());
+    let is_wide = dtz_map.iter().flatten().any(|value| *value > 0xff);
+    let flags = match (is_mapped, is_wide) {
+        (false, _) => 0,
+        (true, false) => FLAG_MAPPED,
+        (true, true) => FLAG_MAPPED | FLAG_WIDE,
+    };
+    let compressed: Vec<CompressedValues> = (0..files)
+        .flat_map(|file| (0..sides).map(move |side| (file, side)))
+        .map(|(file, side)| {
+            let size = layout.pairs_data[file][side].size();
+            let values: Vec<u16> = (0..size).map(|index| value(file, side, index)).collect();
+
+            compress_values(&values, flags)
+        })
+        .collect();
+
+    let mut bytes = table_header(kind, material);
+
+    for values in &compressed {
+        bytes.extend(&values.sizes);
+    }
+
+    if kind == TableKind::Dtz {
+        for _ in 0..files * is_mapped as usize {
+            if is_wide && bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+
+            for map in dtz_map {
+                if is_wide {
+                    bytes.extend((map.len() as u16).to_le_bytes());
+                    bytes.extend(map.iter().flat_map(|value| value.to_le_bytes()));
+                } else {
+                    bytes.push(map.len() as u8);
+                    bytes.extend(map.iter().map(|value| *value as u8));
+                }
+            }
+        }
+
+        if bytes.len() % 2 == 1 {
+            bytes.push(0);
+        }
+    }
+
+    for values in &compressed {
+        bytes.extend(&values.sparse_index);
+    }
+
+    for values in &compressed {
+        bytes.extend(&values.block_lengths);
+    }
+
+    for values in &compressed {
+        bytes.resize(bytes.len().next_multiple_of(64), 0);
+        bytes.extend(&values.blocks);
+    }
+
+    bytes.resize(bytes.len().next_multiple_of(64) + 16, 0);
+    fs::write(path, bytes).unwrap();
+}
@@ -1,17 +1,94 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, OnceLock},
+    time::{Duration, Instant},
+};
 
-use pleco::Player;
+use pleco::{BitMove, Player};
 
 use crate::uci::uci_command::UciGoConfig;
 
-pub fn get_max_time(go_config: UciGoConfig, player: Player) -> Option<Duration> {
+use super::{evaluation::Evaluation, node::Node, types::Children};
+
+/// The default time reserved for the communication with the GUI, in milliseconds.
+pub const DEFAULT_MOVE_OVERHEAD_MS: usize = 500;
+/// The minimum time reserved for the communication with the GUI, in milliseconds.
+pub const MIN_MOVE_OVERHEAD_MS: usize = 0;
+/// The maximum time reserved for the communication with the GUI, in milliseconds.
+pub const MAX_MOVE_OVERHEAD_MS: usize = 10_000;
+
+/// The time to search for if the GUI doesn't set any limit.
+const DEFAULT_SEARCH_TIME: Duration = Duration::from_secs(10);
+/// The number of moves to divide the remaining time between, if the GUI doesn't say.
+const DEFAULT_MOVES_TO_GO: usize = 30;
+/// The hard limit is at most this multiple of the soft limit.
+const HARD_LIMIT_FACTOR: u32 = 5;
+/// The hard limit uses at most this share of the remaining time, in percent.
+const MAX_TIME_USAGE_PERCENT: u32 = 80;
+
+/// The soft limit is extended by this factor if the best move changed in the last iteration.
+const BEST_MOVE_CHANGE_SCALE: f64 = 1.5;
+/// The soft limit is extended by this factor if the evaluation dropped in the last iteration.
+const SCORE_DROP_SCALE: f64 = 1.3;
+/// A drop of the evaluation by more than this many centipawns extends the soft limit.
+const SCORE_DROP_CP: i32 = 30;
+/// The soft limit is shortened by this factor if one move is clearly best.
+const CLEAR_BEST_MOVE_SCALE: f64 = 0.5;
+/// A move is clearly best if it stayed the best move for this many iterations...
+const CLEAR_BEST_MOVE_ITERATIONS: usize = 4;
+/// ...and took at least this share of the searched nodes, the other moves were refuted quickly.
+const CLEAR_BEST_MOVE_EFFORT: f64 = 0.9;
+
+/// The minimum effective branching factor, to not underestimate the next iteration.
+const MIN_BRANCHING_FACTOR: f64 = 1.5;
+/// The maximum effective branching factor, the short iterations at the start are too noisy.
+const MAX_BRANCHING_FACTOR: f64 = 6.0;
+
+/// When our clock started, set once the engine stopped pondering.
+pub type ClockStart = Arc<OnceLock<Instant>>;
+
+/// The time limits of a search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeLimits {
+    /// No new iteration should be started after this time.
+    ///
+    /// It is adjusted to the stability of the search, see [`TimeManager`].
+    /// `None` if the search time is fixed, then the whole time is used.
+    pub soft: Option<Duration>,
+    /// The search is aborted after this time.
+    pub hard: Duration,
+}
+
+impl TimeLimits {
+    /// Search for exactly the given time.
+    fn fixed(time: Duration) -> Self {
+        Self {
+            soft: None,
+            hard: time,
+        }
+    }
+}
+
+/// Determine the time limits of the search from the configuration of the GUI.
+///
+/// The remaining time is divided between the moves to the next time control,
+/// most of the increment can be used on top.
+/// `move_overhead` is reserved for the communication with the GUI.
+///
+/// Returns `None` if the search time is not limited.
+pub fn get_time_limits(
+    go_config: UciGoConfig,
+    player: Player,
+    move_overhead: Duration,
+) -> Option<TimeLimits> {
     // Check if the search time is already determined by the GUI
     if go_config.infinite {
         // Search infinitely
         return None;
     } else if let Some(move_time_ms) = go_config.move_time_ms {
         // Search for a fixed time
-        return Some(Duration::from_millis(move_time_ms.try_into().unwrap()));
+        return Some(TimeLimits::fixed(Duration::from_millis(
+            move_time_ms.try_into().unwrap(),
+        )));
     }
 
     // Determine player time and increment
@@ -23,52 +100,212 @@ pub fn get_max_time(go_config: UciGoConfig, player: Player) -> Option<Duration>
     // Get the remaining time on the clock
     let time_ms = if let Some(time_ms) = time {
         time_ms
-    } else if go_config.max_depth.or(go_config.search_mate).is_some() {
-        // No time is given, but a maximum depth
-        // Just consider the depth and don't restrict the time
+    } else if go_config.max_depth.or(go_config.search_mate).is_some()
+        || go_config.max_nodes.is_some()
+    {
+        // No time is given, but a maximum depth or node count
+        // Just consider these limits and don't restrict the time
         return None;
     } else {
-        // No time information is given, only search for 10 seconds
-        return Some(Duration::from_millis(10_000));
+        // No time information is given
+        return Some(TimeLimits::fixed(DEFAULT_SEARCH_TIME));
     };
 
-    // Determine base time to search for
-    let base_time_ms = match time_ms {
-        // Up to 10 seconds
-        0..=10_000 => 1_000,
-        // 10 seconds to 1 minute
-        10_001..=60_000 => 5_000,
-        // 1 minute to 3 minutes
-        60_001..=180_000 => 7_000,
-        // 3 minutes to 5 minutes
-        180_001..=300_000 => 10_000,
-        // 5 minutes to 10 minutes
-        300_001..=600_000 => 20_000,
-        // 10 minutes to 30 minutes
-        600_001..=1_800_000 => 30_000,
-        // More than 30 minutes
-        _ => 60_000,
+    // Never use the time that is needed to send the move
+    let available = Duration::from_millis(time_ms as u64).saturating_sub(move_overhead);
+    let increment = Duration::from_millis(increment_ms as u64);
+
+    let moves_to_go = if go_config.moves_to_go > 0 {
+        go_config.moves_to_go.min(DEFAULT_MOVES_TO_GO)
+    } else {
+        DEFAULT_MOVES_TO_GO
     };
 
-    // Also add the increment time
-    let mut search_time_ms = base_time_ms + increment_ms;
+    let hard_max = available * MAX_TIME_USAGE_PERCENT / 100;
+    let soft = (available / moves_to_go as u32 + increment * 3 / 4).min(hard_max);
+    let hard = (soft * HARD_LIMIT_FACTOR).min(hard_max);
 
-    // Don't search longer than the remaining time
-    // Including a 500 ms delay to account for API latency
-    search_time_ms = search_time_ms.min(time_ms).saturating_sub(500);
+    Some(TimeLimits {
+        soft: Some(soft),
+        hard,
+    })
+}
 
-    Some(Duration::from_millis(search_time_ms.try_into().unwrap()))
+/// Decides when to stop the search, adapting the soft limit to the stability of the search.
+///
+/// If the best move or the evaluation changes, the search needs more time to settle.
+/// If one move is clearly best, more iterations are unlikely to change it.
+/// An iteration that can't finish before the hard limit isn't started at all.
+#[derive(Debug, Clone)]
+pub struct TimeManager {
+    /// The soft limit before adapting it.
+    soft_limit: Duration,
+    /// The hard limit, after which the search is aborted.
+    hard_limit: Duration,
+    /// When our clock started, unset while we are pondering.
+    clock_start: ClockStart,
+    /// The best move of the previous iteration.
+    previous_best_move: Option<BitMove>,
+    /// The evaluation of the previous iteration.
+    previous_evaluation: Option<Evaluation>,
+    /// The number of iterations in a row that had the same best move.
+    stable_iterations: usize,
+    /// When the last iteration finished.
+    iteration_end: Instant,
+    /// How long the last iteration took.
+    iteration_time: Option<Duration>,
+}
+
+impl TimeManager {
+    /// Create a new time manager when the search starts.
+    ///
+    /// The limits are measured from `clock_start`, which is set once the engine stopped pondering.
+    pub fn new(
+        soft_limit: Duration,
+        hard_limit: Duration,
+        start: Instant,
+        clock_start: ClockStart,
+    ) -> Self {
+        Self {
+            soft_limit,
+            hard_limit,
+            clock_start,
+            previous_best_move: None,
+            previous_evaluation: None,
+            stable_iterations: 0,
+            iteration_end: start,
+            iteration_time: None,
+        }
+    }
+
+    /// The factor to scale the soft limit with, after an iteration with the given result.
+    ///
+    /// `best_move_effort` is the share of the nodes that was spent on the best move.
+    fn update_scale(
+        &mut self,
+        best_move: Option<BitMove>,
+        evaluation: Evaluation,
+        best_move_effort: f64,
+    ) -> f64 {
+        let mut scale = 1.0;
+
+        if self.previous_best_move.is_some() && best_move != self.previous_best_move {
+            self.stable_iterations = 0;
+            scale *= BEST_MOVE_CHANGE_SCALE;
+        } else {
+            self.stable_iterations += 1;
+        }
+
+        if let (Some(Evaluation::Centipawns(previous)), Evaluation::Centipawns(current)) =
+            (self.previous_evaluation, evaluation)
+        {
+            if previous - current > SCORE_DROP_CP {
+                scale *= SCORE_DROP_SCALE;
+            }
+        }
+
+        if self.stable_iterations >= CLEAR_BEST_MOVE_ITERATIONS
+            && best_move_effort >= CLEAR_BEST_MOVE_EFFORT
+        {
+            scale *= CLEAR_BEST_MOVE_SCALE;
+        }
+
+        self.previous_best_move = best_move;
+        self.previous_evaluation = Some(evaluation);
+
+        scale
+    }
+
+    /// Determine if the search should stop after the iteration with the given result,
+    /// instead of starting the next iteration.
+    ///
+    /// While pondering, the search never stops, the clock starts with the ponder hit.
+    pub fn should_stop(&mut self, node: &Node, children: &Children) -> bool {
+        let now = Instant::now();
+        let iteration_time = now - self.iteration_end;
+        let previous_iteration_time = self.iteration_time.replace(iteration_time);
+        self.iteration_end = now;
+
+        let Some(&clock_start) = self.clock_start.get() else {
+            // We are still pondering
+            return false;
+        };
+        let elapsed = now.saturating_duration_since(clock_start);
+
+        let best_move = node.best_line.first().copied();
+        let total_nodes: usize = children.iter().map(|child| child.size).sum();
+        let best_move_nodes = children
+            .iter()
+            .find(|child| child.board.last_move() == best_move)
+            .map_or(0, |child| child.size);
+        let best_move_effort = best_move_nodes as f64 / total_nodes.max(1) as f64;
+
+        let scale = self.update_scale(best_move, node.evaluation, best_move_effort);
+
+        // The next iteration would be aborted by the timer, the time is better saved
+        let cannot_finish = previous_iteration_time.is_some_and(|previous_iteration_time| {
+            let next_iteration_time =
+                estimate_next_iteration(iteration_time, previous_iteration_time);
+            elapsed + next_iteration_time > self.hard_limit
+        });
+
+        // With only one move, there is nothing to decide
+        children.len() <= 1 || cannot_finish || elapsed >= self.soft_limit.mul_f64(scale)
+    }
+}
+
+/// Estimate how long the next iteration takes, from the durations of the last two iterations.
+///
+/// Every iteration takes about the effective branching factor times longer than the one before.
+/// See <https://www.chessprogramming.org/Branching_Factor>.
+fn estimate_next_iteration(
+    iteration_time: Duration,
+    previous_iteration_time: Duration,
+) -> Duration {
+    let branching_factor = iteration_time.as_secs_f64() / previous_iteration_time.as_secs_f64();
+    let branching_factor = if branching_factor.is_finite() {
+        branching_factor.clamp(MIN_BRANCHING_FACTOR, MAX_BRANCHING_FACTOR)
+    } else {
+        MAX_BRANCHING_FACTOR
+    };
+
+    iteration_time.mul_f64(branching_factor)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::time::Duration;
+    use std::{
+        sync::{Arc, OnceLock},
+        time::{Duration, Instant},
+    };
 
-    use pleco::Player;
+    use pleco::{BitMove, Board, Player};
 
-    use crate::uci::uci_command::UciGoConfig;
+    use crate::{
+        stonefish::{
+            evaluation::Evaluation,
+            heuristic::{EvalParameters, PawnHashTable},
+            node::Node,
+            transposition_table::TranspositionTable,
+        },
+        uci::uci_command::UciGoConfig,
+    };
 
-    use super::get_max_time;
+    use super::{
+        estimate_next_iteration, get_time_limits, ClockStart, TimeLimits, TimeManager,
+        CLEAR_BEST_MOVE_ITERATIONS, DEFAULT_MOVE_OVERHEAD_MS,
+    };
+
+    /// The move with the given UCI notation.
+    fn find_move(moves: &[BitMove], uci_move: &str) -> BitMove {
+        *moves.iter().find(|mv| mv.stringify() == uci_move).unwrap()
+    }
+
+    /// The time limits with the default move overhead.
+    fn get_max_time(go_config: UciGoConfig, player: Player) -> Option<Duration> {
+        let move_overhead = Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS as u64);
+        get_time_limits(go_config, player, move_overhead).map(|limits| limits.hard)
+    }
 
     #[test]
     fn should_not_take_longer_than_remaining_time() {
@@ -146,6 +383,11 @@ mod tests {
 
         let expected = Some(Duration::from_millis(1_000));
 
+        // The whole time is used, it isn't adapted to the stability of the search
+        let move_overhead = Duration::from_millis(DEFAULT_MOVE_OVERHEAD_MS as u64);
+        let limits = get_time_limits(go_config.clone(), Player::White, move_overhead).unwrap();
+        assert_eq!(limits.soft, None);
+
         let actual_white = get_max_time(go_config.clone(), Player::White);
         let actual_black = get_max_time(go_config, Player::Black);
 
@@ -176,4 +418,159 @@ mod tests {
         assert_eq!(actual_white, None);
         assert_eq!(actual_black, None);
     }
+
+    #[test]
+    fn should_not_restrict_time_of_node_limited_search() {
+        let go_config = UciGoConfig {
+            search_moves: None,
+            ponder: false,
+            white_time_ms: None,
+            black_time_ms: None,
+            white_increment_ms: 0,
+            black_increment_ms: 0,
+            moves_to_go: 0,
+            max_depth: None,
+            max_nodes: Some(10_000),
+            search_mate: None,
+            move_time_ms: None,
+            infinite: false,
+        };
+
+        let actual_white = get_max_time(go_config.clone(), Player::White);
+        let actual_black = get_max_time(go_config, Player::Black);
+
+        assert_eq!(actual_white, None);
+        assert_eq!(actual_black, None);
+    }
+
+    #[test]
+    fn should_use_more_time_with_fewer_moves_to_go() {
+        let limits = |moves_to_go, move_overhead_ms| {
+            let go_config = UciGoConfig {
+                search_moves: None,
+                ponder: false,
+                white_time_ms: Some(60_000),
+                black_time_ms: Some(60_000),
+                white_increment_ms: 1_000,
+                black_increment_ms: 1_000,
+                moves_to_go,
+                max_depth: None,
+                max_nodes: None,
+                search_mate: None,
+                move_time_ms: None,
+                infinite: false,
+            };
+
+            get_time_limits(
+                go_config,
+                Player::White,
+                Duration::from_millis(move_overhead_ms),
+            )
+            .unwrap()
+        };
+
+        // 60 s for 30 moves and 3/4 of the increment
+        assert_eq!(
+            limits(0, 0),
+            TimeLimits {
+                soft: Some(Duration::from_millis(2_750)),
+                hard: Duration::from_millis(13_750),
+            }
+        );
+        assert!(limits(10, 0).soft > limits(20, 0).soft);
+        // The hard limit never uses all the time, even on the last move
+        assert_eq!(limits(1, 0).hard, Duration::from_millis(48_000));
+        // The move overhead is never used
+        assert!(limits(1, 10_000).hard < Duration::from_millis(50_000));
+        assert!(limits(0, 10_000).soft < limits(0, 0).soft);
+    }
+
+    #[test]
+    fn should_adapt_soft_limit_to_search_stability() {
+        let moves = Board::start_pos().generate_moves();
+        let (e2e4, d2d4) = (find_move(&moves, "e2e4"), find_move(&moves, "d2d4"));
+        let mut time_manager = TimeManager::new(
+            Duration::from_secs(1),
+            Duration::from_secs(5),
+            Instant::now(),
+            Arc::new(OnceLock::new()),
+        );
+
+        // The first iteration can't be compared
+        assert_eq!(
+            time_manager.update_scale(Some(e2e4), Evaluation::Centipawns(50), 0.5),
+            1.0
+        );
+
+        // The best move changed and the score dropped
+        let scale = time_manager.update_scale(Some(d2d4), Evaluation::Centipawns(0), 0.5);
+        assert!(scale > 1.5, "{scale}");
+
+        // The best move is stable, but not clearly best yet
+        for _ in 0..CLEAR_BEST_MOVE_ITERATIONS - 1 {
+            assert_eq!(
+                time_manager.update_scale(Some(d2d4), Evaluation::Centipawns(10), 0.95),
+                1.0
+            );
+        }
+
+        // The best move is clearly best
+        assert!(time_manager.update_scale(Some(d2d4), Evaluation::Centipawns(10), 0.95) < 1.0);
+        assert_eq!(
+            time_manager.update_scale(Some(d2d4), Evaluation::Centipawns(10), 0.5),
+            1.0
+        );
+    }
+
+    #[test]
+    fn should_estimate_next_iteration_from_branching_factor() {
+        let parameters = [
+            ("branching factor of 3", 300, 100, 900),
+            ("noisy first iterations", 10, 0, 60),
+            ("faster than the previous iteration", 100, 200, 150),
+            ("huge jump", 1_000, 10, 6_000),
+        ];
+
+        for (name, iteration_ms, previous_ms, expected_ms) in parameters {
+            let actual = estimate_next_iteration(
+                Duration::from_millis(iteration_ms),
+                Duration::from_millis(previous_ms),
+            );
+
+            // Allow for rounding errors of the factor
+            assert_eq!(
+                (actual.as_secs_f64() * 1000.0).round() as u64,
+                expected_ms,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn should_start_clock_with_ponder_hit() {
+        let mut node = Node::new(Board::start_pos());
+        let children = node.expand(
+            &EvalParameters::default(),
+            &mut PawnHashTable::new(),
+            &TranspositionTable::new(1),
+        );
+        node.update_attributes(&children);
+
+        let clock_start: ClockStart = Arc::new(OnceLock::new());
+        let mut time_manager = TimeManager::new(
+            Duration::from_millis(50),
+            Duration::from_secs(10),
+            Instant::now(),
+            clock_start.clone(),
+        );
+
+        // The search never stops while pondering
+        assert!(!time_manager.should_stop(&node, &children));
+
+        // The time since the ponder hit counts, even if the iteration started before it
+        clock_start
+            .set(Instant::now() - Duration::from_millis(100))
+            .unwrap();
+        assert!(time_manager.should_stop(&node, &children));
+    }
 }
@@ -0,0 +1,293 @@
+//! A fixed-size transposition table that can be shared between search threads.
+//!
+//! Every slot stores the key XORed with the data, so that a torn write from
+//! another thread is detected as a key mismatch instead of returning garbage.
+//! See <https://www.chessprogramming.org/Shared_Hash_Table#Lockless>.
+use std::{
+    fmt::Debug,
+    mem::size_of,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use pleco::BitMove;
+
+use super::evaluation::Evaluation;
+
+/// The default size of the transposition table, in MB.
+pub const DEFAULT_HASH_SIZE_MB: usize = 32;
+/// The minimum size of the transposition table, in MB.
+pub const MIN_HASH_SIZE_MB: usize = 1;
+/// The maximum size of the transposition table, in MB.
+pub const MAX_HASH_SIZE_MB: usize = 32_768;
+
+/// The kind of bound that the stored evaluation represents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The evaluation is exact.
+    Exact,
+    /// The search failed high, the real evaluation is at least this good.
+    Lower,
+    /// The search failed low, the real evaluation is at most this good.
+    Upper,
+}
+
+/// An entry of the transposition table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TableEntry {
+    /// The evaluation of the position, from the view of the player to move.
+    pub evaluation: Evaluation,
+    /// The kind of bound the evaluation represents.
+    pub bound: Bound,
+    /// The depth that the position has been searched to.
+    pub depth: usize,
+    /// The best move that has been found in the position.
+    pub best_move: Option<BitMove>,
+}
+
+impl TableEntry {
+    /// Pack the entry into 64 bits.
+    ///
+    /// - Bits 0-15: The best move (0 if there is none)
+    /// - Bits 16-23: The depth
+    /// - Bits 24-25: The bound
+    /// - Bits 26-27: The kind of evaluation
+    /// - Bits 32-63: The value of the evaluation
+    ///
+    /// Mate evaluations are stored as the number of plies from the stored position,
+    /// so they remain correct when the position is reached again at a different ply.
+    fn pack(&self) -> u64 {
+        let mv = self.best_move.map_or(0, |mv| mv.get_raw()) as u64;
+        let depth = self.depth.min(u8::MAX as usize) as u64;
+        let bound = match self.bound {
+            Bound::Exact => 1,
+            Bound::Lower => 2,
+            Bound::Upper => 3,
+        };
+        let (kind, value) = match self.evaluation {
+            Evaluation::Centipawns(cp) => (0, cp as u32),
+            Evaluation::PlayerCheckmate(plies) => (1, plies as u32),
+            Evaluation::OpponentCheckmate(plies) => (2, plies as u32),
+            Evaluation::Draw => (3, 0),
+        };
+
+        mv | (depth << 16) | (bound << 24) | (kind << 26) | ((value as u64) << 32)
+    }
+
+    /// Unpack an entry from 64 bits.
+    ///
+    /// Returns `None` if the slot has never been written to.
+    fn unpack(data: u64) -> Option<Self> {
+        let mv = (data & 0xFFFF) as u16;
+        let depth = ((data >> 16) & 0xFF) as usize;
+        let bound = match (data >> 24) & 0b11 {
+            1 => Bound::Exact,
+            2 => Bound::Lower,
+            3 => Bound::Upper,
+            _ => return None,
+        };
+        let value = (data >> 32) as u32;
+        let evaluation = match (data >> 26) & 0b11 {
+            0 => Evaluation::Centipawns(value as i32),
+            1 => Evaluation::PlayerCheckmate(value as usize),
+            2 => Evaluation::OpponentCheckmate(value as usize),
+            _ => Evaluation::Draw,
+        };
+
+        Some(Self {
+            evaluation,
+            bound,
+            depth,
+            best_move: if mv == 0 {
+                None
+            } else {
+                Some(BitMove::new(mv))
+            },
+        })
+    }
+}
+
+/// A single slot of the transposition table.
+#[derive(Default)]
+struct Slot {
+    /// The zobrist key XORed with the data.
+    key: AtomicU64,
+    /// The packed table entry.
+    data: AtomicU64,
+}
+
+/// A transposition table with a fixed memory size.
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    /// Create a new transposition table with the given size in MB.
+    pub fn new(size_mb: usize) -> Self {
+        let slot_count = (size_mb * 1024 * 1024 / size_of::<Slot>()).max(1);
+        let mut slots = Vec::with_capacity(slot_count);
+        slots.resize_with(slot_count, Slot::default);
+
+        Self { slots }
+    }
+
+    /// The slot that the given key is stored in.
+    fn slot(&self, key: u64) -> &Slot {
+        &self.slots[(key % self.slots.len() as u64) as usize]
+    }
+
+    /// Remove all entries from the table.
+    pub fn clear(&self) {
+        for slot in &self.slots {
+            slot.key.store(0, Ordering::Relaxed);
+            slot.data.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Get the entry stored for the given zobrist key.
+    pub fn probe(&self, key: u64) -> Option<TableEntry> {
+        let slot = self.slot(key);
+        let data = slot.data.load(Ordering::Relaxed);
+
+        if slot.key.load(Ordering::Relaxed) ^ data != key {
+            return None;
+        }
+
+        TableEntry::unpack(data)
+    }
+
+    /// Store the entry for the given zobrist key.
+    ///
+    /// Entries of the same position are only replaced by entries that
+    /// have been searched at least as deep or that are exact.
+    pub fn store(&self, key: u64, entry: TableEntry) {
+        let slot = self.slot(key);
+
+        if let Some(old_entry) = self.probe(key) {
+            if old_entry.depth > entry.depth && entry.bound != Bound::Exact {
+                return;
+            }
+        }
+
+        let data = entry.pack();
+        slot.key.store(key ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+impl Debug for TranspositionTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TranspositionTable")
+            .field("slots", &self.slots.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pleco::Board;
+
+    use crate::stonefish::evaluation::Evaluation;
+
+    use super::{Bound, TableEntry, TranspositionTable};
+
+    #[test]
+    fn should_store_and_probe_entries() {
+        let board = Board::start_pos();
+        let best_move = board.generate_moves()[0];
+
+        let entries = [
+            (Evaluation::Centipawns(-250), Bound::Exact, Some(best_move)),
+            (Evaluation::Centipawns(31), Bound::Lower, None),
+            (
+                Evaluation::PlayerCheckmate(5),
+                Bound::Upper,
+                Some(best_move),
+            ),
+            (Evaluation::OpponentCheckmate(2), Bound::Exact, None),
+            (Evaluation::Draw, Bound::Lower, Some(best_move)),
+        ];
+
+        for (key, (evaluation, bound, best_move)) in entries.into_iter().enumerate() {
+            let table = TranspositionTable::new(1);
+            let entry = TableEntry {
+                evaluation,
+                bound,
+                depth: key + 3,
+                best_move,
+            };
+
+            table.store(board.zobrist() ^ key as u64, entry);
+            let actual = table.probe(board.zobrist() ^ key as u64);
+
+            assert_eq!(actual, Some(entry));
+            // Draw and 0 cp are considered equal, so check the variant as well
+            assert_eq!(
+                format!("{:?}", actual.unwrap().evaluation),
+                format!("{evaluation:?}")
+            );
+        }
+    }
+
+    #[test]
+    fn should_not_return_entries_of_other_positions() {
+        let table = TranspositionTable::new(1);
+        let key = Board::start_pos().zobrist();
+
+        assert_eq!(table.probe(key), None);
+
+        table.store(
+            key,
+            TableEntry {
+                evaluation: Evaluation::Centipawns(10),
+                bound: Bound::Exact,
+                depth: 1,
+                best_move: None,
+            },
+        );
+
+        assert_eq!(table.probe(key ^ 1), None);
+    }
+
+    #[test]
+    fn should_keep_deeper_entries() {
+        let table = TranspositionTable::new(1);
+        let key = Board::start_pos().zobrist();
+
+        let deep_entry = TableEntry {
+            evaluation: Evaluation::Centipawns(10),
+            bound: Bound::Lower,
+            depth: 8,
+            best_move: None,
+        };
+        let shallow_entry = TableEntry {
+            evaluation: Evaluation::Centipawns(50),
+            bound: Bound::Upper,
+            depth: 2,
+            best_move: None,
+        };
+
+        table.store(key, deep_entry);
+        table.store(key, shallow_entry);
+
+        assert_eq!(table.probe(key), Some(deep_entry));
+    }
+
+    #[test]
+    fn should_clear_entries() {
+        let table = TranspositionTable::new(1);
+        let key = Board::start_pos().zobrist();
+
+        table.store(
+            key,
+            TableEntry {
+                evaluation: Evaluation::Centipawns(10),
+                bound: Bound::Exact,
+                depth: 1,
+                best_move: None,
+            },
+        );
+        table.clear();
+
+        assert_eq!(table.probe(key), None);
+    }
+}
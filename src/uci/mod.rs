@@ -10,6 +10,10 @@ use self::uci_command::{UciCommand, UciGoConfig, UciPosition};
 use self::uci_option::UciOption;
 
 pub type AbortFlag = Arc<AtomicBool>;
+/// A flag that is set while the engine is pondering on the opponent's time.
+///
+/// It is cleared by the `ponderhit` command, after which the search continues normally.
+pub type PonderFlag = Arc<AtomicBool>;
 
 pub trait UciEngine {
     /// Create a new engine instance.
@@ -45,7 +49,9 @@ pub trait UciEngine {
     fn change_position(&mut self, _pos: UciPosition, _moves: Vec<String>) {}
 
     /// Start the search.
-    fn go(&mut self, _go_config: UciGoConfig, _stop_flag: AbortFlag) {}
+    ///
+    /// The `ponder_flag` is set while the search is in ponder mode.
+    fn go(&mut self, _go_config: UciGoConfig, _stop_flag: AbortFlag, _ponder_flag: PonderFlag) {}
 
     /// Stop calculating as soon as possible.
     fn stop(&mut self) {}
@@ -54,6 +60,9 @@ pub trait UciEngine {
     ///
     /// This will be sent if the engine was told to ponder on the same move the user has played.
     /// The engine should continue searching but switch from pondering to normal search.
+    ///
+    /// The ponder flag has already been cleared at this point, so a running search is
+    /// notified without having to wait for it to finish.
     fn ponder_hit(&mut self) {}
 }
 
@@ -63,6 +72,7 @@ impl UciRunner {
     fn engine_loop<Engine: UciEngine>(
         thread: sync::mpsc::Receiver<UciCommand>,
         stop_flag: AbortFlag,
+        ponder_flag: PonderFlag,
     ) {
         // Create a new instance of the engine
         let mut engine = Engine::new();
@@ -94,7 +104,9 @@ impl UciRunner {
                 // Move to a new position
                 UciCommand::Position(pos, moves) => engine.change_position(pos, moves),
                 // Start the search
-                UciCommand::Go(go_config) => engine.go(go_config, stop_flag.clone()),
+                UciCommand::Go(go_config) => {
+                    engine.go(go_config, stop_flag.clone(), ponder_flag.clone())
+                }
                 // Stop the search as soon as possible
                 UciCommand::Stop => engine.stop(),
                 // The user has played the expected move
@@ -117,11 +129,16 @@ impl UciRunner {
         // A flag to indicate that the search should be stopped as soon as possible
         let stop_flag: AbortFlag = Arc::new(AtomicBool::new(false));
         let thread_stop_flag = stop_flag.clone();
+        // A flag to indicate that the engine is searching on the opponent's time
+        let ponder_flag: PonderFlag = Arc::new(AtomicBool::new(false));
+        let thread_ponder_flag = ponder_flag.clone();
 
         thread::Builder::new()
             .name("Engine thread".into())
             .stack_size(8 * 1024 * 1024)
-            .spawn(move || Self::engine_loop::<Engine>(main_rx, thread_stop_flag))
+            .spawn(move || {
+                Self::engine_loop::<Engine>(main_rx, thread_stop_flag, thread_ponder_flag)
+            })
             .unwrap();
 
         // Wait for new commands. Every command is a new line
@@ -138,12 +155,20 @@ impl UciRunner {
                     // Send the stop command
                     main_tx.send(cmd).unwrap();
                 }
-                UciCommand::Go(_) => {
+                UciCommand::Go(ref go_config) => {
                     // Unset the stop flag so that calculations can be made
                     stop_flag.store(false, Ordering::SeqCst);
+                    // Remember if we are searching on the opponent's time
+                    ponder_flag.store(go_config.ponder, Ordering::SeqCst);
                     // Send the go command
                     main_tx.send(cmd).unwrap();
                 }
+                UciCommand::Ponderhit => {
+                    // Switch to a normal search, the running search has to be notified immediately
+                    ponder_flag.store(false, Ordering::SeqCst);
+                    // Send the ponderhit command
+                    main_tx.send(cmd).unwrap();
+                }
                 // Propagate commands to the engine
                 cmd => {
                     main_tx.send(cmd).unwrap();
@@ -41,6 +41,7 @@ impl UciOption {
         }
     }
 
+    /// Create a new UCI option with the given default value.
     pub fn new_with_default(name: &str, option_type: UciOptionType, default: &str) -> UciOption {
         UciOption {
             name: name.to_string(),
@@ -52,6 +53,30 @@ impl UciOption {
         }
     }
 
+    /// Create a new UCI option of type `spin` with the given default and range.
+    pub fn new_spin(name: &str, default: usize, min: usize, max: usize) -> UciOption {
+        UciOption {
+            name: name.to_string(),
+            option_type: UciOptionType::Spin,
+            default: Some(default.to_string()),
+            min: Some(min.to_string()),
+            max: Some(max.to_string()),
+            vars: None,
+        }
+    }
+
+    /// Create a new UCI option of type `combo` with the given default and values.
+    pub fn new_combo(name: &str, default: &str, vars: &[&str]) -> UciOption {
+        UciOption {
+            name: name.to_string(),
+            option_type: UciOptionType::Combo,
+            default: Some(default.to_string()),
+            min: None,
+            max: None,
+            vars: Some(vars.iter().map(|var| var.to_string()).collect()),
+        }
+    }
+
     /// Send the option from the engine to the GUI
     pub fn send_option(&self) {
         // Mandatory options